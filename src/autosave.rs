@@ -0,0 +1,164 @@
+use std::time::{Duration, Instant};
+
+use crate::Pane;
+use crate::layout::PaneId;
+
+/// Idle delay used when cycling into the idle mode.
+pub const DEFAULT_IDLE_SECS: u64 = 30;
+
+/// When dirty documents are saved without being asked.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum AutosaveMode {
+    #[default]
+    Off,
+    /// Once a document has had no edits for this many seconds.
+    Idle(u64),
+    /// When the nust window loses focus.
+    FocusLoss,
+    /// When focus moves to another pane or tab, for the one left behind.
+    PaneSwitch,
+}
+
+impl AutosaveMode {
+    pub fn next(self) -> Self {
+        match self {
+            AutosaveMode::Off => AutosaveMode::Idle(DEFAULT_IDLE_SECS),
+            AutosaveMode::Idle(_) => AutosaveMode::FocusLoss,
+            AutosaveMode::FocusLoss => AutosaveMode::PaneSwitch,
+            AutosaveMode::PaneSwitch => AutosaveMode::Off,
+        }
+    }
+
+    pub fn label(self) -> String {
+        match self {
+            AutosaveMode::Off => "off".into(),
+            AutosaveMode::Idle(secs) => format!("after {secs}s idle"),
+            AutosaveMode::FocusLoss => "on focus loss".into(),
+            AutosaveMode::PaneSwitch => "on pane switch".into(),
+        }
+    }
+}
+
+/// Tracks the events the autosave modes react to.
+#[derive(Default)]
+pub struct Autosave {
+    pub mode: AutosaveMode,
+    window_focused: bool,
+    /// Pane and document that had focus last frame.
+    last_focus: Option<(PaneId, u64)>,
+}
+
+/// Whether `doc` is one autosave should write at all. Blank untitled
+/// buffers and files with an unresolved outside change are left alone.
+fn eligible(doc: &Pane) -> bool {
+    !doc.skip_autosave
+        && doc.is_dirty()
+        && doc.disk_change.is_none()
+        && (doc.path.is_some() || !doc.text.is_empty())
+}
+
+impl Autosave {
+    /// Work out which documents are due this frame. `focus` is the focused
+    /// pane and its active document.
+    pub fn due<'a>(
+        &mut self,
+        docs: impl Iterator<Item = &'a Pane>,
+        window_focused: bool,
+        focus: (PaneId, u64),
+        now: Instant,
+    ) -> Vec<u64> {
+        let lost_focus = self.window_focused && !window_focused;
+        self.window_focused = window_focused;
+        let left = self.last_focus.filter(|last| *last != focus).map(|(_, doc_id)| doc_id);
+        self.last_focus = Some(focus);
+
+        docs.filter(|doc| eligible(doc))
+            .filter(|doc| match self.mode {
+                AutosaveMode::Off => false,
+                AutosaveMode::Idle(secs) => doc
+                    .last_edit
+                    .is_some_and(|at| now.duration_since(at) >= Duration::from_secs(secs)),
+                AutosaveMode::FocusLoss => lost_focus,
+                AutosaveMode::PaneSwitch => left == Some(doc.doc_id),
+            })
+            .map(|doc| doc.doc_id)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An untitled document with text, last edited `idle` ago.
+    fn doc(doc_id: u64, idle: Duration, now: Instant) -> Pane {
+        let mut doc = Pane::untitled(doc_id);
+        doc.text = "draft".into();
+        doc.last_edit = Some(now - idle);
+        doc
+    }
+
+    fn due(
+        autosave: &mut Autosave,
+        docs: &[Pane],
+        focused: bool,
+        focus: (PaneId, u64),
+        now: Instant,
+    ) -> Vec<u64> {
+        autosave.due(docs.iter(), focused, focus, now)
+    }
+
+    #[test]
+    fn off_saves_nothing() {
+        let now = Instant::now();
+        let docs = [doc(1, Duration::from_secs(3600), now)];
+        let mut autosave = Autosave::default();
+        assert!(due(&mut autosave, &docs, true, (PaneId(1), 1), now).is_empty());
+        assert!(due(&mut autosave, &docs, false, (PaneId(2), 1), now).is_empty());
+    }
+
+    #[test]
+    fn idle_waits_for_the_delay_and_skips_ineligible_documents() {
+        let now = Instant::now();
+        let mut skipped = doc(3, Duration::from_secs(60), now);
+        skipped.skip_autosave = true;
+        let mut blank = doc(4, Duration::from_secs(60), now);
+        blank.text = Default::default();
+        let (ready, recent) = (doc(1, Duration::from_secs(30), now), doc(2, Duration::from_secs(29), now));
+        let docs = [ready, recent, skipped, blank];
+        let mut autosave = Autosave {
+            mode: AutosaveMode::Idle(30),
+            ..Default::default()
+        };
+        assert_eq!(due(&mut autosave, &docs, true, (PaneId(1), 1), now), [1]);
+    }
+
+    #[test]
+    fn focus_loss_saves_everything_once() {
+        let now = Instant::now();
+        let docs = [doc(1, Duration::ZERO, now), doc(2, Duration::ZERO, now)];
+        let mut autosave = Autosave {
+            mode: AutosaveMode::FocusLoss,
+            ..Default::default()
+        };
+        let focus = (PaneId(1), 1);
+        assert!(due(&mut autosave, &docs, true, focus, now).is_empty());
+        assert_eq!(due(&mut autosave, &docs, false, focus, now), [1, 2]);
+        assert!(due(&mut autosave, &docs, false, focus, now).is_empty());
+    }
+
+    #[test]
+    fn pane_switch_saves_the_document_left_behind() {
+        let now = Instant::now();
+        let docs = [doc(1, Duration::ZERO, now), doc(2, Duration::ZERO, now)];
+        let mut autosave = Autosave {
+            mode: AutosaveMode::PaneSwitch,
+            ..Default::default()
+        };
+        assert!(due(&mut autosave, &docs, true, (PaneId(1), 1), now).is_empty());
+        assert!(due(&mut autosave, &docs, true, (PaneId(1), 1), now).is_empty());
+        // Another tab in the same pane counts as a switch too
+        assert_eq!(due(&mut autosave, &docs, true, (PaneId(1), 2), now), [1]);
+        assert_eq!(due(&mut autosave, &docs, true, (PaneId(2), 7), now), [2]);
+    }
+}
@@ -0,0 +1,126 @@
+use ropey::Rope;
+use std::fmt;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Source of `Buffer::version`s, shared by all buffers so that a buffer
+/// replaced wholesale never repeats the version of the one it replaced.
+static NEXT_VERSION: AtomicU64 = AtomicU64::new(1);
+
+fn next_version() -> u64 {
+    NEXT_VERSION.fetch_add(1, Ordering::Relaxed)
+}
+
+/// The text of an open document, as a rope so that edits and line lookups
+/// stay cheap however large the file is. Offsets are in chars, and only
+/// `\n` ends a line (see `eol`).
+#[derive(Clone)]
+pub struct Buffer {
+    rope: Rope,
+    /// Changes with every edit, so work derived from the text (searches,
+    /// layout, snapshots) can tell whether it is still current.
+    version: u64,
+}
+
+impl Default for Buffer {
+    fn default() -> Self {
+        Self::from("")
+    }
+}
+
+impl From<&str> for Buffer {
+    fn from(text: &str) -> Self {
+        Self {
+            rope: Rope::from_str(text),
+            version: next_version(),
+        }
+    }
+}
+
+impl From<Rope> for Buffer {
+    fn from(rope: Rope) -> Self {
+        Self {
+            rope,
+            version: next_version(),
+        }
+    }
+}
+
+impl From<String> for Buffer {
+    fn from(text: String) -> Self {
+        Self::from(text.as_str())
+    }
+}
+
+/// The whole text; `to_string()` copies it out, which is O(length).
+impl fmt::Display for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chunk in self.rope.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl Buffer {
+    pub fn rope(&self) -> &Rope {
+        &self.rope
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn len_chars(&self) -> usize {
+        self.rope.len_chars()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rope.len_bytes() == 0
+    }
+
+    /// Number of lines; a trailing newline starts an empty last line.
+    pub fn line_count(&self) -> usize {
+        self.rope.len_lines()
+    }
+
+    /// The chars `range` covers, copied out.
+    pub fn slice(&self, range: Range<usize>) -> String {
+        self.rope.slice(range).to_string()
+    }
+
+    /// Char offset of zero-based `line` and `column`, clamped to the end of
+    /// the line and of the text.
+    pub fn char_offset(&self, line: usize, column: usize) -> usize {
+        let last = self.rope.len_lines() - 1;
+        if line > last {
+            return self.rope.len_chars();
+        }
+        let start = self.rope.line_to_char(line);
+        let end = if line < last {
+            self.rope.line_to_char(line + 1) - 1
+        } else {
+            self.rope.len_chars()
+        };
+        (start + column).min(end)
+    }
+
+    pub fn char_to_byte(&self, at: usize) -> usize {
+        self.rope.char_to_byte(at)
+    }
+
+    pub fn byte_to_char(&self, at: usize) -> usize {
+        self.rope.byte_to_char(at)
+    }
+
+    /// Replace the chars in `range` with `with`.
+    pub fn replace(&mut self, range: Range<usize>, with: &str) {
+        if !range.is_empty() {
+            self.rope.remove(range.clone());
+        }
+        if !with.is_empty() {
+            self.rope.insert(range.start, with);
+        }
+        self.version = next_version();
+    }
+}
@@ -0,0 +1,316 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::autosave::AutosaveMode;
+use crate::fileio::{self, BackupMode};
+use crate::quicksave::RetentionPolicy;
+
+/// User settings, read from `settings.toml` in the config directory. Every
+/// section and key is optional; anything left out keeps its default. Floats
+/// are `f64` so defaults such as 0.42 print exactly in the generated file.
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub editor: EditorConfig,
+    pub layout: LayoutConfig,
+    pub saving: SavingConfig,
+    pub dialogs: DialogConfig,
+    pub search: SearchConfig,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EditorConfig {
+    pub word_wrap: bool,
+    pub font_size: f64,
+}
+
+impl Default for EditorConfig {
+    fn default() -> Self {
+        Self {
+            word_wrap: false,
+            font_size: 14.0,
+        }
+    }
+}
+
+/// Applied when the window opens; changing them needs a restart.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LayoutConfig {
+    pub window_width: f64,
+    pub window_height: f64,
+    /// Share of the width given to the left pane of a fresh two-pane layout.
+    pub split_share: f64,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        Self {
+            window_width: 1000.0,
+            window_height: 700.0,
+            split_share: 0.42,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutosaveKind {
+    #[default]
+    Off,
+    Idle,
+    FocusLoss,
+    PaneSwitch,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SavingConfig {
+    /// Initial contents of the "Save to:" box.
+    pub manual_path: String,
+    pub backup: BackupMode,
+    pub autosave: AutosaveKind,
+    pub autosave_idle_secs: u64,
+    pub quick_save_keep_last: usize,
+    pub quick_save_max_age_days: u64,
+    pub quick_save_max_total_mb: u64,
+}
+
+impl Default for SavingConfig {
+    fn default() -> Self {
+        let retention = RetentionPolicy::default();
+        Self {
+            manual_path: "target/quick_saves/output.txt".into(),
+            backup: BackupMode::Off,
+            autosave: AutosaveKind::Off,
+            autosave_idle_secs: crate::autosave::DEFAULT_IDLE_SECS,
+            quick_save_keep_last: retention.keep_last,
+            quick_save_max_age_days: retention.max_age_days,
+            quick_save_max_total_mb: retention.max_total_mb,
+        }
+    }
+}
+
+impl SavingConfig {
+    pub fn autosave_mode(&self) -> AutosaveMode {
+        match self.autosave {
+            AutosaveKind::Off => AutosaveMode::Off,
+            AutosaveKind::Idle => AutosaveMode::Idle(self.autosave_idle_secs),
+            AutosaveKind::FocusLoss => AutosaveMode::FocusLoss,
+            AutosaveKind::PaneSwitch => AutosaveMode::PaneSwitch,
+        }
+    }
+
+    pub fn retention(&self) -> RetentionPolicy {
+        RetentionPolicy {
+            keep_last: self.quick_save_keep_last,
+            max_age_days: self.quick_save_max_age_days,
+            max_total_mb: self.quick_save_max_total_mb,
+        }
+    }
+}
+
+/// A named group of extensions offered by the Open/Save dialogs.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileFilter {
+    pub name: String,
+    pub extensions: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DialogConfig {
+    /// Offered before the built-in "All Files" filter.
+    pub filters: Vec<FileFilter>,
+}
+
+impl Default for DialogConfig {
+    fn default() -> Self {
+        Self {
+            filters: vec![FileFilter {
+                name: "Text/Markdown".into(),
+                extensions: vec!["txt".into(), "md".into(), "log".into()],
+            }],
+        }
+    }
+}
+
+impl DialogConfig {
+    /// A native file dialog with the configured filters.
+    pub fn file_dialog(&self, title: impl Into<String>) -> rfd::FileDialog {
+        let mut dialog = rfd::FileDialog::new().set_title(title);
+        for filter in &self.filters {
+            dialog = dialog.add_filter(&filter.name, &filter.extensions);
+        }
+        dialog.add_filter("All Files", &["*"])
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SearchConfig {
+    /// Globs Find in Files skips, on top of hidden and .gitignored files.
+    pub exclude: Vec<String>,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            exclude: vec!["target/".into(), "node_modules/".into()],
+        }
+    }
+}
+
+impl Config {
+    /// Reject values that parse but make no sense, naming the offending key.
+    fn validate(&self) -> Result<(), String> {
+        let editor = &self.editor;
+        if !(6.0..=72.0).contains(&editor.font_size) {
+            return Err("editor.font_size must be between 6 and 72".into());
+        }
+        let layout = &self.layout;
+        if !(0.1..=0.9).contains(&layout.split_share) {
+            return Err("layout.split_share must be between 0.1 and 0.9".into());
+        }
+        if layout.window_width < 200.0 || layout.window_height < 150.0 {
+            return Err("layout.window_width/window_height are too small (min 200x150)".into());
+        }
+        if self.saving.autosave_idle_secs == 0 {
+            return Err("saving.autosave_idle_secs must be at least 1".into());
+        }
+        if let Some(filter) = self
+            .dialogs
+            .filters
+            .iter()
+            .find(|filter| filter.extensions.is_empty())
+        {
+            return Err(format!("dialogs.filters \"{}\" lists no extensions", filter.name));
+        }
+        Ok(())
+    }
+}
+
+/// `~/.config/nust/settings.toml` on Linux, `%APPDATA%\nust\settings.toml` on Windows.
+pub fn settings_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("nust").join("settings.toml"))
+}
+
+/// Parse and validate the settings file. A missing file is not an error;
+/// anything else comes back as a message for the status bar.
+pub fn read(path: &Path) -> Result<Config, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => return Err(format!("{}: {e}", path.display())),
+    };
+    let config: Config = toml::from_str(&text).map_err(|e| match e.span() {
+        Some(span) => format!(
+            "settings.toml line {}: {}",
+            text[..span.start].matches('\n').count() + 1,
+            e.message()
+        ),
+        None => format!("settings.toml: {}", e.message()),
+    })?;
+    config.validate().map_err(|e| format!("settings.toml: {e}"))?;
+    Ok(config)
+}
+
+/// Write a settings file holding every default, so there is something to edit.
+pub fn write_defaults(path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    }
+    let body = toml::to_string_pretty(&Config::default())?;
+    let text = format!(
+        "# nust settings. Changes apply as soon as the file is saved;\n\
+         # [layout] only takes effect on the next start.\n\n{body}"
+    );
+    fileio::write_atomic(path, text.as_bytes(), BackupMode::Off)?;
+    Ok(())
+}
+
+/// Notices when the settings file changes so it can be re-read.
+pub struct ConfigWatcher {
+    pub path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: PathBuf) -> Self {
+        let modified = modified(&path);
+        Self { path, modified }
+    }
+
+    /// Whether the file was written, created or removed since the last call.
+    pub fn changed(&mut self) -> bool {
+        let now = modified(&self.path);
+        if now == self.modified {
+            return false;
+        }
+        self.modified = now;
+        true
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    fn read_str(text: &str) -> Result<Config, String> {
+        let dir = TempDir::new("config-read");
+        read(&dir.file("settings.toml", text))
+    }
+
+    #[test]
+    fn defaults_round_trip_through_the_file() {
+        let dir = TempDir::new("config-defaults");
+        let path = dir.path().join("nust").join("settings.toml");
+        assert_eq!(read(&path), Ok(Config::default()));
+        write_defaults(&path).unwrap();
+        assert_eq!(read(&path), Ok(Config::default()));
+    }
+
+    #[test]
+    fn out_of_range_values_name_their_key() {
+        let cases = [
+            ("[editor]\nfont_size = 100.0\n", "editor.font_size"),
+            ("[layout]\nsplit_share = 0.95\n", "layout.split_share"),
+            ("[layout]\nwindow_width = 100.0\n", "layout.window_width"),
+            ("[saving]\nautosave_idle_secs = 0\n", "saving.autosave_idle_secs"),
+            (
+                "[[dialogs.filters]]\nname = \"Logs\"\nextensions = []\n",
+                "dialogs.filters \"Logs\"",
+            ),
+        ];
+        for (text, key) in cases {
+            let error = read_str(text).unwrap_err();
+            assert!(error.starts_with(&format!("settings.toml: {key}")), "{error}");
+        }
+    }
+
+    #[test]
+    fn parse_errors_give_the_line() {
+        let error = read_str("[editor]\nword_wrap = true\nfont_sise = 12.0\n").unwrap_err();
+        assert!(error.starts_with("settings.toml line 3: "), "{error}");
+        let error = read_str("[saving]\nautosave = \"sometimes\"\n").unwrap_err();
+        assert!(error.starts_with("settings.toml line 2: "), "{error}");
+    }
+
+    #[test]
+    fn missing_keys_keep_their_defaults() {
+        let config = read_str("[editor]\nword_wrap = true\n").unwrap();
+        assert!(config.editor.word_wrap);
+        assert_eq!(config.editor.font_size, EditorConfig::default().font_size);
+        assert_eq!(config.saving, SavingConfig::default());
+    }
+}
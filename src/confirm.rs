@@ -0,0 +1,102 @@
+use eframe::egui;
+
+use crate::encoding::TextEncoding;
+use crate::layout::PaneId;
+
+/// A destructive operation held back until the user decides what to do with
+/// the unsaved documents it would throw away.
+#[derive(Clone, Copy)]
+pub enum PendingClose {
+    Tab { pane: PaneId, doc_id: u64 },
+    OtherTabs { pane: PaneId, keep: u64 },
+    Pane(PaneId),
+    /// Re-read a document from disk in another encoding.
+    Reopen {
+        pane: PaneId,
+        doc_id: u64,
+        encoding: TextEncoding,
+    },
+    Exit,
+}
+
+/// One unsaved document listed in the confirmation modal.
+pub struct DirtyDoc {
+    pub doc_id: u64,
+    pub title: String,
+    pub location: String,
+}
+
+pub struct ConfirmDiscard {
+    pub op: PendingClose,
+    pub docs: Vec<DirtyDoc>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ConfirmChoice {
+    Save,
+    Discard,
+    Cancel,
+}
+
+/// Draw the Save / Discard / Cancel modal over a dimmed, click-blocking backdrop.
+pub fn confirm_discard_ui(ctx: &egui::Context, confirm: &ConfirmDiscard) -> Option<ConfirmChoice> {
+    let screen = ctx.screen_rect();
+    egui::Area::new(egui::Id::new("confirm_discard_backdrop"))
+        .order(egui::Order::Foreground)
+        .fixed_pos(screen.min)
+        .show(ctx, |ui| {
+            ui.painter()
+                .rect_filled(screen, 0.0, egui::Color32::from_black_alpha(120));
+            ui.allocate_rect(screen, egui::Sense::click_and_drag());
+        });
+
+    let modal_id = egui::Id::new("confirm_discard_modal");
+    let mut choice = None;
+    egui::Area::new(modal_id)
+        .order(egui::Order::Foreground)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .show(ctx, |ui| {
+            egui::Frame::window(ui.style()).show(ui, |ui| {
+                ui.set_max_width(420.0);
+                ui.heading("Unsaved Changes");
+                let what = match confirm.op {
+                    PendingClose::Tab { .. } => "Closing this tab",
+                    PendingClose::OtherTabs { .. } => "Closing the other tabs",
+                    PendingClose::Pane(_) => "Closing this pane",
+                    PendingClose::Reopen { .. } => "Reopening with another encoding",
+                    PendingClose::Exit => "Quitting",
+                };
+                ui.label(format!(
+                    "{what} will discard changes in {} document(s):",
+                    confirm.docs.len()
+                ));
+                ui.add_space(4.0);
+                egui::ScrollArea::vertical()
+                    .max_height(200.0)
+                    .show(ui, |ui| {
+                        for doc in &confirm.docs {
+                            ui.label(format!("• {}", doc.title))
+                                .on_hover_text(&doc.location);
+                        }
+                    });
+                ui.add_space(8.0);
+                ui.horizontal(|ui| {
+                    if ui.button("Save").clicked() {
+                        choice = Some(ConfirmChoice::Save);
+                    }
+                    if ui.button("Discard").clicked() {
+                        choice = Some(ConfirmChoice::Discard);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        choice = Some(ConfirmChoice::Cancel);
+                    }
+                });
+            });
+        });
+    ctx.move_to_top(egui::LayerId::new(egui::Order::Foreground, modal_id));
+
+    if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+        choice = Some(ConfirmChoice::Cancel);
+    }
+    choice
+}
@@ -0,0 +1,147 @@
+use eframe::egui;
+use similar::{ChangeTag, TextDiff};
+
+/// Lines of unchanged context kept around each change.
+const CONTEXT_LINES: usize = 3;
+const REMOVED: egui::Color32 = egui::Color32::from_rgb(0xd0, 0x50, 0x50);
+const ADDED: egui::Color32 = egui::Color32::from_rgb(0x50, 0xa0, 0x50);
+
+/// One row of a drawn diff.
+enum Row {
+    /// Unchanged lines left out between two groups of changes.
+    Gap,
+    Line(ChangeTag, String),
+}
+
+/// A unified line diff, worked out once and redrawn every frame. `show`
+/// only fetches and diffs the texts again when its key changes, so an open
+/// window does not copy and diff a large buffer on every repaint.
+pub struct DiffView<K> {
+    key: Option<K>,
+    rows: Vec<Row>,
+}
+
+impl<K> Default for DiffView<K> {
+    fn default() -> Self {
+        Self {
+            key: None,
+            rows: Vec::new(),
+        }
+    }
+}
+
+impl<K: PartialEq> DiffView<K> {
+    /// Draw the diff from old to new as returned by `texts`, colouring
+    /// removed and added lines. Unchanged stretches are collapsed to a few
+    /// lines of context.
+    pub fn show<O, N>(&mut self, ui: &mut egui::Ui, key: K, texts: impl FnOnce() -> (O, N))
+    where
+        O: AsRef<str>,
+        N: AsRef<str>,
+    {
+        if self.key.as_ref() != Some(&key) {
+            let (old, new) = texts();
+            self.rows = rows(old.as_ref(), new.as_ref());
+            self.key = Some(key);
+        }
+        if self.rows.is_empty() {
+            ui.label("No differences.");
+            return;
+        }
+        let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+        egui::ScrollArea::both()
+            .auto_shrink([false, false])
+            .show_rows(ui, row_height, self.rows.len(), |ui, range| {
+                for row in &self.rows[range] {
+                    let text = match row {
+                        Row::Gap => egui::RichText::new("  ⋯").monospace().weak(),
+                        Row::Line(tag, line) => {
+                            let (sign, color) = match tag {
+                                ChangeTag::Delete => ('-', Some(REMOVED)),
+                                ChangeTag::Insert => ('+', Some(ADDED)),
+                                ChangeTag::Equal => (' ', None),
+                            };
+                            let text = egui::RichText::new(format!("{sign} {line}")).monospace();
+                            match color {
+                                Some(color) => text.color(color),
+                                None => text,
+                            }
+                        }
+                    };
+                    ui.label(text);
+                }
+            });
+    }
+}
+
+fn rows(old: &str, new: &str) -> Vec<Row> {
+    let diff = TextDiff::from_lines(old, new);
+    let mut rows = Vec::new();
+    for (idx, group) in diff.grouped_ops(CONTEXT_LINES).iter().enumerate() {
+        if idx > 0 {
+            rows.push(Row::Gap);
+        }
+        for op in group {
+            for change in diff.iter_changes(op) {
+                let line = change.value().trim_end_matches('\n').to_owned();
+                rows.push(Row::Line(change.tag(), line));
+            }
+        }
+    }
+    rows
+}
+
+/// A single line before and after a change, as a two-line diff.
+pub fn line_change(ui: &mut egui::Ui, old: &str, new: &str) {
+    ui.label(egui::RichText::new(format!("- {old}")).monospace().color(REMOVED));
+    ui.label(egui::RichText::new(format!("+ {new}")).monospace().color(ADDED));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn describe(rows: &[Row]) -> Vec<String> {
+        rows.iter()
+            .map(|row| match row {
+                Row::Gap => "...".to_owned(),
+                Row::Line(ChangeTag::Delete, line) => format!("-{line}"),
+                Row::Line(ChangeTag::Insert, line) => format!("+{line}"),
+                Row::Line(ChangeTag::Equal, line) => format!(" {line}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn collapses_unchanged_stretches() {
+        let old: String = (1..=20).map(|n| format!("line {n}\n")).collect();
+        let new = old.replace("line 2\n", "two\n").replace("line 19\n", "nineteen\n");
+        let rows = describe(&rows(&old, &new));
+        assert_eq!(
+            rows,
+            [
+                " line 1", "-line 2", "+two", " line 3", " line 4", " line 5", "...", " line 16",
+                " line 17", " line 18", "-line 19", "+nineteen", " line 20",
+            ]
+        );
+    }
+
+    #[test]
+    fn rediffs_only_when_the_key_changes() {
+        let ctx = egui::Context::default();
+        let mut view = DiffView::default();
+        let mut fetched = 0;
+        for key in [1, 1, 2] {
+            let _ = ctx.run(Default::default(), |ctx| {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    view.show(ui, key, || {
+                        fetched += 1;
+                        ("a\n", "b\n")
+                    });
+                });
+            });
+        }
+        assert_eq!(fetched, 2);
+        assert_eq!(describe(&view.rows), ["-a", "+b"]);
+    }
+}
@@ -0,0 +1,1330 @@
+use eframe::egui::{
+    self, Align, Color32, Event, EventFilter, FontId, Galley, Key, Modifiers, Pos2, Rect, Response,
+    Sense, Vec2, pos2, text::CCursor, text::LayoutJob, vec2,
+};
+use ropey::Rope;
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::buffer::Buffer;
+use crate::eol;
+use crate::undo::Edit;
+
+/// Space between the editor's frame and its text.
+const MARGIN: Vec2 = vec2(4.0, 2.0);
+
+/// A caret and the selection it drags along, in char offsets. A bare caret
+/// has `anchor == head`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Selection {
+    pub anchor: usize,
+    pub head: usize,
+}
+
+impl Selection {
+    pub fn caret(at: usize) -> Self {
+        Self { anchor: at, head: at }
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.anchor.min(self.head)..self.anchor.max(self.head)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.anchor == self.head
+    }
+}
+
+/// Byte ranges to shade behind the text, such as find matches: in order,
+/// not overlapping, and one of them perhaps the current one.
+#[derive(Clone, Copy, Default)]
+pub struct Shading<'a> {
+    pub ranges: &'a [Range<usize>],
+    pub current: Option<usize>,
+}
+
+/// The chars of `line`, without its newline.
+fn line_span(rope: &Rope, line: usize) -> Range<usize> {
+    let start = rope.line_to_char(line);
+    let end = if line + 1 < rope.len_lines() {
+        rope.line_to_char(line + 1) - 1
+    } else {
+        rope.len_chars()
+    };
+    start..end
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Word,
+    Space,
+    Other,
+}
+
+fn class(c: char) -> CharClass {
+    if c.is_alphanumeric() || c == '_' {
+        CharClass::Word
+    } else if c.is_whitespace() {
+        CharClass::Space
+    } else {
+        CharClass::Other
+    }
+}
+
+/// Skip whitespace, then one run of word characters or punctuation.
+fn skip_word(chars: impl Iterator<Item = char>) -> usize {
+    let mut chars = chars.peekable();
+    let mut skipped = 0;
+    while chars.next_if(|&c| class(c) == CharClass::Space).is_some() {
+        skipped += 1;
+    }
+    if let Some(&first) = chars.peek() {
+        let kind = class(first);
+        while chars.next_if(|&c| class(c) == kind).is_some() {
+            skipped += 1;
+        }
+    }
+    skipped
+}
+
+fn word_left(rope: &Rope, at: usize) -> usize {
+    at - skip_word(rope.chars_at(at).reversed())
+}
+
+fn word_right(rope: &Rope, at: usize) -> usize {
+    at + skip_word(rope.chars_at(at))
+}
+
+/// The word (or run of punctuation) touching `at`, preferring a word.
+fn word_at(rope: &Rope, at: usize) -> Range<usize> {
+    let span = line_span(rope, rope.char_to_line(at));
+    let chars: Vec<char> = rope.slice(span.clone()).chars().collect();
+    let column = at - span.start;
+    let is_word = |idx: usize| chars.get(idx).is_some_and(|&c| class(c) == CharClass::Word);
+    let idx = if is_word(column) {
+        column
+    } else if column > 0 && is_word(column - 1) {
+        column - 1
+    } else if column < chars.len() {
+        column
+    } else if column > 0 {
+        column - 1
+    } else {
+        return at..at;
+    };
+    let kind = class(chars[idx]);
+    let mut start = idx;
+    while start > 0 && class(chars[start - 1]) == kind {
+        start -= 1;
+    }
+    let mut end = idx + 1;
+    while end < chars.len() && class(chars[end]) == kind {
+        end += 1;
+    }
+    span.start + start..span.start + end
+}
+
+/// Every place `needle` occurs in `rope`, as char offsets. The rope is
+/// searched a chunk at a time, each along with the end of the one before
+/// so that matches straddling two chunks are found too.
+fn occurrences(rope: &Rope, needle: &str) -> Vec<usize> {
+    let mut found = Vec::new();
+    if needle.is_empty() {
+        return found;
+    }
+    // The text still to search and its byte offset in the rope
+    let (mut window, mut window_at) = (String::new(), 0);
+    for chunk in rope.chunks() {
+        window.push_str(chunk);
+        let mut searched = 0;
+        for (byte, _) in window.match_indices(needle) {
+            found.push(rope.byte_to_char(window_at + byte));
+            searched = byte + needle.len();
+        }
+        // Too short to hold a match on its own, and clear of the last one
+        let mut keep = searched.max(window.len().saturating_sub(needle.len() - 1));
+        while !window.is_char_boundary(keep) {
+            keep += 1;
+        }
+        window.drain(..keep);
+        window_at += keep;
+    }
+    found
+}
+
+/// Replace the chars in `range` with `insert`, keeping `view` in step, and
+/// return what was removed.
+fn replace(text: &mut Buffer, view: &mut View, range: Range<usize>, insert: &str) -> String {
+    let synced = view.version == text.version();
+    if synced && let Some(wrap) = &mut view.wrap {
+        wrap.splice(text.rope(), range.clone(), insert);
+    }
+    let removed = text.slice(range.clone());
+    text.replace(range, insert);
+    if synced {
+        view.version = text.version();
+    }
+    removed
+}
+
+/// How the view should bring the primary caret into sight.
+#[derive(Clone, Copy)]
+enum Reveal {
+    Nearest,
+    Centre,
+}
+
+/// The carets of one document. They are kept in order and never overlap;
+/// the primary one is what the view follows and what single-caret commands
+/// start from.
+pub struct Cursors {
+    sels: Vec<Selection>,
+    primary: usize,
+    /// The x positions Up and Down aim for, one per caret, so a run of
+    /// moves keeps its column across short lines.
+    goals: Vec<f32>,
+    /// Where an Alt+drag block selection started, relative to the text.
+    block_from: Option<Vec2>,
+    reveal: Option<Reveal>,
+}
+
+impl Default for Cursors {
+    fn default() -> Self {
+        Self {
+            sels: vec![Selection::default()],
+            primary: 0,
+            goals: Vec::new(),
+            block_from: None,
+            reveal: None,
+        }
+    }
+}
+
+impl Cursors {
+    /// A single caret at `at`.
+    pub fn at(at: usize) -> Self {
+        Self {
+            sels: vec![Selection::caret(at)],
+            ..Default::default()
+        }
+    }
+
+    pub fn primary(&self) -> Selection {
+        self.sels[self.primary]
+    }
+
+    pub fn count(&self) -> usize {
+        self.sels.len()
+    }
+
+    /// Collapse to one caret at `at` and scroll it into view.
+    pub fn place(&mut self, at: usize) {
+        self.set(vec![Selection::caret(at)], 0);
+        self.reveal = Some(Reveal::Nearest);
+    }
+
+    /// Select `range` alone and centre it in the view, as for a find match.
+    pub fn select(&mut self, range: Range<usize>) {
+        self.set(
+            vec![Selection {
+                anchor: range.start,
+                head: range.end,
+            }],
+            0,
+        );
+        self.reveal = Some(Reveal::Centre);
+    }
+
+    /// Add a caret on the line above (or below) the outermost caret, at the
+    /// same column. False when there is no line there.
+    pub fn add_vertical(&mut self, text: &Buffer, down: bool) -> bool {
+        let rope = text.rope();
+        let from = if down { self.sels[self.sels.len() - 1] } else { self.sels[0] };
+        let line = rope.char_to_line(from.head);
+        let target = if down { line + 1 } else { line.wrapping_sub(1) };
+        if target >= rope.len_lines() {
+            return false;
+        }
+        let span = line_span(rope, target);
+        let at = (span.start + from.head - rope.line_to_char(line)).min(span.end);
+        self.add(Selection::caret(at));
+        true
+    }
+
+    /// Select the word at the primary caret or, when something is already
+    /// selected, add a selection at its next occurrence, wrapping around.
+    /// False when there is nothing (more) to select.
+    pub fn add_next_occurrence(&mut self, text: &Buffer) -> bool {
+        let rope = text.rope();
+        let primary = self.primary();
+        if primary.is_empty() {
+            let word = word_at(rope, primary.head);
+            if word.is_empty() {
+                return false;
+            }
+            self.sels[self.primary] = Selection {
+                anchor: word.start,
+                head: word.end,
+            };
+            self.normalize();
+            self.reveal = Some(Reveal::Nearest);
+            return true;
+        }
+        let range = primary.range();
+        let found = occurrences(rope, &text.slice(range.clone()));
+        let taken: Vec<Range<usize>> = self.sels.iter().map(Selection::range).collect();
+        // The first one after the primary selection, wrapping around
+        let after = found.partition_point(|&start| start < range.end);
+        let found = found[after..]
+            .iter()
+            .chain(&found[..after])
+            .map(|&start| start..start + range.len())
+            .find(|found| !taken.iter().any(|sel| sel.start < found.end && found.start < sel.end));
+        let Some(found) = found else {
+            return false;
+        };
+        self.add(Selection {
+            anchor: found.start,
+            head: found.end,
+        });
+        true
+    }
+
+    /// Select every occurrence of the primary selection, or of the word at
+    /// the primary caret. Returns how many there are.
+    pub fn select_all_occurrences(&mut self, text: &Buffer) -> usize {
+        let mut range = self.primary().range();
+        if range.is_empty() {
+            range = word_at(text.rope(), range.start);
+            if range.is_empty() {
+                return 0;
+            }
+        }
+        let sels: Vec<Selection> = occurrences(text.rope(), &text.slice(range.clone()))
+            .into_iter()
+            .map(|start| Selection {
+                anchor: start,
+                head: start + range.len(),
+            })
+            .collect();
+        let primary = sels.iter().position(|sel| sel.range() == range).unwrap_or(0);
+        let count = sels.len();
+        self.set(sels, primary);
+        self.reveal = Some(Reveal::Nearest);
+        count
+    }
+
+    fn set(&mut self, sels: Vec<Selection>, primary: usize) {
+        self.sels = sels;
+        self.primary = primary;
+        self.goals.clear();
+        self.normalize();
+    }
+
+    /// Replace the primary selection, leaving the other carets be.
+    fn set_primary(&mut self, sel: Selection) {
+        self.sels[self.primary] = sel;
+        self.goals.clear();
+        self.normalize();
+    }
+
+    /// Add `sel` as the new primary selection.
+    fn add(&mut self, sel: Selection) {
+        self.sels.push(sel);
+        self.primary = self.sels.len() - 1;
+        self.goals.clear();
+        self.normalize();
+        self.reveal = Some(Reveal::Nearest);
+    }
+
+    /// Sort the selections and merge any that overlap or share a caret.
+    fn normalize(&mut self) {
+        let primary = self.sels[self.primary];
+        let mut sels = std::mem::take(&mut self.sels);
+        sels.sort_by_key(|sel| (sel.range().start, sel.range().end));
+        let mut merged: Vec<Selection> = Vec::with_capacity(sels.len());
+        for sel in sels {
+            let range = sel.range();
+            match merged.last_mut() {
+                Some(last) if range.start < last.range().end || range.start == last.range().start => {
+                    let start = last.range().start;
+                    let end = last.range().end.max(range.end);
+                    *last = if last.head < last.anchor {
+                        Selection { anchor: end, head: start }
+                    } else {
+                        Selection { anchor: start, head: end }
+                    };
+                }
+                _ => merged.push(sel),
+            }
+            if sel == primary {
+                self.primary = merged.len() - 1;
+            }
+        }
+        if merged.len() != self.goals.len() {
+            self.goals.clear();
+        }
+        self.sels = merged;
+    }
+
+    /// Keep every caret inside a text of `len` chars, e.g. after an undo.
+    fn clamp(&mut self, len: usize) {
+        if self.sels.iter().any(|sel| sel.anchor.max(sel.head) > len) {
+            for sel in &mut self.sels {
+                sel.anchor = sel.anchor.min(len);
+                sel.head = sel.head.min(len);
+            }
+            self.normalize();
+        }
+    }
+
+    /// Move every head to `to(selection)`, dragging the anchor along unless
+    /// `extend`.
+    fn move_heads(&mut self, extend: bool, mut to: impl FnMut(Selection) -> usize) {
+        for sel in &mut self.sels {
+            sel.head = to(*sel);
+            if !extend {
+                sel.anchor = sel.head;
+            }
+        }
+        self.goals.clear();
+        self.normalize();
+        self.reveal = Some(Reveal::Nearest);
+    }
+
+    /// Up or Down for every caret, keeping to the column each started from.
+    fn move_vertical(&mut self, layout: &Layout, down: bool, extend: bool) {
+        if self.goals.len() != self.sels.len() {
+            self.goals = self.sels.iter().map(|sel| layout.caret(sel.head).min.x).collect();
+        }
+        for (sel, &x) in self.sels.iter_mut().zip(&self.goals) {
+            sel.head = layout.vertical(sel.head, x, down);
+            if !extend {
+                sel.anchor = sel.head;
+            }
+        }
+        self.normalize();
+        self.reveal = Some(Reveal::Nearest);
+    }
+
+    /// Select a rectangle: on every line between the two points, from the
+    /// column under one to the column under the other.
+    fn select_block(&mut self, layout: &Layout, from: Vec2, to: Vec2) {
+        let (first, last) = (layout.line_at(from.y), layout.line_at(to.y));
+        let lines: Vec<usize> = if first <= last {
+            (first..=last).collect()
+        } else {
+            (last..=first).rev().collect()
+        };
+        let sels = lines
+            .into_iter()
+            .map(|line| Selection {
+                anchor: layout.column_at(line, from.x),
+                head: layout.column_at(line, to.x),
+            })
+            .collect::<Vec<_>>();
+        let primary = sels.len() - 1;
+        self.set(sels, primary);
+        self.reveal = Some(Reveal::Nearest);
+    }
+
+    /// Replace what `change` returns for each selection, in order, and leave
+    /// a caret after each replacement. Ranges that overlap (say, two
+    /// word-deletes) are merged first.
+    fn replace_each(
+        &mut self,
+        text: &mut Buffer,
+        view: &mut View,
+        edits: &mut Vec<Edit>,
+        mut change: impl FnMut(usize, Selection) -> (Range<usize>, String),
+    ) {
+        let mut changes: Vec<(Range<usize>, String)> = Vec::with_capacity(self.sels.len());
+        let mut primary = 0;
+        for (idx, sel) in self.sels.iter().enumerate() {
+            let (range, insert) = change(idx, *sel);
+            match changes.last_mut() {
+                Some((last, last_insert)) if range.start < last.end => {
+                    last.end = last.end.max(range.end);
+                    last_insert.push_str(&insert);
+                }
+                _ => changes.push((range, insert)),
+            }
+            if idx == self.primary {
+                primary = changes.len() - 1;
+            }
+        }
+        // Back to front, so the offsets still to come stay valid
+        for (range, insert) in changes.iter().rev() {
+            let removed = replace(text, view, range.clone(), insert);
+            edits.push(Edit {
+                at: range.start,
+                removed,
+                inserted: insert.clone(),
+            });
+        }
+        let mut shift = 0isize;
+        let sels = changes
+            .iter()
+            .map(|(range, insert)| {
+                let inserted = insert.chars().count();
+                let at = range.start.saturating_add_signed(shift) + inserted;
+                shift += inserted as isize - range.len() as isize;
+                Selection::caret(at)
+            })
+            .collect();
+        self.set(sels, primary);
+        self.reveal = Some(Reveal::Nearest);
+    }
+
+    /// Type `typed` at every caret, over whatever each has selected.
+    fn insert(&mut self, text: &mut Buffer, view: &mut View, edits: &mut Vec<Edit>, typed: &str) {
+        self.replace_each(text, view, edits, |_, sel| (sel.range(), typed.to_owned()));
+    }
+
+    /// Paste at every caret. With one pasted line per caret (as copied from
+    /// the same number of selections), each caret gets its own line.
+    fn paste(&mut self, text: &mut Buffer, view: &mut View, edits: &mut Vec<Edit>, pasted: &str) {
+        let pasted = eol::normalize(pasted);
+        let trimmed = pasted.strip_suffix('\n').unwrap_or(&pasted);
+        let parts: Vec<&str> = trimmed.split('\n').collect();
+        if self.sels.len() > 1 && parts.len() == self.sels.len() {
+            self.replace_each(text, view, edits, |idx, sel| (sel.range(), parts[idx].to_owned()));
+        } else {
+            self.insert(text, view, edits, &pasted);
+        }
+    }
+
+    /// Backspace or Delete at every caret: the selection if there is one,
+    /// else the char (or word) before or after it.
+    fn delete(&mut self, text: &mut Buffer, view: &mut View, edits: &mut Vec<Edit>, forward: bool, word: bool) {
+        let rope = text.rope();
+        let ranges: Vec<Range<usize>> = self
+            .sels
+            .iter()
+            .map(|sel| match (sel.is_empty(), forward, word) {
+                (false, _, _) => sel.range(),
+                (true, true, true) => sel.head..word_right(rope, sel.head),
+                (true, true, false) => sel.head..(sel.head + 1).min(rope.len_chars()),
+                (true, false, true) => word_left(rope, sel.head)..sel.head,
+                (true, false, false) => sel.head.saturating_sub(1)..sel.head,
+            })
+            .collect();
+        self.replace_each(text, view, edits, |idx, _| (ranges[idx].clone(), String::new()));
+    }
+
+    /// The selected text, one line per selection, for the clipboard.
+    fn copied(&self, text: &Buffer) -> Option<String> {
+        let parts: Vec<String> = self
+            .sels
+            .iter()
+            .filter(|sel| !sel.is_empty())
+            .map(|sel| text.slice(sel.range()))
+            .collect();
+        (!parts.is_empty()).then(|| parts.join("\n"))
+    }
+}
+
+/// Font and wrapping the text is laid out with this frame.
+struct Style {
+    font_id: FontId,
+    row_height: f32,
+    char_width: f32,
+    /// Infinite without word wrap.
+    wrap_width: f32,
+}
+
+fn lay_out_line(ctx: &egui::Context, style: &Style, rope: &Rope, line: usize) -> Arc<Galley> {
+    let job = LayoutJob::simple(
+        rope.slice(line_span(rope, line)).to_string(),
+        style.font_id.clone(),
+        Color32::PLACEHOLDER,
+        style.wrap_width,
+    );
+    ctx.fonts(|fonts| fonts.layout_job(job))
+}
+
+/// How tall each line is with word wrap on. Lines that have not been on
+/// screen yet are guessed from their length; once laid out, a line keeps
+/// its real height until it is edited.
+#[derive(Clone)]
+struct WrapIndex {
+    /// About how many chars fit in a row.
+    columns: f32,
+    row_height: f32,
+    heights: Vec<f32>,
+    /// Where each line starts, plus where the last one ends. Emptied when
+    /// the heights change and rebuilt before it is next needed.
+    tops: Vec<f32>,
+}
+
+impl WrapIndex {
+    fn new(rope: &Rope, style: &Style) -> Self {
+        let mut index = Self {
+            columns: (style.wrap_width / style.char_width).max(1.0),
+            row_height: style.row_height,
+            heights: Vec::with_capacity(rope.len_lines()),
+            tops: Vec::new(),
+        };
+        for line in rope.lines() {
+            let height = index.estimate(line.len_chars());
+            index.heights.push(height);
+        }
+        index.rebuild_tops();
+        index
+    }
+
+    fn fits(&self, style: &Style) -> bool {
+        self.columns == (style.wrap_width / style.char_width).max(1.0) && self.row_height == style.row_height
+    }
+
+    fn estimate(&self, chars: usize) -> f32 {
+        (chars as f32 / self.columns).ceil().max(1.0) * self.row_height
+    }
+
+    fn rebuild_tops(&mut self) {
+        self.tops.clear();
+        self.tops.reserve(self.heights.len() + 1);
+        let mut top = 0.0;
+        self.tops.push(top);
+        for height in &self.heights {
+            top += height;
+            self.tops.push(top);
+        }
+    }
+
+    /// Follow `insert` replacing the chars in `range` of `rope`, before it
+    /// happens: the lines it touches get fresh estimates.
+    fn splice(&mut self, rope: &Rope, range: Range<usize>, insert: &str) {
+        let first = rope.char_to_line(range.start);
+        let last = rope.char_to_line(range.end);
+        let head = range.start - rope.line_to_char(first);
+        let tail = line_span(rope, last).end - range.end;
+        let mut lens: Vec<usize> = insert.split('\n').map(|part| part.chars().count()).collect();
+        lens[0] += head;
+        if let Some(len) = lens.last_mut() {
+            *len += tail;
+        }
+        let heights: Vec<f32> = lens.into_iter().map(|len| self.estimate(len)).collect();
+        self.heights.splice(first..=last, heights);
+        self.tops.clear();
+    }
+}
+
+/// What the editor keeps about one document between frames.
+#[derive(Clone, Default)]
+struct View {
+    /// The buffer version the wrap index describes.
+    version: u64,
+    /// The widest line laid out so far, so scrolling sideways reaches it.
+    widest: f32,
+    wrap: Option<WrapIndex>,
+}
+
+impl View {
+    /// Get ready to lay out `text`: with word wrap, make sure the index
+    /// matches the text and the wrap width.
+    fn prepare(&mut self, text: &Buffer, style: &Style, word_wrap: bool) {
+        if !word_wrap {
+            self.wrap = None;
+            return;
+        }
+        match &mut self.wrap {
+            Some(wrap) if self.version == text.version() && wrap.fits(style) => {
+                if wrap.tops.is_empty() {
+                    wrap.rebuild_tops();
+                }
+            }
+            _ => {
+                self.wrap = Some(WrapIndex::new(text.rope(), style));
+                self.version = text.version();
+            }
+        }
+    }
+
+    /// Distance from the top of the text to the top of `line`.
+    fn top(&self, line: usize, row_height: f32) -> f32 {
+        match &self.wrap {
+            Some(wrap) => wrap.tops[line],
+            None => line as f32 * row_height,
+        }
+    }
+
+    /// The line at height `y`, clamped to the first and last of `lines`.
+    fn line_at(&self, y: f32, row_height: f32, lines: usize) -> usize {
+        let line = match &self.wrap {
+            Some(wrap) => wrap.tops.partition_point(|&top| top <= y).saturating_sub(1),
+            None => (y / row_height).max(0.0) as usize,
+        };
+        line.min(lines - 1)
+    }
+
+    /// Lay out the lines that show in `clip`, a rectangle relative to the
+    /// text, and note their real sizes.
+    fn lay_out(&mut self, ctx: &egui::Context, style: &Style, rope: &Rope, clip: Rect) -> Vec<LineLayout> {
+        let lines = rope.len_lines();
+        let mut line = self.line_at(clip.min.y, style.row_height, lines);
+        let mut top = self.top(line, style.row_height);
+        let mut shown = Vec::new();
+        let mut resized = false;
+        while line < lines && (shown.is_empty() || top < clip.max.y) {
+            let galley = lay_out_line(ctx, style, rope, line);
+            let height = galley.size().y.max(style.row_height);
+            self.widest = self.widest.max(galley.size().x);
+            if let Some(wrap) = &mut self.wrap
+                && wrap.heights[line] != height
+            {
+                wrap.heights[line] = height;
+                resized = true;
+            }
+            shown.push(LineLayout {
+                line,
+                start: rope.line_to_char(line),
+                top,
+                galley,
+            });
+            top += height;
+            line += 1;
+        }
+        if resized && let Some(wrap) = &mut self.wrap {
+            wrap.rebuild_tops();
+        }
+        shown
+    }
+}
+
+struct LineLayout {
+    line: usize,
+    /// Char offset of the line's first char.
+    start: usize,
+    /// Distance from the top of the text.
+    top: f32,
+    galley: Arc<Galley>,
+}
+
+impl LineLayout {
+    /// Length in chars, without the newline.
+    fn len(&self) -> usize {
+        self.galley.rows.iter().map(|row| row.char_count_excluding_newline()).sum()
+    }
+}
+
+/// The text as laid out this frame. Only the lines on screen are laid out
+/// up front; any other line is laid out when asked about, and placed by
+/// the row height or, with word wrap, the wrap index. Positions are
+/// relative to the text's top-left corner unless they say otherwise.
+struct Layout<'a> {
+    ctx: &'a egui::Context,
+    style: &'a Style,
+    rope: &'a Rope,
+    view: &'a View,
+    shown: &'a [LineLayout],
+    origin: Pos2,
+}
+
+impl Layout<'_> {
+    fn top(&self, line: usize) -> f32 {
+        self.view.top(line, self.style.row_height)
+    }
+
+    /// The line at height `y`, clamped to the first and last.
+    fn line_at(&self, y: f32) -> usize {
+        self.view.line_at(y, self.style.row_height, self.rope.len_lines())
+    }
+
+    fn is_shown(&self, line: usize) -> bool {
+        self.shown
+            .first()
+            .is_some_and(|first| line >= first.line && line < first.line + self.shown.len())
+    }
+
+    fn galley(&self, line: usize) -> Arc<Galley> {
+        if self.is_shown(line) {
+            self.shown[line - self.shown[0].line].galley.clone()
+        } else {
+            lay_out_line(self.ctx, self.style, self.rope, line)
+        }
+    }
+
+    /// A thin rectangle where the caret at `at` goes.
+    fn caret(&self, at: usize) -> Rect {
+        let line = self.rope.char_to_line(at);
+        self.galley(line)
+            .pos_from_ccursor(CCursor::new(at - self.rope.line_to_char(line)))
+            .translate(vec2(0.0, self.top(line)))
+    }
+
+    fn caret_on_screen(&self, at: usize) -> Rect {
+        self.caret(at).translate(self.origin.to_vec2())
+    }
+
+    /// The char nearest to `pos`.
+    fn char_at(&self, pos: Vec2) -> usize {
+        let line = self.line_at(pos.y);
+        let galley = self.galley(line);
+        self.rope.line_to_char(line) + galley.cursor_from_pos(pos - vec2(0.0, self.top(line))).ccursor.index
+    }
+
+    /// The char at `x` on the first row of `line`.
+    fn column_at(&self, line: usize, x: f32) -> usize {
+        self.rope.line_to_char(line) + self.galley(line).cursor_from_pos(vec2(x, 0.0)).ccursor.index
+    }
+
+    /// The char one row above or below `at`, as near `x` as the row allows.
+    fn vertical(&self, at: usize, x: f32, down: bool) -> usize {
+        let line = self.rope.char_to_line(at);
+        let start = self.rope.line_to_char(line);
+        let galley = self.galley(line);
+        let row = galley.from_ccursor(CCursor::new(at - start)).rcursor.row;
+        let on_row = |line: usize, galley: &Galley, row: usize| {
+            let y = galley.rows[row].rect.center().y;
+            self.rope.line_to_char(line) + galley.cursor_from_pos(vec2(x, y)).ccursor.index
+        };
+        if down {
+            if row + 1 < galley.rows.len() {
+                on_row(line, &galley, row + 1)
+            } else if line + 1 < self.rope.len_lines() {
+                on_row(line + 1, &self.galley(line + 1), 0)
+            } else {
+                self.rope.len_chars()
+            }
+        } else if row > 0 {
+            on_row(line, &galley, row - 1)
+        } else if line > 0 {
+            let above = self.galley(line - 1);
+            on_row(line - 1, &above, above.rows.len() - 1)
+        } else {
+            0
+        }
+    }
+
+    /// Rectangles covering `range` on the lines on screen, one per row.
+    fn range_rects(&self, range: Range<usize>) -> Vec<Rect> {
+        let mut rects = Vec::new();
+        for line in self.shown {
+            let len = line.len();
+            if range.start > line.start + len || range.end < line.start {
+                continue;
+            }
+            let start = range.start.saturating_sub(line.start).min(len);
+            let end = (range.end - line.start).min(len);
+            // Show a selected newline as a sliver past the end of the line
+            let newline = range.end > line.start + len;
+            let mut row_start = 0;
+            for (row_idx, row) in line.galley.rows.iter().enumerate() {
+                let row_end = row_start + row.char_count_excluding_newline();
+                if start <= row_end && end >= row_start {
+                    let x0 = row.x_offset(start.max(row_start) - row_start);
+                    let mut x1 = row.x_offset(end.min(row_end) - row_start);
+                    if newline && row_idx + 1 == line.galley.rows.len() {
+                        x1 += self.style.row_height * 0.5;
+                    }
+                    if x1 > x0 {
+                        let top = line.top + row.rect.min.y;
+                        rects.push(Rect::from_min_max(pos2(x0, top), pos2(x1, line.top + row.rect.max.y)));
+                    }
+                }
+                row_start = row_end;
+            }
+        }
+        rects
+    }
+}
+
+/// What the editor did this frame.
+pub struct EditorOutput {
+    pub response: Response,
+    /// Changes to the text in the order they were made, one batch per
+    /// keystroke with an edit for each caret.
+    pub edits: Vec<Vec<Edit>>,
+    /// The carets were moved, so the next edit starts a new undo step.
+    pub moved: bool,
+}
+
+/// Show `text` for editing at `cursors`, with `shading` painted behind the
+/// text, e.g. for find matches.
+///
+/// Typing, deleting and pasting act at every caret at once. Ctrl+click
+/// adds a caret, and Alt+drag selects a rectangle, one selection per line.
+/// Only the lines in view are laid out, so the size of the text matters
+/// little.
+pub fn show(
+    ui: &mut egui::Ui,
+    id: egui::Id,
+    text: &mut Buffer,
+    cursors: &mut Cursors,
+    word_wrap: bool,
+    shading: Shading,
+) -> EditorOutput {
+    cursors.clamp(text.len_chars());
+    let mut view: View = ui.data_mut(|data| data.remove_temp(id)).unwrap_or_default();
+    let available = ui.available_size();
+    let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+    let (row_height, char_width) = ui.fonts(|fonts| (fonts.row_height(&font_id), fonts.glyph_width(&font_id, 'x')));
+    // Rows are laid out a whole number of pixels apart
+    let row_height = ui.painter().round_to_pixel(row_height);
+    let style = Style {
+        font_id,
+        row_height,
+        char_width,
+        wrap_width: if word_wrap {
+            (available.x - 2.0 * MARGIN.x).max(1.0)
+        } else {
+            f32::INFINITY
+        },
+    };
+    let ctx = ui.ctx().clone();
+    let origin = ui.cursor().min + MARGIN;
+    let clip = ui.clip_rect().translate(-origin.to_vec2());
+    view.prepare(text, &style, word_wrap);
+    let mut shown = view.lay_out(&ctx, &style, text.rope(), clip);
+
+    let height = view.top(text.rope().len_lines(), row_height);
+    let width = if word_wrap {
+        available.x
+    } else {
+        // Room for a caret after the longest line
+        available.x.max(view.widest + 2.0 * MARGIN.x + row_height)
+    };
+    let size = vec2(width, available.y.max(height + 2.0 * MARGIN.y));
+    let rect = ui.allocate_space(size).1;
+    let response = ui.interact(rect, id, Sense::click_and_drag());
+    let mut edits = Vec::new();
+    let mut moved = false;
+
+    if response.hovered() {
+        ui.ctx().set_cursor_icon(egui::CursorIcon::Text);
+        ui.output_mut(|o| o.mutable_text_under_cursor = true);
+    }
+    if let Some(pointer) = response.interact_pointer_pos() {
+        let layout = Layout {
+            ctx: &ctx,
+            style: &style,
+            rope: text.rope(),
+            view: &view,
+            shown: &shown,
+            origin,
+        };
+        let rope = text.rope();
+        let pos = pointer - origin;
+        let at = layout.char_at(pos);
+        let (pressed, modifiers) = ui.input(|i| (i.pointer.primary_pressed(), i.modifiers));
+        if response.triple_clicked() {
+            let line = rope.char_to_line(at);
+            let end = if line + 1 < rope.len_lines() {
+                rope.line_to_char(line + 1)
+            } else {
+                rope.len_chars()
+            };
+            cursors.set_primary(Selection {
+                anchor: rope.line_to_char(line),
+                head: end,
+            });
+        } else if response.double_clicked() {
+            let word = word_at(rope, at);
+            cursors.set_primary(Selection {
+                anchor: word.start,
+                head: word.end,
+            });
+        } else if pressed {
+            response.request_focus();
+            cursors.block_from = None;
+            if modifiers.alt {
+                cursors.block_from = Some(pos);
+                cursors.set(vec![Selection::caret(at)], 0);
+            } else if modifiers.command {
+                cursors.add(Selection::caret(at));
+            } else if modifiers.shift {
+                let anchor = cursors.primary().anchor;
+                cursors.set(vec![Selection { anchor, head: at }], 0);
+            } else {
+                cursors.set(vec![Selection::caret(at)], 0);
+            }
+        } else if response.dragged() {
+            match cursors.block_from {
+                Some(from) => cursors.select_block(&layout, from, pos),
+                None => {
+                    let anchor = cursors.primary().anchor;
+                    cursors.set_primary(Selection { anchor, head: at });
+                }
+            }
+            cursors.reveal = Some(Reveal::Nearest);
+        }
+        moved = true;
+    }
+
+    if response.has_focus() && ui.is_enabled() {
+        ui.memory_mut(|mem| {
+            mem.set_focus_lock_filter(
+                id,
+                EventFilter {
+                    tab: true,
+                    horizontal_arrows: true,
+                    vertical_arrows: true,
+                    // Escape first drops the extra carets, then leaves the editor
+                    escape: cursors.count() > 1,
+                },
+            );
+        });
+        let events = ui.input(|i| i.events.clone());
+        let mut stale = false;
+        for event in events {
+            let mut batch = Vec::new();
+            match event {
+                Event::Text(typed) | Event::CompositionEnd(typed) => {
+                    cursors.insert(text, &mut view, &mut batch, &typed);
+                }
+                Event::Paste(pasted) => cursors.paste(text, &mut view, &mut batch, &pasted),
+                Event::Copy => {
+                    if let Some(copied) = cursors.copied(text) {
+                        ui.ctx().copy_text(copied);
+                    }
+                }
+                Event::Cut => {
+                    if let Some(copied) = cursors.copied(text) {
+                        ui.ctx().copy_text(copied);
+                        cursors.delete(text, &mut view, &mut batch, false, false);
+                    }
+                }
+                Event::Key {
+                    key: key @ (Key::ArrowUp | Key::ArrowDown),
+                    pressed: true,
+                    modifiers,
+                    ..
+                } => {
+                    if stale {
+                        view.prepare(text, &style, word_wrap);
+                        shown = view.lay_out(&ctx, &style, text.rope(), clip);
+                        stale = false;
+                    }
+                    let layout = Layout {
+                        ctx: &ctx,
+                        style: &style,
+                        rope: text.rope(),
+                        view: &view,
+                        shown: &shown,
+                        origin,
+                    };
+                    cursors.move_vertical(&layout, key == Key::ArrowDown, modifiers.shift);
+                    moved = true;
+                }
+                Event::Key {
+                    key,
+                    pressed: true,
+                    modifiers,
+                    ..
+                } => moved |= on_key(text, &mut view, cursors, &mut batch, key, modifiers),
+                _ => {}
+            }
+            if !batch.is_empty() {
+                edits.push(batch);
+                stale = true;
+            }
+        }
+        if stale {
+            view.prepare(text, &style, word_wrap);
+            shown = view.lay_out(&ctx, &style, text.rope(), clip);
+            // The space allocated above was for the old text
+            ui.ctx().request_repaint();
+        }
+    }
+
+    let layout = Layout {
+        ctx: &ctx,
+        style: &style,
+        rope: text.rope(),
+        view: &view,
+        shown: &shown,
+        origin,
+    };
+    paint(ui, &response, &layout, cursors, edits.is_empty().then_some(shading));
+    if let Some(reveal) = cursors.reveal.take() {
+        let caret = layout.caret_on_screen(cursors.primary().head).expand2(MARGIN);
+        let align = match reveal {
+            Reveal::Nearest => None,
+            Reveal::Centre => Some(Align::Center),
+        };
+        ui.scroll_to_rect(caret, align);
+    }
+    ui.data_mut(|data| data.insert_temp(id, view));
+    EditorOutput {
+        response,
+        edits,
+        moved,
+    }
+}
+
+/// Act on one key press other than Up and Down. Returns true if it moved
+/// the carets.
+fn on_key(
+    text: &mut Buffer,
+    view: &mut View,
+    cursors: &mut Cursors,
+    edits: &mut Vec<Edit>,
+    key: Key,
+    modifiers: Modifiers,
+) -> bool {
+    let extend = modifiers.shift;
+    // Alt on macOS, Ctrl elsewhere
+    let word = modifiers.alt || modifiers.ctrl;
+    let rope: &Rope = text.rope();
+    match key {
+        Key::Enter => cursors.insert(text, view, edits, "\n"),
+        Key::Tab if !modifiers.any() => cursors.insert(text, view, edits, "\t"),
+        Key::Backspace => cursors.delete(text, view, edits, false, word),
+        Key::Delete => cursors.delete(text, view, edits, true, word),
+        Key::A if modifiers.command => {
+            cursors.set(
+                vec![Selection {
+                    anchor: 0,
+                    head: rope.len_chars(),
+                }],
+                0,
+            );
+            return true;
+        }
+        Key::Escape if cursors.count() > 1 => {
+            let primary = cursors.primary();
+            cursors.set(vec![primary], 0);
+            return true;
+        }
+        Key::ArrowLeft => {
+            cursors.move_heads(extend, |sel| match (sel.is_empty() || extend, word) {
+                (false, false) => sel.range().start,
+                (_, true) => word_left(rope, sel.head),
+                (true, false) => sel.head.saturating_sub(1),
+            });
+            return true;
+        }
+        Key::ArrowRight => {
+            cursors.move_heads(extend, |sel| match (sel.is_empty() || extend, word) {
+                (false, false) => sel.range().end,
+                (_, true) => word_right(rope, sel.head),
+                (true, false) => (sel.head + 1).min(rope.len_chars()),
+            });
+            return true;
+        }
+        Key::Home => {
+            cursors.move_heads(extend, |sel| {
+                if modifiers.command {
+                    return 0;
+                }
+                // First to the indentation, then to the very start
+                let span = line_span(rope, rope.char_to_line(sel.head));
+                let indent = rope.slice(span.clone()).chars().take_while(|c| c.is_whitespace()).count();
+                let start = span.start;
+                if sel.head == start + indent { start } else { start + indent }
+            });
+            return true;
+        }
+        Key::End => {
+            cursors.move_heads(extend, |sel| {
+                if modifiers.command {
+                    rope.len_chars()
+                } else {
+                    line_span(rope, rope.char_to_line(sel.head)).end
+                }
+            });
+            return true;
+        }
+        _ => {}
+    }
+    false
+}
+
+fn paint(ui: &egui::Ui, response: &Response, layout: &Layout, cursors: &Cursors, shading: Option<Shading>) {
+    let visuals = ui.visuals();
+    let painter = ui.painter();
+    let focused = response.has_focus();
+    let stroke = if focused {
+        visuals.selection.stroke
+    } else if response.hovered() {
+        visuals.widgets.hovered.bg_stroke
+    } else {
+        visuals.widgets.inactive.bg_stroke
+    };
+    painter.rect(response.rect, visuals.widgets.inactive.rounding, visuals.extreme_bg_color, stroke);
+
+    let offset = layout.origin.to_vec2();
+    let rope = layout.rope;
+    // Shading is in bytes of the text as the caller saw it, so skip it on a
+    // frame that edited the text
+    if let Some(shading) = shading
+        && let (Some(first), Some(last)) = (layout.shown.first(), layout.shown.last())
+    {
+        let from = rope.char_to_byte(first.start);
+        let to = rope.char_to_byte(last.start + last.len());
+        let skipped = shading.ranges.partition_point(|range| range.end < from);
+        let other = visuals.warn_fg_color.gamma_multiply(0.3);
+        for (idx, range) in shading.ranges.iter().enumerate().skip(skipped) {
+            if range.start > to {
+                break;
+            }
+            let color = if shading.current == Some(idx) {
+                visuals.selection.bg_fill
+            } else {
+                other
+            };
+            let chars = rope.byte_to_char(range.start)..rope.byte_to_char(range.end);
+            for rect in layout.range_rects(chars) {
+                painter.rect_filled(rect.translate(offset), 0.0, color);
+            }
+        }
+    }
+    let selection = if focused {
+        visuals.selection.bg_fill
+    } else {
+        visuals.selection.bg_fill.gamma_multiply(0.5)
+    };
+    for sel in cursors.sels.iter().filter(|sel| !sel.is_empty()) {
+        for rect in layout.range_rects(sel.range()) {
+            painter.rect_filled(rect.translate(offset), 0.0, selection);
+        }
+    }
+    let color = visuals
+        .override_text_color
+        .unwrap_or_else(|| visuals.widgets.inactive.text_color());
+    for line in layout.shown {
+        painter.galley(layout.origin + vec2(0.0, line.top), line.galley.clone(), color);
+    }
+    if focused {
+        for sel in &cursors.sels {
+            if layout.is_shown(rope.char_to_line(sel.head)) {
+                let caret = layout.caret_on_screen(sel.head);
+                painter.line_segment([caret.center_top(), caret.center_bottom()], visuals.text_cursor);
+            }
+        }
+        let cursor_rect = layout.caret_on_screen(cursors.primary().head);
+        ui.ctx().output_mut(|o| {
+            o.ime = Some(egui::output::IMEOutput {
+                rect: response.rect,
+                cursor_rect,
+            });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sel(anchor: usize, head: usize) -> Selection {
+        Selection { anchor, head }
+    }
+
+    fn cursors(sels: Vec<Selection>, primary: usize) -> Cursors {
+        let mut cursors = Cursors::default();
+        cursors.set(sels, primary);
+        cursors
+    }
+
+    #[test]
+    fn occurrences_match_a_whole_string_search() {
+        let text: String = (0..3000)
+            .map(|i| match i % 7 {
+                0 => "needle",
+                3 => "né",
+                _ => "hay",
+            })
+            .collect();
+        let rope = Rope::from_str(&text);
+        assert!(rope.chunks().count() > 1);
+        for needle in ["needle", "éha", "yn", "a"] {
+            let expected: Vec<usize> = text
+                .match_indices(needle)
+                .map(|(byte, _)| rope.byte_to_char(byte))
+                .collect();
+            assert_eq!(occurrences(&rope, needle), expected, "{needle}");
+        }
+        // Matches never overlap, even across chunk boundaries
+        let rope = Rope::from_str(&"a".repeat(5001));
+        assert_eq!(occurrences(&rope, "aa"), (0..2500).map(|i| i * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn normalize_sorts_and_merges_carets() {
+        let sels = vec![sel(6, 2), Selection::caret(0), sel(4, 9), Selection::caret(0), sel(12, 14)];
+        let merged = cursors(sels, 2);
+        // The overlapping pair merges, keeping the first one's direction
+        assert_eq!(merged.sels, [Selection::caret(0), sel(9, 2), sel(12, 14)]);
+        assert_eq!(merged.primary().range(), 2..9);
+        // Selections that only touch stay apart
+        let touching = cursors(vec![sel(2, 4), sel(0, 2)], 0);
+        assert_eq!(touching.sels, [sel(0, 2), sel(2, 4)]);
+        assert_eq!(touching.primary(), sel(2, 4));
+    }
+
+    #[test]
+    fn clamp_pulls_carets_back_into_the_text() {
+        let mut clamped = cursors(vec![Selection::caret(1), sel(2, 6), Selection::caret(9)], 2);
+        clamped.clamp(4);
+        assert_eq!(clamped.sels, [Selection::caret(1), sel(2, 4), Selection::caret(4)]);
+        assert_eq!(clamped.primary(), Selection::caret(4));
+        // Carets that land on the same char become one
+        let mut clamped = cursors(vec![Selection::caret(6), Selection::caret(9)], 0);
+        clamped.clamp(4);
+        assert_eq!(clamped.sels, [Selection::caret(4)]);
+    }
+
+    #[test]
+    fn paste_gives_each_caret_its_own_line() {
+        let mut text = Buffer::from("a\nb\nc");
+        let (mut view, mut edits) = (View::default(), Vec::new());
+        let mut carets = cursors(vec![Selection::caret(1), Selection::caret(3), Selection::caret(5)], 0);
+        carets.paste(&mut text, &mut view, &mut edits, "1\r\n2\r\n3\r\n");
+        assert_eq!(text.to_string(), "a1\nb2\nc3");
+        assert_eq!(carets.sels, [Selection::caret(2), Selection::caret(5), Selection::caret(8)]);
+        assert_eq!(edits.len(), 3);
+        // A different number of lines goes in whole at every caret
+        let mut carets = cursors(vec![Selection::caret(0), sel(3, 5)], 1);
+        carets.paste(&mut text, &mut view, &mut edits, "x\ny\nz");
+        assert_eq!(text.to_string(), "x\ny\nza1\nx\ny\nz\nc3");
+        assert_eq!(carets.primary(), Selection::caret(13));
+    }
+
+    #[test]
+    fn add_next_occurrence_wraps_around() {
+        let text = Buffer::from("foo bar foo baz foo");
+        let mut cursors = Cursors::at(9);
+        // First the word under the caret, then the ones after it, then wrap
+        assert!(cursors.add_next_occurrence(&text));
+        assert_eq!(cursors.sels, [sel(8, 11)]);
+        assert!(cursors.add_next_occurrence(&text));
+        assert!(cursors.add_next_occurrence(&text));
+        assert_eq!(cursors.sels, [sel(0, 3), sel(8, 11), sel(16, 19)]);
+        assert_eq!(cursors.primary(), sel(0, 3));
+        // Every one is taken
+        assert!(!cursors.add_next_occurrence(&text));
+        assert_eq!(cursors.count(), 3);
+    }
+
+    #[test]
+    fn select_block_takes_the_same_columns_on_each_line() {
+        let ctx = egui::Context::default();
+        let _ = ctx.run(Default::default(), |_| {});
+        let font_id = FontId::monospace(14.0);
+        let (row_height, char_width) =
+            ctx.fonts(|fonts| (fonts.row_height(&font_id), fonts.glyph_width(&font_id, 'x')));
+        let style = Style {
+            font_id,
+            row_height,
+            char_width,
+            wrap_width: f32::INFINITY,
+        };
+        let rope = Rope::from_str("abcdef\nab\nabcdef");
+        let view = View::default();
+        let layout = Layout {
+            ctx: &ctx,
+            style: &style,
+            rope: &rope,
+            view: &view,
+            shown: &[],
+            origin: Pos2::ZERO,
+        };
+        let at = |column: f32, line: f32| vec2(column * char_width, (line + 0.5) * row_height);
+        let mut cursors = Cursors::default();
+        // Dragged up and to the left: the short line is cut off at its end
+        cursors.select_block(&layout, at(4.0, 2.0), at(1.0, 0.0));
+        assert_eq!(cursors.sels, [sel(4, 1), sel(9, 8), sel(14, 11)]);
+        assert_eq!(cursors.primary(), sel(4, 1));
+    }
+}
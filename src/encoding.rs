@@ -0,0 +1,197 @@
+use anyhow::{Result, bail};
+use encoding_rs::{Encoding, UTF_8, UTF_16BE, UTF_16LE, WINDOWS_1252};
+use std::fmt;
+
+/// Bytes inspected when guessing at BOM-less UTF-16.
+const SNIFF_LEN: usize = 8 * 1024;
+
+/// How a document's text is stored on disk.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TextEncoding {
+    pub encoding: &'static Encoding,
+    pub bom: bool,
+}
+
+impl Default for TextEncoding {
+    fn default() -> Self {
+        Self::new(UTF_8, false)
+    }
+}
+
+impl fmt::Display for TextEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.encoding.name())?;
+        if self.bom {
+            f.write_str(" BOM")?;
+        }
+        Ok(())
+    }
+}
+
+/// Encodings offered by "Reopen with Encoding" / "Save with Encoding".
+pub const CHOICES: &[(&str, TextEncoding)] = &[
+    ("UTF-8", TextEncoding::new(UTF_8, false)),
+    ("UTF-8 with BOM", TextEncoding::new(UTF_8, true)),
+    ("UTF-16LE", TextEncoding::new(UTF_16LE, true)),
+    ("UTF-16BE", TextEncoding::new(UTF_16BE, true)),
+    ("Western (Windows-1252 / Latin-1)", TextEncoding::new(WINDOWS_1252, false)),
+    ("Central European (ISO-8859-2)", TextEncoding::new(encoding_rs::ISO_8859_2, false)),
+    ("Western (ISO-8859-15)", TextEncoding::new(encoding_rs::ISO_8859_15, false)),
+    ("Cyrillic (Windows-1251)", TextEncoding::new(encoding_rs::WINDOWS_1251, false)),
+    ("Japanese (Shift_JIS)", TextEncoding::new(encoding_rs::SHIFT_JIS, false)),
+    ("Chinese (GBK)", TextEncoding::new(encoding_rs::GBK, false)),
+];
+
+impl TextEncoding {
+    pub const fn new(encoding: &'static Encoding, bom: bool) -> Self {
+        Self { encoding, bom }
+    }
+
+    pub fn is_utf16(self) -> bool {
+        self.encoding == UTF_16LE || self.encoding == UTF_16BE
+    }
+
+    /// Guess the encoding of `bytes`: a BOM wins, then UTF-16 judged by where
+    /// the NUL bytes fall, then valid UTF-8, and finally Windows-1252, which
+    /// decodes any byte sequence.
+    pub fn detect(bytes: &[u8]) -> Self {
+        Self::guess(bytes, true)
+    }
+
+    /// `detect` for the first part of a longer file: a char cut off at the
+    /// end still counts as UTF-8. The caller falls back to Windows-1252 if
+    /// the rest turns out not to be.
+    pub fn detect_start(bytes: &[u8]) -> Self {
+        Self::guess(bytes, false)
+    }
+
+    fn guess(bytes: &[u8], complete: bool) -> Self {
+        if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+            return Self::new(encoding, true);
+        }
+        let sample = &bytes[..bytes.len().min(SNIFF_LEN)];
+        let even_nuls = sample.iter().step_by(2).filter(|b| **b == 0).count();
+        let odd_nuls = sample.iter().skip(1).step_by(2).filter(|b| **b == 0).count();
+        let pairs = sample.len() / 2;
+        // Mostly-ASCII UTF-16 has a NUL in every other byte
+        if pairs > 0 && odd_nuls * 10 > pairs * 3 && even_nuls * 10 < pairs {
+            return Self::new(UTF_16LE, false);
+        }
+        if pairs > 0 && even_nuls * 10 > pairs * 3 && odd_nuls * 10 < pairs {
+            return Self::new(UTF_16BE, false);
+        }
+        let utf8 = match std::str::from_utf8(bytes) {
+            Ok(_) => true,
+            Err(e) => !complete && e.error_len().is_none(),
+        };
+        if utf8 {
+            return Self::new(UTF_8, false);
+        }
+        Self::new(WINDOWS_1252, false)
+    }
+
+    /// Decode `bytes`, skipping a leading BOM. Returns `None` if they are not
+    /// valid in this encoding.
+    pub fn decode(self, bytes: &[u8]) -> Option<String> {
+        let body = match Encoding::for_bom(bytes) {
+            Some((encoding, len)) if encoding == self.encoding => &bytes[len..],
+            _ => bytes,
+        };
+        self.encoding
+            .decode_without_bom_handling_and_without_replacement(body)
+            .map(|text| text.into_owned())
+    }
+
+    /// Encode `text` for writing, failing rather than silently replacing
+    /// characters the encoding cannot represent.
+    pub fn encode(self, text: &str) -> Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(text.len() + 3);
+        if self.is_utf16() {
+            let little = self.encoding == UTF_16LE;
+            if self.bom {
+                out.extend_from_slice(if little { &[0xFF, 0xFE] } else { &[0xFE, 0xFF] });
+            }
+            for unit in text.encode_utf16() {
+                out.extend_from_slice(&if little {
+                    unit.to_le_bytes()
+                } else {
+                    unit.to_be_bytes()
+                });
+            }
+            return Ok(out);
+        }
+        if self.bom && self.encoding == UTF_8 {
+            out.extend_from_slice(&[0xEF, 0xBB, 0xBF]);
+        }
+        let (bytes, _, unmappable) = self.encoding.encode(text);
+        if unmappable {
+            bail!(
+                "text contains characters that cannot be saved as {}",
+                self.encoding.name()
+            );
+        }
+        out.extend_from_slice(&bytes);
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bom_decides_the_encoding() {
+        assert_eq!(TextEncoding::detect(b"\xEF\xBB\xBFhi"), TextEncoding::new(UTF_8, true));
+        assert_eq!(TextEncoding::detect(b"\xFF\xFEh\0i\0"), TextEncoding::new(UTF_16LE, true));
+        assert_eq!(TextEncoding::detect(b"\xFE\xFF\0h\0i"), TextEncoding::new(UTF_16BE, true));
+    }
+
+    #[test]
+    fn bomless_utf16_is_found_by_its_nuls() {
+        let le: Vec<u8> = "plain ascii text".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let be: Vec<u8> = "plain ascii text".encode_utf16().flat_map(u16::to_be_bytes).collect();
+        assert_eq!(TextEncoding::detect(&le), TextEncoding::new(UTF_16LE, false));
+        assert_eq!(TextEncoding::detect(&be), TextEncoding::new(UTF_16BE, false));
+        assert_eq!(TextEncoding::new(UTF_16LE, false).decode(&le).unwrap(), "plain ascii text");
+    }
+
+    #[test]
+    fn invalid_utf8_falls_back_to_windows_1252() {
+        assert_eq!(TextEncoding::detect("café".as_bytes()), TextEncoding::default());
+        let latin1 = b"caf\xE9 \x93quoted\x94";
+        let encoding = TextEncoding::detect(latin1);
+        assert_eq!(encoding, TextEncoding::new(WINDOWS_1252, false));
+        assert_eq!(encoding.decode(latin1).unwrap(), "café \u{201C}quoted\u{201D}");
+        assert!(TextEncoding::default().decode(latin1).is_none());
+    }
+
+    #[test]
+    fn detect_start_allows_a_char_cut_off_at_the_end() {
+        let cut = &"naïve".as_bytes()[..3];
+        assert_eq!(TextEncoding::detect_start(cut), TextEncoding::default());
+        assert_eq!(TextEncoding::detect(cut), TextEncoding::new(WINDOWS_1252, false));
+    }
+
+    #[test]
+    fn every_choice_round_trips() {
+        for (label, encoding) in CHOICES {
+            let text = if encoding.encoding == UTF_8 || encoding.is_utf16() {
+                "Grüße, 世界 🦀\n"
+            } else {
+                "plain text\n"
+            };
+            let bytes = encoding.encode(text).unwrap();
+            assert_eq!(Encoding::for_bom(&bytes).is_some(), encoding.bom, "{label}");
+            assert_eq!(encoding.decode(&bytes).as_deref(), Some(text), "{label}");
+            if encoding.bom {
+                assert_eq!(TextEncoding::detect(&bytes), *encoding, "{label}");
+            }
+        }
+    }
+
+    #[test]
+    fn unmappable_characters_fail_to_encode() {
+        assert!(TextEncoding::new(WINDOWS_1252, false).encode("snow ☃").is_err());
+        assert_eq!(TextEncoding::new(WINDOWS_1252, false).encode("café").unwrap(), b"caf\xE9");
+    }
+}
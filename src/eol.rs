@@ -0,0 +1,154 @@
+use std::borrow::Cow;
+use std::fmt;
+
+/// Line-ending style a document is written with. Buffers always hold plain
+/// `\n`; the style is applied only when the text goes back to disk.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+impl fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LineEnding::Lf => "LF",
+            LineEnding::Crlf => "CRLF",
+        })
+    }
+}
+
+impl LineEnding {
+    /// Turn the text back into this style for writing.
+    pub fn apply(self, text: &str) -> Cow<'_, str> {
+        match self {
+            LineEnding::Lf => Cow::Borrowed(text),
+            LineEnding::Crlf => Cow::Owned(text.replace('\n', "\r\n")),
+        }
+    }
+}
+
+/// Strip the `\r` from every `\r\n`, leaving the buffer's plain-`\n` form.
+pub fn normalize(text: &str) -> Cow<'_, str> {
+    if text.contains("\r\n") {
+        Cow::Owned(text.replace("\r\n", "\n"))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// `normalize` for text that arrives in pieces, such as a large file being
+/// decoded, counting each style of line ending on the way.
+#[derive(Default)]
+pub struct Normalizer {
+    crlf: usize,
+    lf: usize,
+    /// The last piece ended in `\r`, which may be half of a `\r\n`.
+    cr_pending: bool,
+}
+
+impl Normalizer {
+    /// Append `piece` to `out` with every `\r\n` turned into `\n`.
+    pub fn push(&mut self, piece: &str, out: &mut String) {
+        if piece.is_empty() {
+            return;
+        }
+        let mut rest = piece;
+        if std::mem::take(&mut self.cr_pending) {
+            if let Some(after) = rest.strip_prefix('\n') {
+                self.crlf += 1;
+                out.push('\n');
+                rest = after;
+            } else {
+                out.push('\r');
+            }
+        }
+        let body = match rest.strip_suffix('\r') {
+            Some(body) => {
+                self.cr_pending = true;
+                body
+            }
+            None => rest,
+        };
+        let mut last = 0;
+        for (idx, _) in body.match_indices('\n') {
+            if idx > 0 && body.as_bytes()[idx - 1] == b'\r' {
+                self.crlf += 1;
+                out.push_str(&body[last..idx - 1]);
+            } else {
+                self.lf += 1;
+                out.push_str(&body[last..idx]);
+            }
+            out.push('\n');
+            last = idx + 1;
+        }
+        out.push_str(&body[last..]);
+    }
+
+    /// Flush what is held back into `out` and report which style the text
+    /// used, and whether it mixed both. Mixed text gets whichever style most
+    /// of its lines use; text without line breaks is treated as LF.
+    pub fn finish(self, out: &mut String) -> (LineEnding, bool) {
+        if self.cr_pending {
+            out.push('\r');
+        }
+        let ending = if self.crlf > self.lf {
+            LineEnding::Crlf
+        } else {
+            LineEnding::Lf
+        };
+        (ending, self.crlf > 0 && self.lf > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run `pieces` through a `Normalizer`, as a chunked file read does.
+    fn normalize_pieces(pieces: &[&str]) -> (String, LineEnding, bool) {
+        let mut normalizer = Normalizer::default();
+        let mut out = String::new();
+        for piece in pieces {
+            normalizer.push(piece, &mut out);
+        }
+        let (ending, mixed) = normalizer.finish(&mut out);
+        (out, ending, mixed)
+    }
+
+    #[test]
+    fn detects_lf_crlf_and_mixed_endings() {
+        assert_eq!(normalize_pieces(&["a\nb\n"]), ("a\nb\n".into(), LineEnding::Lf, false));
+        assert_eq!(normalize_pieces(&["a\r\nb\r\n"]), ("a\nb\n".into(), LineEnding::Crlf, false));
+        assert_eq!(normalize_pieces(&["a\r\nb\r\nc\n"]), ("a\nb\nc\n".into(), LineEnding::Crlf, true));
+        assert_eq!(normalize_pieces(&["a\r\nb\nc\n"]), ("a\nb\nc\n".into(), LineEnding::Lf, true));
+        assert_eq!(normalize_pieces(&["no breaks"]), ("no breaks".into(), LineEnding::Lf, false));
+    }
+
+    #[test]
+    fn crlf_split_across_pieces_is_one_ending() {
+        let split = normalize_pieces(&["a\r", "", "\nb\r", "\n"]);
+        assert_eq!(split, ("a\nb\n".into(), LineEnding::Crlf, false));
+    }
+
+    #[test]
+    fn lone_carriage_returns_are_kept() {
+        assert_eq!(normalize_pieces(&["a\rb", "\r", "\rc\r"]).0, "a\rb\r\rc\r");
+        assert_eq!(normalize("a\rb\r\n"), "a\rb\n");
+    }
+
+    #[test]
+    fn normalize_borrows_text_without_crlf() {
+        assert!(matches!(normalize("a\nb"), Cow::Borrowed(_)));
+        assert_eq!(normalize("a\r\nb\r\n"), "a\nb\n");
+    }
+
+    #[test]
+    fn apply_restores_the_original_endings() {
+        let original = "one\r\ntwo\r\n\r\nthree";
+        let (text, ending, _) = normalize_pieces(&[original]);
+        assert_eq!(ending.apply(&text), original);
+        assert_eq!(LineEnding::Lf.apply("one\ntwo"), "one\ntwo");
+    }
+}
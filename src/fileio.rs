@@ -0,0 +1,453 @@
+use anyhow::{Context, Result};
+use encoding_rs::{DecoderResult, Encoding, WINDOWS_1252};
+use ropey::RopeBuilder;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::SystemTime;
+
+use crate::buffer::Buffer;
+use crate::encoding::TextEncoding;
+use crate::eol::{LineEnding, Normalizer};
+
+/// Files larger than this are refused instead of being read into memory
+/// whole, as `read_text` does for replacing across files.
+pub const MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+/// Documents are read a piece at a time into a rope, so they may be much
+/// larger; this only stops something absurd being opened by mistake.
+pub const MAX_DOCUMENT_SIZE: u64 = 2 * 1024 * 1024 * 1024;
+/// How much of a document is read and decoded at a time.
+const READ_CHUNK: usize = 1024 * 1024;
+/// How much of a file is scanned for NUL bytes when deciding it is binary.
+const BINARY_SNIFF_LEN: usize = 8 * 1024;
+
+/// Why a file could not be opened as text. Callers match on the variant to
+/// decide how to react (retry a typed path, suggest another encoding, ...).
+#[derive(Debug)]
+pub enum LoadError {
+    NotFound(PathBuf),
+    PermissionDenied(PathBuf),
+    InvalidEncoding { path: PathBuf, encoding: &'static str },
+    Binary(PathBuf),
+    TooLarge { path: PathBuf, size: u64, limit: u64 },
+    Io { path: PathBuf, source: io::Error },
+}
+
+impl LoadError {
+    fn from_io(path: &Path, source: io::Error) -> Self {
+        let path = path.to_path_buf();
+        match source.kind() {
+            io::ErrorKind::NotFound => LoadError::NotFound(path),
+            io::ErrorKind::PermissionDenied => LoadError::PermissionDenied(path),
+            _ => LoadError::Io { path, source },
+        }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const MB: u64 = 1024 * 1024;
+        match self {
+            LoadError::NotFound(path) => write!(f, "{}: file not found", path.display()),
+            LoadError::PermissionDenied(path) => {
+                write!(f, "{}: permission denied", path.display())
+            }
+            LoadError::InvalidEncoding { path, encoding } => {
+                write!(f, "{}: not valid {encoding}", path.display())
+            }
+            LoadError::Binary(path) => write!(f, "{}: looks like a binary file", path.display()),
+            LoadError::TooLarge { path, size, limit } => write!(
+                f,
+                "{}: {} MB exceeds the {} MB limit",
+                path.display(),
+                size.div_ceil(MB),
+                limit / MB
+            ),
+            LoadError::Io { path, source } => write!(f, "{}: {source}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// What a file looked like when we last read or wrote it. Only metadata is
+/// kept, so checking it never costs a read of the file itself.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DiskStamp {
+    modified: Option<SystemTime>,
+    size: u64,
+}
+
+impl DiskStamp {
+    fn new(meta: &fs::Metadata) -> Self {
+        Self {
+            modified: meta.modified().ok(),
+            size: meta.len(),
+        }
+    }
+}
+
+/// How a file differs from the [`DiskStamp`] we hold for it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DiskChange {
+    Modified(DiskStamp),
+    Deleted,
+}
+
+/// Compare `path` against `stamp` by size and mtime alone. A touch that left
+/// the bytes alone still counts as a change; reloading it is a no-op.
+pub fn check_disk(path: &Path, stamp: &DiskStamp) -> Option<DiskChange> {
+    let meta = match fs::metadata(path) {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Some(DiskChange::Deleted),
+        // Unreadable for now (e.g. mid-replace); look again on the next poll
+        Err(_) => return None,
+    };
+    let current = DiskStamp::new(&meta);
+    (current != *stamp).then_some(DiskChange::Modified(current))
+}
+
+/// Read `path` as text, detecting its encoding unless `forced` is given.
+/// Anything that would not round-trip is refused rather than mangled.
+pub fn read_text(
+    path: &Path,
+    forced: Option<TextEncoding>,
+) -> Result<(String, TextEncoding, DiskStamp), LoadError> {
+    let meta = fs::metadata(path).map_err(|e| LoadError::from_io(path, e))?;
+    if meta.len() > MAX_FILE_SIZE {
+        return Err(LoadError::TooLarge {
+            path: path.to_path_buf(),
+            size: meta.len(),
+            limit: MAX_FILE_SIZE,
+        });
+    }
+    let bytes = fs::read(path).map_err(|e| LoadError::from_io(path, e))?;
+    let encoding = match forced {
+        // Keep whatever BOM the file really has rather than the choice's default
+        Some(forced) => TextEncoding::new(
+            forced.encoding,
+            encoding_rs::Encoding::for_bom(&bytes).is_some_and(|(bom, _)| bom == forced.encoding),
+        ),
+        None => TextEncoding::detect(&bytes),
+    };
+    // UTF-16 text is full of NULs, so only sniff for binary in byte encodings
+    if !encoding.is_utf16() && bytes[..bytes.len().min(BINARY_SNIFF_LEN)].contains(&0) {
+        return Err(LoadError::Binary(path.to_path_buf()));
+    }
+    match encoding.decode(&bytes) {
+        Some(text) => Ok((text, encoding, DiskStamp::new(&meta))),
+        None => Err(LoadError::InvalidEncoding {
+            path: path.to_path_buf(),
+            encoding: encoding.encoding.name(),
+        }),
+    }
+}
+
+/// A document as read from disk, ready for editing.
+pub struct Document {
+    pub text: Buffer,
+    pub encoding: TextEncoding,
+    pub line_ending: LineEnding,
+    /// The file mixes LF and CRLF.
+    pub mixed_endings: bool,
+    pub stamp: DiskStamp,
+}
+
+/// Read `path` for editing, like `read_text` but a piece at a time and
+/// straight into a rope, with line endings normalized to `\n` on the way.
+pub fn read_document(path: &Path, forced: Option<TextEncoding>) -> Result<Document, LoadError> {
+    let io_error = |e| LoadError::from_io(path, e);
+    let meta = fs::metadata(path).map_err(io_error)?;
+    if meta.len() > MAX_DOCUMENT_SIZE {
+        return Err(LoadError::TooLarge {
+            path: path.to_path_buf(),
+            size: meta.len(),
+            limit: MAX_DOCUMENT_SIZE,
+        });
+    }
+    let mut file = File::open(path).map_err(io_error)?;
+    let mut head = Vec::with_capacity(READ_CHUNK);
+    (&mut file)
+        .take(READ_CHUNK as u64)
+        .read_to_end(&mut head)
+        .map_err(io_error)?;
+    let encoding = match forced {
+        Some(forced) => TextEncoding::new(
+            forced.encoding,
+            Encoding::for_bom(&head).is_some_and(|(bom, _)| bom == forced.encoding),
+        ),
+        None => TextEncoding::detect_start(&head),
+    };
+    if !encoding.is_utf16() && head[..head.len().min(BINARY_SNIFF_LEN)].contains(&0) {
+        return Err(LoadError::Binary(path.to_path_buf()));
+    }
+    let mut decoded = decode_document(&mut file, &meta, &head, encoding).map_err(io_error)?;
+    // The start looked like UTF-8 but the rest is not: like `detect`, take
+    // it as Windows-1252, which decodes anything
+    if decoded.is_none() && forced.is_none() && encoding == TextEncoding::default() {
+        let fallback = TextEncoding::new(WINDOWS_1252, false);
+        decoded = decode_document(&mut file, &meta, &head, fallback).map_err(io_error)?;
+    }
+    decoded.ok_or_else(|| LoadError::InvalidEncoding {
+        path: path.to_path_buf(),
+        encoding: encoding.encoding.name(),
+    })
+}
+
+/// The text of `path` with `\n` line endings, for comparing and previewing.
+/// Goes through [`read_document`], so anything that opens can be diffed.
+pub fn read_normalized(path: &Path) -> Result<String, LoadError> {
+    read_document(path, None).map(|document| document.text.to_string())
+}
+
+/// Decode `head` and the rest of `file` after it as `encoding`, or `None`
+/// if they are not valid in that encoding.
+fn decode_document(
+    file: &mut File,
+    meta: &fs::Metadata,
+    head: &[u8],
+    encoding: TextEncoding,
+) -> io::Result<Option<Document>> {
+    file.seek(SeekFrom::Start(head.len() as u64))?;
+    let bom = match Encoding::for_bom(head) {
+        Some((found, len)) if found == encoding.encoding => len,
+        _ => 0,
+    };
+    let mut decoder = encoding.encoding.new_decoder_without_bom_handling();
+    let mut normalizer = Normalizer::default();
+    let mut rope = RopeBuilder::new();
+    let (mut decoded, mut normalized) = (String::new(), String::new());
+    let mut chunk = head[bom..].to_vec();
+    let mut next = Vec::with_capacity(READ_CHUNK);
+    loop {
+        next.clear();
+        (&mut *file).take(READ_CHUNK as u64).read_to_end(&mut next)?;
+        let last = next.is_empty();
+        decoded.clear();
+        let mut src = &chunk[..];
+        loop {
+            let needed = decoder
+                .max_utf8_buffer_length_without_replacement(src.len())
+                .unwrap_or(src.len() * 3);
+            decoded.reserve(needed);
+            let (result, read) = decoder.decode_to_string_without_replacement(src, &mut decoded, last);
+            src = &src[read..];
+            match result {
+                DecoderResult::InputEmpty => break,
+                DecoderResult::OutputFull => {}
+                DecoderResult::Malformed(..) => return Ok(None),
+            }
+        }
+        normalized.clear();
+        normalizer.push(&decoded, &mut normalized);
+        rope.append(&normalized);
+        if last {
+            break;
+        }
+        std::mem::swap(&mut chunk, &mut next);
+    }
+    normalized.clear();
+    let (line_ending, mixed_endings) = normalizer.finish(&mut normalized);
+    rope.append(&normalized);
+    Ok(Some(Document {
+        text: Buffer::from(rope.finish()),
+        encoding,
+        line_ending,
+        mixed_endings,
+        stamp: DiskStamp::new(meta),
+    }))
+}
+
+/// Per-user directory for nust's own state (session, recovery, ...), e.g.
+/// `~/.local/state/nust` on Linux or `%LOCALAPPDATA%\nust` on Windows.
+pub fn state_dir() -> Option<PathBuf> {
+    dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .map(|dir| dir.join("nust"))
+}
+
+/// Copy of the previous contents kept next to a file when it is overwritten.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackupMode {
+    #[default]
+    Off,
+    /// `notes.md~`
+    Tilde,
+    /// `notes.md.bak`
+    Bak,
+}
+
+impl BackupMode {
+    pub fn next(self) -> Self {
+        match self {
+            BackupMode::Off => BackupMode::Tilde,
+            BackupMode::Tilde => BackupMode::Bak,
+            BackupMode::Bak => BackupMode::Off,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            BackupMode::Off => "off",
+            BackupMode::Tilde => "file~",
+            BackupMode::Bak => "file.bak",
+        }
+    }
+
+    fn backup_path(self, path: &Path) -> Option<PathBuf> {
+        let suffix = match self {
+            BackupMode::Off => return None,
+            BackupMode::Tilde => "~",
+            BackupMode::Bak => ".bak",
+        };
+        let mut name = path.file_name()?.to_os_string();
+        name.push(suffix);
+        Some(path.with_file_name(name))
+    }
+}
+
+static TEMP_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+/// Replace `path` with `bytes` without ever leaving it half-written.
+///
+/// The data goes to a temp file in the same directory, is fsynced, then
+/// renamed over the target, so a crash or full disk leaves the original
+/// untouched. The original's permissions are carried over, and symlinks are
+/// followed so the link itself survives. Returns the stamp of the new file.
+pub fn write_atomic(path: &Path, bytes: &[u8], backup: BackupMode) -> Result<DiskStamp> {
+    let target = match fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_symlink() => fs::canonicalize(path)
+            .with_context(|| format!("resolving symlink {}", path.display()))?,
+        _ => path.to_path_buf(),
+    };
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let file_name = target
+        .file_name()
+        .with_context(|| format!("{} is not a file path", target.display()))?;
+    let original = fs::metadata(&target).ok();
+
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(file_name);
+    tmp_name.push(format!(
+        ".nust-tmp-{}-{}",
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let tmp_path = dir.join(tmp_name);
+
+    let result = (|| -> Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp_path)
+            .with_context(|| format!("creating temp file in {}", dir.display()))?;
+        file.write_all(bytes)
+            .with_context(|| format!("writing {}", tmp_path.display()))?;
+        file.sync_all()
+            .with_context(|| format!("flushing {}", tmp_path.display()))?;
+        drop(file);
+
+        if let Some(meta) = &original {
+            fs::set_permissions(&tmp_path, meta.permissions())
+                .with_context(|| format!("copying permissions of {}", target.display()))?;
+            if let Some(backup_path) = backup.backup_path(&target) {
+                fs::copy(&target, &backup_path)
+                    .with_context(|| format!("writing backup {}", backup_path.display()))?;
+            }
+        }
+
+        fs::rename(&tmp_path, &target)
+            .with_context(|| format!("replacing {}", target.display()))?;
+        Ok(())
+    })();
+
+    if let Err(e) = result {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e);
+    }
+
+    // Persist the rename itself; not every platform lets us open a directory.
+    if let Ok(dir) = File::open(&dir) {
+        let _ = dir.sync_all();
+    }
+    let meta = fs::metadata(&target)
+        .with_context(|| format!("reading back {}", target.display()))?;
+    Ok(DiskStamp::new(&meta))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn read_document_normalizes_line_endings_across_chunks() {
+        // Put a CRLF right across the first chunk boundary
+        let mut text = "a".repeat(READ_CHUNK - 1);
+        text.push_str("\r\nb\r\nc\n");
+        let dir = TempDir::new("fileio-chunks");
+        let file = dir.file("chunks.txt", &text);
+        let document = read_document(&file, None).unwrap();
+        assert_eq!(document.text.to_string(), text.replace("\r\n", "\n"));
+        assert_eq!(document.line_ending, LineEnding::Crlf);
+        assert!(document.mixed_endings);
+        assert_eq!(document.encoding, TextEncoding::default());
+    }
+
+    #[test]
+    fn read_document_falls_back_when_utf8_breaks_late() {
+        let mut bytes = "é".repeat(READ_CHUNK).into_bytes();
+        bytes.push(0xE9);
+        let dir = TempDir::new("fileio-latin1");
+        let file = dir.file("latin1.txt", &bytes);
+        let document = read_document(&file, None).unwrap();
+        assert_eq!(document.encoding.encoding, WINDOWS_1252);
+        assert!(document.text.to_string().ends_with('é'));
+        assert!(read_document(&file, Some(TextEncoding::default())).is_err());
+    }
+
+    #[test]
+    fn read_document_stamp_matches_a_write() {
+        let dir = TempDir::new("fileio-stamp");
+        let file = dir.file("stamp.txt", b"");
+        let stamp = write_atomic(&file, b"hello\r\n", BackupMode::Off).unwrap();
+        let document = read_document(&file, None).unwrap();
+        assert_eq!(document.stamp, stamp);
+        assert_eq!(document.text.to_string(), "hello\n");
+        assert!(check_disk(&file, &stamp).is_none());
+    }
+
+    #[test]
+    fn check_disk_sees_growth_and_deletion() {
+        let dir = TempDir::new("fileio-grow");
+        let file = dir.file("grow.log", b"");
+        let stamp = write_atomic(&file, b"one\n", BackupMode::Off).unwrap();
+        fs::OpenOptions::new().append(true).open(&file).unwrap().write_all(b"two\n").unwrap();
+        let Some(DiskChange::Modified(newer)) = check_disk(&file, &stamp) else {
+            panic!("growth not noticed");
+        };
+        assert!(check_disk(&file, &newer).is_none());
+        fs::remove_file(&file).unwrap();
+        assert_eq!(check_disk(&file, &newer), Some(DiskChange::Deleted));
+    }
+
+    #[test]
+    fn read_document_refuses_binary() {
+        let dir = TempDir::new("fileio-binary");
+        let file = dir.file("binary.bin", b"some text with a\0 NUL in it");
+        assert!(matches!(read_document(&file, None), Err(LoadError::Binary(_))));
+    }
+}
@@ -0,0 +1,442 @@
+use eframe::egui;
+use regex::{Regex, RegexBuilder};
+use std::ops::Range;
+
+use crate::buffer::Buffer;
+
+/// How the find bar reads its query.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct FindOptions {
+    /// Treat the query as a regular expression; replacements may use `$1`.
+    pub regex: bool,
+    pub case_sensitive: bool,
+    /// Only match whole words.
+    pub whole_word: bool,
+}
+
+/// What the find bar asks its pane to do.
+#[derive(Clone, Copy)]
+pub enum FindCommand {
+    Next,
+    Previous,
+    Replace,
+    ReplaceAll,
+    Close,
+}
+
+/// Compile `query` under `options`; plain queries match literally.
+pub fn build_regex(query: &str, options: FindOptions) -> Result<Regex, regex::Error> {
+    let mut pattern = if options.regex {
+        query.to_owned()
+    } else {
+        regex::escape(query)
+    };
+    if options.whole_word {
+        pattern = if options.regex {
+            format!(r"\b(?:{pattern})\b")
+        } else {
+            // `\b` next to punctuation would demand a word character there
+            let word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+            let start = if word(query.chars().next()) { r"\b" } else { "" };
+            let end = if word(query.chars().last()) { r"\b" } else { "" };
+            format!("{start}{pattern}{end}")
+        };
+    }
+    RegexBuilder::new(&pattern)
+        .case_insensitive(!options.case_sensitive)
+        .multi_line(true)
+        .build()
+}
+
+/// What the match of `regex` at `range` in `text` becomes: with capture
+/// groups (`$1`, `${name}`) expanded if `groups`, else `replacement` as is.
+pub fn expand(regex: &Regex, text: &str, range: Range<usize>, replacement: &str, groups: bool) -> String {
+    if !groups {
+        return replacement.to_owned();
+    }
+    let mut expanded = String::new();
+    if let Some(caps) = regex.captures_at(text, range.start) {
+        caps.expand(replacement, &mut expanded);
+    }
+    expanded
+}
+
+/// A compiled query and where the current match is.
+struct Matcher {
+    regex: Regex,
+    anchor: usize,
+}
+
+impl Matcher {
+    /// Byte ranges of every non-empty match in `text`.
+    fn matches(&self, text: &str) -> Vec<Range<usize>> {
+        self.regex
+            .find_iter(text)
+            .filter(|m| !m.is_empty())
+            .map(|m| m.range())
+            .collect()
+    }
+
+    /// The first match at or after the anchor, wrapping to the first one.
+    fn current(&self, matches: &[Range<usize>]) -> Option<usize> {
+        matches
+            .iter()
+            .position(|m| m.start >= self.anchor)
+            .or((!matches.is_empty()).then_some(0))
+    }
+}
+
+/// Matches of one query in one version of a document.
+struct Found {
+    version: u64,
+    query: String,
+    options: FindOptions,
+    matches: Vec<Range<usize>>,
+}
+
+/// The find (and replace) bar of one pane, searching its active document.
+#[derive(Default)]
+pub struct FindBar {
+    pub query: String,
+    pub replacement: String,
+    pub options: FindOptions,
+    pub show_replace: bool,
+    /// Take keyboard focus, and pick up the editor's selection, next frame.
+    pub focus: bool,
+    /// Select and scroll to the current match next frame.
+    pub reveal: bool,
+    /// One of the bar's fields has keyboard focus.
+    pub has_focus: bool,
+    /// Byte offset the current match is the first one at or after.
+    anchor: usize,
+    /// The query and options last compiled, with the outcome.
+    compiled: Option<(String, FindOptions, Result<Regex, String>)>,
+    /// The last search's matches, kept until the text or query changes so
+    /// a large document is not searched again every frame.
+    found: Option<Found>,
+    /// From the last search, for the status bar.
+    count: usize,
+    current: Option<usize>,
+    replaced: Option<usize>,
+}
+
+impl FindBar {
+    /// Start from the editor's state: search from `cursor` (a byte offset)
+    /// and, if a single line is selected, look for that.
+    pub fn seed(&mut self, selection: Option<&str>, cursor: usize) {
+        self.anchor = cursor;
+        if let Some(selected) = selection.filter(|s| !s.is_empty() && !s.contains('\n')) {
+            self.query = if self.options.regex {
+                regex::escape(selected)
+            } else {
+                selected.to_owned()
+            };
+            self.changed();
+        }
+    }
+
+    /// The compiled query, or `None` while it is empty or invalid.
+    fn matcher(&mut self) -> Option<Matcher> {
+        if self.query.is_empty() {
+            return None;
+        }
+        let stale = self
+            .compiled
+            .as_ref()
+            .is_none_or(|(query, options, _)| *query != self.query || *options != self.options);
+        if stale {
+            let result = build_regex(&self.query, self.options).map_err(|e| e.to_string());
+            self.compiled = Some((self.query.clone(), self.options, result));
+        }
+        let (_, _, result) = self.compiled.as_ref()?;
+        let regex = result.as_ref().ok()?.clone();
+        Some(Matcher {
+            regex,
+            anchor: self.anchor,
+        })
+    }
+
+    fn error(&self) -> Option<&str> {
+        match &self.compiled {
+            Some((query, _, Err(e))) if *query == self.query => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Every match in `text` (as byte ranges) and which one is current,
+    /// remembered for `summary`.
+    pub fn search(&mut self, text: &Buffer) -> (&[Range<usize>], Option<usize>) {
+        let Some(matcher) = self.matcher() else {
+            self.count = 0;
+            self.current = None;
+            self.found = None;
+            return (&[], None);
+        };
+        let stale = self.found.as_ref().is_none_or(|found| {
+            found.version != text.version() || found.query != self.query || found.options != self.options
+        });
+        if stale {
+            self.found = Some(Found {
+                version: text.version(),
+                query: self.query.clone(),
+                options: self.options,
+                matches: matcher.matches(&text.to_string()),
+            });
+        }
+        let matches = self.found.as_ref().map_or(&[][..], |found| &found.matches);
+        self.count = matches.len();
+        self.current = matcher.current(matches);
+        (matches, self.current)
+    }
+
+    /// Move to the next (or previous) match, wrapping around.
+    pub fn step(&mut self, text: &Buffer, forward: bool) {
+        let (matches, Some(current)) = self.search(text) else {
+            return;
+        };
+        self.anchor = if forward {
+            matches[current].end
+        } else {
+            matches[(current + matches.len() - 1) % matches.len()].start
+        };
+        self.reveal = true;
+        self.replaced = None;
+    }
+
+    fn expansion(&self, regex: &Regex, text: &str, range: Range<usize>) -> String {
+        expand(regex, text, range, &self.replacement, self.options.regex)
+    }
+
+    /// The current match (in chars) and what replaces it, moving on to the
+    /// next match.
+    pub fn replace_current(&mut self, text: &Buffer) -> Option<(Range<usize>, String)> {
+        let matcher = self.matcher()?;
+        let flat = text.to_string();
+        let matches = matcher.matches(&flat);
+        let range = matches.get(matcher.current(&matches)?)?.clone();
+        let with = self.expansion(&matcher.regex, &flat, range.clone());
+        // Continue after the replacement so it is not matched again
+        self.anchor = range.start + with.len();
+        self.reveal = true;
+        self.replaced = None;
+        Some((text.byte_to_char(range.start)..text.byte_to_char(range.end), with))
+    }
+
+    /// `text` with every match replaced, or `None` if nothing matched.
+    pub fn replace_all(&mut self, text: &Buffer) -> Option<String> {
+        let matcher = self.matcher()?;
+        let text = text.to_string();
+        let mut replaced = String::with_capacity(text.len());
+        let mut last = 0;
+        let mut count = 0;
+        for range in matcher.matches(&text) {
+            replaced.push_str(&text[last..range.start]);
+            replaced.push_str(&self.expansion(&matcher.regex, &text, range.clone()));
+            last = range.end;
+            count += 1;
+        }
+        if count == 0 {
+            return None;
+        }
+        replaced.push_str(&text[last..]);
+        self.replaced = Some(count);
+        Some(replaced)
+    }
+
+    /// The query or options changed: search again from the same place.
+    fn changed(&mut self) {
+        self.reveal = true;
+        self.replaced = None;
+    }
+
+    /// Match counts for the status bar; `None` while the query is empty.
+    pub fn summary(&self) -> Option<String> {
+        if let Some(count) = self.replaced {
+            return Some(format!("Replaced {count} {}", plural(count)));
+        }
+        if self.query.is_empty() {
+            return None;
+        }
+        if self.error().is_some() {
+            return Some("Invalid regex".into());
+        }
+        Some(match self.current {
+            Some(idx) => format!("Match {} of {}", idx + 1, self.count),
+            None => "No matches".into(),
+        })
+    }
+
+    /// Draw the bar. Enter finds the next match (Shift+Enter the previous
+    /// one) or, in the replace field, replaces the current one; Escape closes.
+    pub fn show(&mut self, ui: &mut egui::Ui) -> Option<FindCommand> {
+        let (enter, shift, escape) = ui.input(|i| {
+            (
+                i.key_pressed(egui::Key::Enter),
+                i.modifiers.shift,
+                i.key_pressed(egui::Key::Escape),
+            )
+        });
+        let mut command = None;
+        ui.horizontal(|ui| {
+            let query = ui.add(
+                egui::TextEdit::singleline(&mut self.query)
+                    .hint_text("Find")
+                    .desired_width(220.0),
+            );
+            if std::mem::take(&mut self.focus) {
+                query.request_focus();
+                // Select the query so typing replaces it
+                let mut state = egui::TextEdit::load_state(ui.ctx(), query.id).unwrap_or_default();
+                let all = egui::text::CCursorRange::two(
+                    egui::text::CCursor::new(0),
+                    egui::text::CCursor::new(self.query.chars().count()),
+                );
+                state.cursor.set_char_range(Some(all));
+                state.store(ui.ctx(), query.id);
+            }
+            if query.changed() {
+                self.changed();
+            }
+            if query.lost_focus() && enter {
+                command = Some(if shift { FindCommand::Previous } else { FindCommand::Next });
+                query.request_focus();
+            }
+            if (query.has_focus() || query.lost_focus()) && escape {
+                command = Some(FindCommand::Close);
+            }
+            self.has_focus = query.has_focus();
+
+            if option_toggles(ui, &mut self.options) {
+                self.changed();
+            }
+            if ui.button("↑").on_hover_text("Previous match (Shift+Enter)").clicked() {
+                command = Some(FindCommand::Previous);
+            }
+            if ui.button("↓").on_hover_text("Next match (Enter)").clicked() {
+                command = Some(FindCommand::Next);
+            }
+            if let Some(error) = self.error() {
+                ui.colored_label(ui.visuals().error_fg_color, "Invalid regex")
+                    .on_hover_text(error);
+            }
+            if ui.small_button("×").on_hover_text("Close (Escape)").clicked() {
+                command = Some(FindCommand::Close);
+            }
+        });
+        if self.show_replace {
+            ui.horizontal(|ui| {
+                let hint = if self.options.regex {
+                    "Replace ($1 for groups)"
+                } else {
+                    "Replace"
+                };
+                let replace = ui.add(
+                    egui::TextEdit::singleline(&mut self.replacement)
+                        .hint_text(hint)
+                        .desired_width(220.0),
+                );
+                if replace.lost_focus() && enter {
+                    command = Some(FindCommand::Replace);
+                    replace.request_focus();
+                }
+                if (replace.has_focus() || replace.lost_focus()) && escape {
+                    command = Some(FindCommand::Close);
+                }
+                self.has_focus |= replace.has_focus();
+                if ui.button("Replace").clicked() {
+                    command = Some(FindCommand::Replace);
+                }
+                if ui.button("Replace All").clicked() {
+                    command = Some(FindCommand::ReplaceAll);
+                }
+            });
+        }
+        command
+    }
+}
+
+/// The match case / whole word / regex buttons. Returns whether one changed.
+pub fn option_toggles(ui: &mut egui::Ui, options: &mut FindOptions) -> bool {
+    let mut toggled = false;
+    toggled |= ui
+        .toggle_value(&mut options.case_sensitive, "Aa")
+        .on_hover_text("Match case")
+        .changed();
+    toggled |= ui
+        .toggle_value(&mut options.whole_word, "ab")
+        .on_hover_text("Whole word")
+        .changed();
+    toggled |= ui
+        .toggle_value(&mut options.regex, ".*")
+        .on_hover_text("Regular expression")
+        .changed();
+    toggled
+}
+
+pub fn plural(count: usize) -> &'static str {
+    if count == 1 { "match" } else { "matches" }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Start and end of every match of `query` in `text`.
+    fn find_all(query: &str, options: FindOptions, text: &str) -> Vec<(usize, usize)> {
+        let regex = build_regex(query, options).unwrap();
+        regex.find_iter(text).map(|m| (m.start(), m.end())).collect()
+    }
+
+    const WHOLE_WORD: FindOptions = FindOptions {
+        regex: false,
+        case_sensitive: false,
+        whole_word: true,
+    };
+
+    #[test]
+    fn plain_queries_match_literally() {
+        let options = FindOptions::default();
+        assert_eq!(find_all("a.b", options, "a.b axb A.B"), [(0, 3), (8, 11)]);
+        let case_sensitive = FindOptions {
+            case_sensitive: true,
+            ..options
+        };
+        assert_eq!(find_all("a.b", case_sensitive, "a.b A.B"), [(0, 3)]);
+    }
+
+    #[test]
+    fn whole_word_skips_parts_of_words() {
+        assert_eq!(find_all("cat", WHOLE_WORD, "cat concat cats cat_ (cat)"), [(0, 3), (22, 25)]);
+    }
+
+    #[test]
+    fn whole_word_with_punctuation_at_either_end() {
+        // No `\b` is demanded next to the punctuation itself
+        assert_eq!(find_all(".len", WHOLE_WORD, "x.len() x.length"), [(1, 5)]);
+        assert_eq!(find_all("foo(", WHOLE_WORD, "foo(1) barfoo(2)"), [(0, 4)]);
+        assert_eq!(find_all("->", WHOLE_WORD, "a -> b->c"), [(2, 4), (6, 8)]);
+    }
+
+    #[test]
+    fn whole_word_wraps_regex_alternations() {
+        let options = FindOptions {
+            regex: true,
+            ..WHOLE_WORD
+        };
+        assert_eq!(find_all("ab|cd", options, "ab abc cd xcd"), [(0, 2), (7, 9)]);
+    }
+
+    #[test]
+    fn expand_fills_in_capture_groups() {
+        let options = FindOptions {
+            regex: true,
+            ..FindOptions::default()
+        };
+        let regex = build_regex(r"(?<key>\w+)=(\w+)", options).unwrap();
+        let text = "a=1 bb=22";
+        assert_eq!(expand(&regex, text, 4..9, "$2=${key}", true), "22=bb");
+        assert_eq!(expand(&regex, text, 0..3, "${2}0", true), "10");
+        // Plain replacements keep the dollar signs
+        assert_eq!(expand(&regex, text, 0..3, "$2=${key}", false), "$2=${key}");
+    }
+}
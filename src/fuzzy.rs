@@ -0,0 +1,219 @@
+use eframe::egui::{self, text::LayoutJob};
+
+/// Points for each matched character.
+const MATCH: i32 = 1;
+/// Extra points when a match starts a word ("s" in "Quick Save").
+const WORD_START: i32 = 8;
+/// Extra points when a match is the very first character.
+const PREFIX: i32 = 4;
+/// Extra points when a match directly follows the previous one.
+const CONSECUTIVE: i32 = 5;
+
+/// How well a query matched, and which characters of the candidate it used.
+pub struct FuzzyMatch {
+    pub score: i32,
+    /// Char (not byte) indices into the candidate, ascending.
+    pub positions: Vec<usize>,
+}
+
+fn word_start(chars: &[char], idx: usize) -> bool {
+    let Some(&prev) = idx.checked_sub(1).and_then(|prev| chars.get(prev)) else {
+        return true;
+    };
+    let cur = chars[idx];
+    (!prev.is_alphanumeric() && cur.is_alphanumeric()) || (prev.is_lowercase() && cur.is_uppercase())
+}
+
+/// Match `query` as a case-insensitive subsequence of `candidate`, picking
+/// the alignment with the best score. Whitespace in the query is ignored.
+/// `None` when some query character has no place to go.
+pub fn fuzzy_match(query: &str, candidate: &str) -> Option<FuzzyMatch> {
+    let query: Vec<char> = query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect();
+    let chars: Vec<char> = candidate.chars().collect();
+    let lower: Vec<char> = chars
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+    let (m, n) = (query.len(), chars.len());
+    if m == 0 {
+        return Some(FuzzyMatch {
+            score: 0,
+            positions: Vec::new(),
+        });
+    }
+    if m > n {
+        return None;
+    }
+
+    let bonus = |j: usize| {
+        MATCH + if word_start(&chars, j) { WORD_START } else { 0 } + if j == 0 { PREFIX } else { 0 }
+    };
+    // score[i][j]: best total with query[..=i] matched and query[i] at chars[j].
+    // from[i][j]: where query[i - 1] sat in that alignment.
+    let mut score = vec![vec![None::<i32>; n]; m];
+    let mut from = vec![vec![0usize; n]; m];
+    for j in 0..n {
+        if lower[j] == query[0] {
+            // Slight preference for matches near the start
+            score[0][j] = Some(bonus(j) - (j as i32).min(5));
+        }
+    }
+    for i in 1..m {
+        // Best score[i - 1][k] + k + 1 over k < j - 1, so a gap of
+        // (j - k - 1) characters costs one point each.
+        let mut gap_best: Option<(i32, usize)> = None;
+        for j in 1..n {
+            if j >= 2
+                && let Some(prev) = score[i - 1][j - 2]
+            {
+                let candidate = prev + (j - 2) as i32 + 1;
+                if gap_best.is_none_or(|(best, _)| candidate > best) {
+                    gap_best = Some((candidate, j - 2));
+                }
+            }
+            if lower[j] != query[i] {
+                continue;
+            }
+            let consecutive = score[i - 1][j - 1].map(|prev| (prev + CONSECUTIVE, j - 1));
+            let gapped = gap_best.map(|(best, k)| (best - j as i32, k));
+            let best = match (consecutive, gapped) {
+                (Some(a), Some(b)) => Some(if b.0 > a.0 { b } else { a }),
+                (a, b) => a.or(b),
+            };
+            if let Some((total, k)) = best {
+                score[i][j] = Some(total + bonus(j));
+                from[i][j] = k;
+            }
+        }
+    }
+
+    let (mut j, total) = score[m - 1]
+        .iter()
+        .enumerate()
+        .filter_map(|(j, total)| total.map(|total| (j, total)))
+        .max_by_key(|&(j, total)| (total, std::cmp::Reverse(j)))?;
+    let mut positions = vec![j; m];
+    for i in (1..m).rev() {
+        j = from[i][j];
+        positions[i - 1] = j;
+    }
+    Some(FuzzyMatch {
+        score: total,
+        positions,
+    })
+}
+
+/// `text` with the characters at `positions` drawn in the strong text colour
+/// and underlined, for listing match results.
+pub fn highlight(ui: &egui::Ui, text: &str, positions: &[usize]) -> LayoutJob {
+    let style = ui.style();
+    let font_id = egui::TextStyle::Button.resolve(style);
+    let normal = egui::TextFormat {
+        font_id: font_id.clone(),
+        color: style.visuals.text_color(),
+        ..Default::default()
+    };
+    let matched = egui::TextFormat {
+        font_id,
+        color: style.visuals.strong_text_color(),
+        underline: egui::Stroke::new(1.0, style.visuals.strong_text_color()),
+        ..Default::default()
+    };
+    let mut job = LayoutJob::default();
+    let mut buf = [0u8; 4];
+    for (idx, c) in text.chars().enumerate() {
+        let format = if positions.contains(&idx) { &matched } else { &normal };
+        job.append(c.encode_utf8(&mut buf), 0.0, format.clone());
+    }
+    job
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(query: &str, candidate: &str) -> Option<i32> {
+        fuzzy_match(query, candidate).map(|found| found.score)
+    }
+
+    fn positions(query: &str, candidate: &str) -> Vec<usize> {
+        fuzzy_match(query, candidate).unwrap().positions
+    }
+
+    #[test]
+    fn word_starts_beat_incidental_matches() {
+        assert_eq!(positions("qs", "Quick Save"), [0, 6]);
+        assert_eq!(score("qs", "Quick Save"), Some(17));
+        assert_eq!(positions("qs", "requests"), [2, 5]);
+        assert_eq!(score("qs", "requests"), Some(-2));
+        // Query characters must appear in order
+        assert_eq!(score("qs", "Save Quick"), None);
+    }
+
+    #[test]
+    fn camel_case_humps_are_word_starts() {
+        assert_eq!(positions("fm", "fuzzyMatch"), [0, 5]);
+        assert!(score("fm", "fuzzyMatch") > score("fm", "fuzzymatch"));
+    }
+
+    #[test]
+    fn prefix_and_early_matches_score_higher() {
+        assert_eq!(score("s", "save"), Some(MATCH + WORD_START + PREFIX));
+        assert_eq!(score("s", "a save"), Some(MATCH + WORD_START - 2));
+    }
+
+    #[test]
+    fn gaps_cost_a_point_per_character() {
+        let consecutive = score("ab", "abx").unwrap();
+        let one_gap = score("ab", "axb").unwrap();
+        let two_gap = score("ab", "axxb").unwrap();
+        assert_eq!(consecutive, 2 * MATCH + WORD_START + PREFIX + CONSECUTIVE);
+        assert_eq!(one_gap - two_gap, 1);
+        assert!(consecutive > one_gap);
+    }
+
+    #[test]
+    fn ties_take_the_earliest_alignment() {
+        // Both "a"s are word starts far enough in to lose the same amount
+        assert_eq!(positions("a", "xxxxx a a"), [6]);
+    }
+
+    #[test]
+    fn ignores_case_and_query_whitespace() {
+        assert_eq!(positions("Q S", "quick save"), [0, 6]);
+        assert_eq!(score("", "anything"), Some(0));
+        assert_eq!(score("longer", "long"), None);
+    }
+
+    #[test]
+    fn palette_ranks_best_first_and_keeps_ties_in_order() {
+        let candidate = |label: &str, boost| crate::palette::Candidate {
+            label: label.to_owned(),
+            alias: None,
+            detail: None,
+            boost,
+            target: label.to_owned(),
+        };
+        let ranked = crate::palette::rank(
+            "qs",
+            [
+                candidate("requests", 0),
+                candidate("Quick Save", 0),
+                candidate("Quick Split", 0),
+                candidate("quests", 20),
+                candidate("Open", 0),
+            ],
+        );
+        let order: Vec<&str> = ranked.iter().map(|(_, target)| target.as_str()).collect();
+        assert_eq!(order, ["quests", "Quick Save", "Quick Split", "requests"]);
+    }
+
+    #[test]
+    fn positions_count_chars_not_bytes() {
+        assert_eq!(positions("éb", "café bar"), [3, 5]);
+    }
+}
@@ -0,0 +1,335 @@
+use anyhow::{Context, Result};
+use eframe::egui;
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::SystemTime;
+
+use crate::buffer::Buffer;
+use crate::diff::DiffView;
+use crate::fileio::{self, BackupMode};
+use crate::quicksave::format_age;
+
+/// Oldest revisions beyond this many per file are dropped.
+const MAX_REVISIONS: usize = 200;
+
+/// One saved version of a file in the local history store.
+pub struct Revision {
+    pub file: PathBuf,
+    /// Milliseconds since the Unix epoch.
+    pub saved_at: u64,
+    /// Length of the text in bytes, before compression.
+    pub size: u64,
+    /// Short hex digest of the text, so a save that changed nothing can be
+    /// spotted without decompressing anything. Missing from older names.
+    digest: Option<String>,
+}
+
+impl Revision {
+    pub fn read(&self) -> Result<String> {
+        let compressed =
+            fs::read(&self.file).with_context(|| format!("reading {}", self.file.display()))?;
+        let mut text = String::new();
+        GzDecoder::new(compressed.as_slice())
+            .read_to_string(&mut text)
+            .with_context(|| format!("decompressing {}", self.file.display()))?;
+        Ok(text)
+    }
+}
+
+/// Directory holding the revisions of `path`, named after a hash of its
+/// canonical form so relative and symlinked spellings of a file agree.
+fn store_dir(path: &Path) -> Option<PathBuf> {
+    let canonical = fs::canonicalize(path).ok()?;
+    let digest = Sha256::digest(canonical.to_string_lossy().as_bytes());
+    let key: String = digest[..12].iter().map(|byte| format!("{byte:02x}")).collect();
+    Some(fileio::state_dir()?.join("history").join(key))
+}
+
+/// Hex of the first bytes of the text's SHA-256, as kept in revision names.
+fn digest(text: &str) -> String {
+    Sha256::digest(text.as_bytes())[..8].iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Revisions of `path`, newest first.
+pub fn revisions(path: &Path) -> Vec<Revision> {
+    store_dir(path).map_or_else(Vec::new, |dir| revisions_in(&dir))
+}
+
+/// The revisions in one store directory, named `<saved_at>-<size>-<digest>.gz`.
+fn revisions_in(dir: &Path) -> Vec<Revision> {
+    let mut found: Vec<Revision> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            let mut parts = name.to_str()?.strip_suffix(".gz")?.splitn(3, '-');
+            Some(Revision {
+                file: entry.path(),
+                saved_at: parts.next()?.parse().ok()?,
+                size: parts.next()?.parse().ok()?,
+                digest: parts.next().map(str::to_owned),
+            })
+        })
+        .collect();
+    found.sort_by_key(|revision| std::cmp::Reverse(revision.saved_at));
+    found
+}
+
+/// Store `text` as the newest revision of the file just saved at `path`,
+/// unless it matches the latest one already there.
+fn record(path: &Path, text: &str) -> Result<()> {
+    let dir = store_dir(path).context("no history directory for this file")?;
+    record_in(&dir, path, text)
+}
+
+fn record_in(dir: &Path, path: &Path, text: &str) -> Result<()> {
+    let existing = revisions_in(dir);
+    let digest = digest(text);
+    if existing.first().and_then(|latest| latest.digest.as_ref()) == Some(&digest) {
+        return Ok(());
+    }
+    fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    // Note which file this directory belongs to, for anyone browsing the store
+    let _ = fs::write(dir.join("path.txt"), path.to_string_lossy().as_bytes());
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(text.as_bytes())?;
+    let compressed = encoder.finish()?;
+    let saved_at = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64);
+    // Two saves in the same millisecond must still sort in order
+    let saved_at = saved_at.max(existing.first().map_or(0, |latest| latest.saved_at + 1));
+    let file = dir.join(format!("{saved_at}-{}-{digest}.gz", text.len()));
+    fileio::write_atomic(&file, &compressed, BackupMode::Off)?;
+
+    for old in existing.iter().skip(MAX_REVISIONS - 1) {
+        let _ = fs::remove_file(&old.file);
+    }
+    Ok(())
+}
+
+enum Job {
+    /// Record `text` (cheap to clone, costly to copy out) for `path`.
+    Record { path: PathBuf, text: Buffer },
+    /// Answer once every job queued before it is done.
+    Flush(Sender<()>),
+}
+
+/// Queue of the thread that records revisions, started by the first save.
+static JOBS: OnceLock<Sender<Job>> = OnceLock::new();
+
+fn jobs() -> &'static Sender<Job> {
+    JOBS.get_or_init(|| {
+        let (jobs, rx) = mpsc::channel();
+        // If the thread cannot start, `rx` is dropped and `record_later`
+        // falls back to recording on the caller's thread
+        let _ = std::thread::Builder::new()
+            .name("file-history".into())
+            .spawn(move || run_worker(rx));
+        jobs
+    })
+}
+
+/// The last thing that went wrong recording history, until the app shows it.
+static FAILURE: Mutex<Option<String>> = Mutex::new(None);
+
+fn report(path: &Path, e: &anyhow::Error) {
+    if let Ok(mut failure) = FAILURE.lock() {
+        *failure = Some(format!("File history for {}: {e:#}", path.display()));
+    }
+}
+
+/// What last went wrong recording history, once, for the status bar.
+pub fn take_failure() -> Option<String> {
+    FAILURE.lock().ok()?.take()
+}
+
+fn run_worker(jobs: Receiver<Job>) {
+    for job in jobs {
+        match job {
+            Job::Record { path, text } => {
+                if let Err(e) = record(&path, &text.to_string()) {
+                    report(&path, &e);
+                }
+            }
+            Job::Flush(done) => {
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+/// `record`, on the history thread, so saving (and autosaving) never waits
+/// on hashing and compressing revisions.
+pub fn record_later(path: &Path, text: &Buffer) {
+    let job = Job::Record {
+        path: path.to_path_buf(),
+        text: text.clone(),
+    };
+    if let Err(mpsc::SendError(Job::Record { path, text })) = jobs().send(job)
+        && let Err(e) = record(&path, &text.to_string())
+    {
+        report(&path, &e);
+    }
+}
+
+/// Wait for revisions still queued to be written, e.g. before exiting.
+pub fn flush() {
+    let Some(jobs) = JOBS.get() else {
+        return;
+    };
+    let (done, finished) = mpsc::channel();
+    if jobs.send(Job::Flush(done)).is_ok() {
+        let _ = finished.recv();
+    }
+}
+
+/// The "File History" window for one document.
+pub struct HistoryPanel {
+    pub doc_id: u64,
+    pub title: String,
+    pub revisions: Vec<Revision>,
+    /// Index into `revisions` and its decompressed text, or why it could
+    /// not be read.
+    selected: Option<(usize, Result<String, String>)>,
+    diff: DiffView<(u64, usize)>,
+}
+
+impl HistoryPanel {
+    pub fn new(doc_id: u64, title: String, path: &Path) -> Self {
+        Self {
+            doc_id,
+            title,
+            revisions: revisions(path),
+            selected: None,
+            diff: DiffView::default(),
+        }
+    }
+
+    /// Draw the window against the document's `current` text. Returns
+    /// whether it stays open and the text of a revision to restore.
+    pub fn show(&mut self, ctx: &egui::Context, current: &Buffer) -> (bool, Option<String>) {
+        let mut open = true;
+        let mut restore = None;
+        egui::Window::new(format!("File History: {}", self.title))
+            .id(egui::Id::new("file_history"))
+            .open(&mut open)
+            .default_size([720.0, 460.0])
+            .show(ctx, |ui| {
+                if self.revisions.is_empty() {
+                    ui.label("No saved revisions yet. Every save adds one.");
+                    return;
+                }
+                ui.columns(2, |columns| {
+                    egui::ScrollArea::vertical()
+                        .id_source("file_history_list")
+                        .show(&mut columns[0], |ui| {
+                            for (idx, revision) in self.revisions.iter().enumerate() {
+                                let selected = self.selected.as_ref().is_some_and(|(i, _)| *i == idx);
+                                let label = format!(
+                                    "{}  ({:.1} KB)",
+                                    format_age(revision.saved_at / 1000),
+                                    revision.size as f64 / 1024.0
+                                );
+                                if ui.selectable_label(selected, label).clicked() {
+                                    let text = revision.read().map_err(|e| format!("{e:#}"));
+                                    self.selected = Some((idx, text));
+                                }
+                            }
+                        });
+                    let ui = &mut columns[1];
+                    let (idx, text) = match &self.selected {
+                        None => {
+                            ui.label("Select a revision to compare it with the current text.");
+                            return;
+                        }
+                        Some((_, Err(e))) => {
+                            let error = ui.visuals().error_fg_color;
+                            ui.colored_label(error, format!("Could not read this revision: {e}"));
+                            return;
+                        }
+                        Some((idx, Ok(text))) => (*idx, text),
+                    };
+                    ui.horizontal(|ui| {
+                        if ui
+                            .button("Restore")
+                            .on_hover_text("Replace the document's text with this revision (undoable)")
+                            .clicked()
+                        {
+                            restore = Some(text.clone());
+                        }
+                        ui.label("− current   + revision");
+                    });
+                    ui.separator();
+                    self.diff.show(ui, (current.version(), idx), || (current.to_string(), text));
+                });
+            });
+        (open, restore)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    fn record_text(dir: &TempDir, text: &str) {
+        record_in(dir.path(), Path::new("notes.txt"), text).unwrap();
+    }
+
+    #[test]
+    fn revisions_are_named_by_time_size_and_digest() {
+        let dir = TempDir::new("history-names");
+        record_text(&dir, "héllo");
+        let found = revisions_in(dir.path());
+        assert_eq!(found.len(), 1);
+        let name = found[0].file.file_name().unwrap().to_str().unwrap();
+        assert_eq!(name, format!("{}-6-{}.gz", found[0].saved_at, digest("héllo")));
+        assert_eq!(found[0].size, 6);
+        // Names from before digests were kept still count; stray files do not
+        dir.file("5-3.gz", b"");
+        dir.file("path.txt", b"notes.txt");
+        let found = revisions_in(dir.path());
+        assert_eq!(found.last().map(|revision| revision.saved_at), Some(5));
+        assert_eq!(found[1].digest, None);
+    }
+
+    #[test]
+    fn revisions_round_trip_through_gzip() {
+        let dir = TempDir::new("history-gzip");
+        let text = "line one\nlíne two\n".repeat(50);
+        record_text(&dir, &text);
+        assert_eq!(revisions_in(dir.path())[0].read().unwrap(), text);
+    }
+
+    #[test]
+    fn unchanged_saves_are_not_recorded_twice() {
+        let dir = TempDir::new("history-dedupe");
+        record_text(&dir, "one");
+        record_text(&dir, "one");
+        assert_eq!(revisions_in(dir.path()).len(), 1);
+        record_text(&dir, "two");
+        record_text(&dir, "one");
+        assert_eq!(revisions_in(dir.path()).len(), 3);
+    }
+
+    #[test]
+    fn oldest_revisions_are_pruned() {
+        let dir = TempDir::new("history-prune");
+        for saved_at in 1..=MAX_REVISIONS {
+            dir.file(&format!("{saved_at}-1-{saved_at:016x}.gz"), b"");
+        }
+        record_text(&dir, "newest");
+        let found = revisions_in(dir.path());
+        assert_eq!(found.len(), MAX_REVISIONS);
+        assert_eq!(found.last().map(|revision| revision.saved_at), Some(2));
+        assert_eq!(found[0].digest.as_deref(), Some(digest("newest").as_str()));
+    }
+}
@@ -0,0 +1,494 @@
+use anyhow::{Context, Result};
+use eframe::egui::{self, Key, KeyboardShortcut, Modifiers};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::Action;
+use crate::fileio::{self, BackupMode};
+
+/// How long the first chords of a sequence wait for the rest.
+pub const SEQUENCE_TIMEOUT: Duration = Duration::from_millis(1500);
+
+/// One or more chords pressed in turn, written like `Ctrl+K Ctrl+S`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct KeySequence(pub Vec<KeyboardShortcut>);
+
+/// Parse a single chord such as `Ctrl+Shift+S`, `Alt+PageDown` or `Ctrl++`.
+fn parse_chord(text: &str) -> Result<KeyboardShortcut, String> {
+    let (mods, key) = match text.strip_suffix("++") {
+        Some(mods) => (mods, "+"),
+        None => text.rsplit_once('+').unwrap_or(("", text)),
+    };
+    let key = Key::from_name(key).ok_or_else(|| format!("unknown key \"{key}\" in \"{text}\""))?;
+    let mut modifiers = Modifiers::NONE;
+    for name in mods.split('+').filter(|name| !name.is_empty()) {
+        match name.to_ascii_lowercase().as_str() {
+            "ctrl" | "control" => modifiers.ctrl = true,
+            "shift" => modifiers.shift = true,
+            "alt" | "option" => modifiers.alt = true,
+            "cmd" | "command" => modifiers.mac_cmd = true,
+            _ => return Err(format!("unknown modifier \"{name}\" in \"{text}\"")),
+        }
+    }
+    Ok(KeyboardShortcut::new(modifiers, key))
+}
+
+/// The chord in the same spelling the keymap file uses.
+pub fn format_chord(chord: &KeyboardShortcut) -> String {
+    let mut parts = Vec::new();
+    let modifiers = chord.modifiers;
+    for (held, name) in [
+        (modifiers.ctrl, "Ctrl"),
+        (modifiers.shift, "Shift"),
+        (modifiers.alt, "Alt"),
+        (modifiers.mac_cmd, "Cmd"),
+    ] {
+        if held {
+            parts.push(name);
+        }
+    }
+    parts.push(chord.logical_key.name());
+    parts.join("+")
+}
+
+impl KeySequence {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let chords = text
+            .split_whitespace()
+            .map(parse_chord)
+            .collect::<Result<Vec<_>, _>>()?;
+        if chords.is_empty() {
+            return Err("empty key binding".into());
+        }
+        Ok(Self(chords))
+    }
+}
+
+impl fmt::Display for KeySequence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let chords: Vec<String> = self.0.iter().map(format_chord).collect();
+        f.write_str(&chords.join(" "))
+    }
+}
+
+/// A key sequence and the action it runs.
+pub struct Binding {
+    pub action_id: &'static str,
+    pub sequence: KeySequence,
+}
+
+/// Either spelling allowed for an action in `keymap.toml`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Chords {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct KeymapFile {
+    bindings: BTreeMap<String, Chords>,
+}
+
+/// What a frame's key presses amounted to.
+pub enum KeyEvent {
+    /// A complete sequence; run this action.
+    Matched(&'static str),
+    /// The first chords of a longer sequence; waiting for the rest.
+    Pending(String),
+    /// A started sequence timed out or was followed by an unbound key.
+    Cancelled(String),
+}
+
+/// The effective key bindings: each action's built-in shortcut, overridden
+/// per action by the user's `keymap.toml`.
+#[derive(Default)]
+pub struct Keymap {
+    bindings: Vec<Binding>,
+    /// Problems found while loading, reported in the status bar and the
+    /// Show Keymap window. The keymap still works around them.
+    pub problems: Vec<String>,
+    /// Chords of a sequence typed so far.
+    pending: Vec<KeyboardShortcut>,
+    pending_since: Option<Instant>,
+}
+
+/// `~/.config/nust/keymap.toml` on Linux, `%APPDATA%\nust\keymap.toml` on Windows.
+pub fn keymap_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("nust").join("keymap.toml"))
+}
+
+impl Keymap {
+    /// Build the keymap for `actions`, applying overrides from `path` if it
+    /// exists. Bindings for the same action are kept in the file's order.
+    pub fn load(actions: &[Action], path: Option<&Path>) -> Self {
+        let mut problems = Vec::new();
+        let mut overrides = match path.map(read_file) {
+            Some(Ok(file)) => file.bindings,
+            Some(Err(e)) => {
+                problems.push(e);
+                BTreeMap::new()
+            }
+            None => BTreeMap::new(),
+        };
+
+        let palette = crate::App::command_palette_shortcut();
+        let mut bindings = Vec::new();
+        for action in actions {
+            let sequences = match overrides.remove(action.id) {
+                None => action.shortcut.map(|chord| KeySequence(vec![chord])).into_iter().collect(),
+                Some(chords) => {
+                    let texts = match chords {
+                        Chords::One(text) => vec![text],
+                        Chords::Many(texts) => texts,
+                    };
+                    let mut sequences = Vec::new();
+                    for text in texts {
+                        match KeySequence::parse(&text) {
+                            Ok(sequence) => sequences.push(sequence),
+                            Err(e) => problems.push(format!("{}: {e}", action.id)),
+                        }
+                    }
+                    sequences
+                }
+            };
+            for sequence in sequences {
+                if sequence.0[0] == palette {
+                    problems.push(format!(
+                        "{}: {} is reserved for the command palette",
+                        action.id,
+                        format_chord(&palette)
+                    ));
+                    continue;
+                }
+                bindings.push(Binding {
+                    action_id: action.id,
+                    sequence,
+                });
+            }
+        }
+        for unknown in overrides.keys() {
+            problems.push(format!("keymap.toml: no action called \"{unknown}\""));
+        }
+
+        problems.extend(conflicts(&bindings));
+        Self {
+            bindings,
+            problems,
+            pending: Vec::new(),
+            pending_since: None,
+        }
+    }
+
+    /// Every sequence bound to `action_id`, comma separated.
+    pub fn describe(&self, action_id: &str) -> Option<String> {
+        let sequences: Vec<String> = self
+            .bindings
+            .iter()
+            .filter(|binding| binding.action_id == action_id)
+            .map(|binding| binding.sequence.to_string())
+            .collect();
+        (!sequences.is_empty()).then(|| sequences.join(", "))
+    }
+
+    /// Drop any half-typed sequence, e.g. when a modal opens.
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.pending_since = None;
+    }
+
+    /// Consume this frame's chords that belong to a binding and report what
+    /// they add up to.
+    pub fn process(&mut self, ctx: &egui::Context, now: Instant) -> Option<KeyEvent> {
+        if self
+            .pending_since
+            .is_some_and(|since| now.duration_since(since) > SEQUENCE_TIMEOUT)
+        {
+            return Some(KeyEvent::Cancelled(self.take_pending()));
+        }
+
+        let depth = self.pending.len();
+        let mut next: Vec<KeyboardShortcut> = Vec::new();
+        for binding in &self.bindings {
+            let chords = &binding.sequence.0;
+            if chords.len() > depth && chords.starts_with(&self.pending) && !next.contains(&chords[depth]) {
+                next.push(chords[depth]);
+            }
+        }
+        // egui ignores extra Shift/Alt when matching, so try the most specific
+        // chords first or Ctrl+S would swallow Ctrl+Shift+S.
+        next.sort_by_key(|chord| {
+            std::cmp::Reverse(chord.modifiers.shift as u8 + chord.modifiers.alt as u8)
+        });
+        for chord in next {
+            if !ctx.input_mut(|i| i.consume_shortcut(&chord)) {
+                continue;
+            }
+            self.pending.push(chord);
+            if let Some(binding) = self.bindings.iter().find(|binding| binding.sequence.0 == self.pending) {
+                let action_id = binding.action_id;
+                self.reset();
+                return Some(KeyEvent::Matched(action_id));
+            }
+            self.pending_since = Some(now);
+            ctx.request_repaint_after(SEQUENCE_TIMEOUT);
+            return Some(KeyEvent::Pending(KeySequence(self.pending.clone()).to_string()));
+        }
+
+        let other_key = depth > 0
+            && ctx.input(|i| {
+                i.events
+                    .iter()
+                    .any(|event| matches!(event, egui::Event::Key { pressed: true, .. }))
+            });
+        other_key.then(|| KeyEvent::Cancelled(self.take_pending()))
+    }
+
+    fn take_pending(&mut self) -> String {
+        let typed = KeySequence(std::mem::take(&mut self.pending)).to_string();
+        self.pending_since = None;
+        typed
+    }
+}
+
+fn read_file(path: &Path) -> Result<KeymapFile, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(KeymapFile::default()),
+        Err(e) => return Err(format!("{}: {e}", path.display())),
+    };
+    toml::from_str(&text).map_err(|e| match e.span() {
+        Some(span) => format!(
+            "keymap.toml line {}: {}",
+            text[..span.start].matches('\n').count() + 1,
+            e.message()
+        ),
+        None => format!("keymap.toml: {}", e.message()),
+    })
+}
+
+/// Bindings that can never fire as written: the same sequence on two
+/// actions (the first registered wins), or a sequence that is the start of
+/// a longer one (the shorter fires before the rest can be typed).
+fn conflicts(bindings: &[Binding]) -> Vec<String> {
+    let mut found = Vec::new();
+    for (idx, first) in bindings.iter().enumerate() {
+        for second in &bindings[idx + 1..] {
+            let (a, b) = (&first.sequence.0, &second.sequence.0);
+            if a == b {
+                if first.action_id != second.action_id {
+                    found.push(format!(
+                        "{} is bound to both {} and {}; {} wins",
+                        first.sequence, first.action_id, second.action_id, first.action_id
+                    ));
+                }
+            } else if b.starts_with(a) || a.starts_with(b) {
+                let (short, long) = if a.len() < b.len() { (first, second) } else { (second, first) };
+                found.push(format!(
+                    "{} ({}) hides {} ({})",
+                    short.sequence, short.action_id, long.sequence, long.action_id
+                ));
+            }
+        }
+    }
+    found
+}
+
+/// Write a commented starting point for the keymap file.
+pub fn write_template(path: &Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    }
+    let text = "# nust key bindings. Changes apply as soon as the file is saved.\n\
+                # Map an action id (see Show Keymap) to one chord or a list of them;\n\
+                # chords separated by spaces form a sequence. [] unbinds an action.\n\
+                #\n\
+                # save_file = \"Ctrl+S\"\n\
+                # quick_save = [\"Ctrl+Alt+S\", \"Ctrl+K Ctrl+S\"]\n\
+                # toggle_word_wrap = []\n\n\
+                [bindings]\n";
+    fileio::write_atomic(path, text.as_bytes(), BackupMode::Off)?;
+    Ok(())
+}
+
+/// The "Keymap" window listing every action and its effective bindings.
+/// Returns whether it stays open and whether the user asked to edit the file.
+pub fn keymap_window(ctx: &egui::Context, keymap: &Keymap, actions: &[Action]) -> (bool, bool) {
+    let mut open = true;
+    let mut edit = false;
+    egui::Window::new("Keymap")
+        .open(&mut open)
+        .default_size([560.0, 480.0])
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Edit keymap.toml").clicked() {
+                    edit = true;
+                }
+                if let Some(path) = keymap_path() {
+                    ui.weak(path.display().to_string());
+                }
+            });
+            for problem in &keymap.problems {
+                ui.colored_label(ui.visuals().warn_fg_color, problem);
+            }
+            ui.separator();
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("keymap_grid").striped(true).show(ui, |ui| {
+                    ui.strong("Command");
+                    ui.strong("Id");
+                    ui.strong("Keys");
+                    ui.end_row();
+                    ui.label("Command Palette");
+                    ui.weak("—");
+                    ui.monospace(format_chord(&crate::App::command_palette_shortcut()));
+                    ui.end_row();
+                    for action in actions {
+                        ui.label(action.label);
+                        ui.weak(action.id);
+                        ui.monospace(keymap.describe(action.id).unwrap_or_default());
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+    (open, edit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    /// The default keymap with `toml` as the user's `keymap.toml`.
+    fn keymap_with(name: &str, toml: &str) -> Keymap {
+        let dir = TempDir::new(name);
+        Keymap::load(&crate::App::registered_actions(), Some(&dir.file("keymap.toml", toml)))
+    }
+
+    /// Run one frame in which `chord` is pressed through `keymap`.
+    fn press(keymap: &mut Keymap, chord: &str, now: Instant) -> Option<KeyEvent> {
+        let chord = parse_chord(chord).unwrap();
+        // Integrations report Ctrl as `command` too, except on macOS
+        let modifiers = Modifiers {
+            command: chord.modifiers.ctrl,
+            ..chord.modifiers
+        };
+        let ctx = egui::Context::default();
+        ctx.begin_frame(egui::RawInput {
+            modifiers,
+            events: vec![egui::Event::Key {
+                key: chord.logical_key,
+                physical_key: None,
+                pressed: true,
+                repeat: false,
+                modifiers,
+            }],
+            ..Default::default()
+        });
+        let event = keymap.process(&ctx, now);
+        let _ = ctx.end_frame();
+        event
+    }
+
+    #[test]
+    fn parses_chords() {
+        let chord = parse_chord("Ctrl+Shift+S").unwrap();
+        assert_eq!(chord.logical_key, Key::S);
+        assert!(chord.modifiers.ctrl && chord.modifiers.shift && !chord.modifiers.alt);
+        assert_eq!(parse_chord("Ctrl++").unwrap().logical_key, Key::Plus);
+        assert_eq!(format_chord(&parse_chord("option+control+PageDown").unwrap()), "Ctrl+Alt+PageDown");
+        assert!(parse_chord("Hyper+A").unwrap_err().contains("unknown modifier"));
+        assert!(parse_chord("Ctrl+Nope").unwrap_err().contains("unknown key"));
+    }
+
+    #[test]
+    fn parses_sequences() {
+        let sequence = KeySequence::parse("Ctrl+K  Ctrl+S").unwrap();
+        assert_eq!(sequence.0.len(), 2);
+        assert_eq!(sequence.to_string(), "Ctrl+K Ctrl+S");
+        assert!(KeySequence::parse("  ").is_err());
+    }
+
+    #[test]
+    fn defaults_have_no_problems() {
+        let keymap = Keymap::load(&crate::App::registered_actions(), None);
+        assert_eq!(keymap.problems, Vec::<String>::new());
+        assert_eq!(keymap.describe("undo").as_deref(), Some("Ctrl+Z"));
+    }
+
+    #[test]
+    fn overrides_replace_and_empty_list_unbinds() {
+        let toml = "[bindings]\nundo = []\nredo = [\"Ctrl+Y\", \"Ctrl+K Ctrl+Z\"]\n";
+        let keymap = keymap_with("keymap-overrides", toml);
+        assert_eq!(keymap.problems, Vec::<String>::new());
+        assert_eq!(keymap.describe("undo"), None);
+        assert_eq!(keymap.describe("redo").as_deref(), Some("Ctrl+Y, Ctrl+K Ctrl+Z"));
+    }
+
+    #[test]
+    fn reports_bad_entries() {
+        let keymap = keymap_with(
+            "bad_entries",
+            "[bindings]\nno_such_action = \"Ctrl+J\"\nundo = \"Ctrl+Shift+P\"\nredo = \"Ctrl+Nope\"\n",
+        );
+        assert_eq!(keymap.problems.len(), 3, "{:?}", keymap.problems);
+        assert!(keymap.problems.iter().any(|problem| problem.contains("no_such_action")));
+        assert!(keymap.problems.iter().any(|problem| problem.contains("reserved")));
+        assert!(keymap.problems.iter().any(|problem| problem.contains("unknown key")));
+    }
+
+    #[test]
+    fn detects_conflicts() {
+        let binding = |action_id, text| Binding {
+            action_id,
+            sequence: KeySequence::parse(text).unwrap(),
+        };
+        let found = conflicts(&[
+            binding("a", "Ctrl+J"),
+            binding("b", "Ctrl+J"),
+            binding("c", "Ctrl+K"),
+            binding("d", "Ctrl+K Ctrl+S"),
+            binding("e", "Ctrl+L"),
+        ]);
+        assert_eq!(
+            found,
+            [
+                "Ctrl+J is bound to both a and b; a wins",
+                "Ctrl+K (c) hides Ctrl+K Ctrl+S (d)",
+            ]
+        );
+    }
+
+    #[test]
+    fn more_specific_chord_wins() {
+        let mut keymap = Keymap::load(&crate::App::registered_actions(), None);
+        let now = Instant::now();
+        assert!(matches!(press(&mut keymap, "Ctrl+Shift+Z", now), Some(KeyEvent::Matched("redo"))));
+        assert!(matches!(press(&mut keymap, "Ctrl+Z", now), Some(KeyEvent::Matched("undo"))));
+    }
+
+    #[test]
+    fn sequences_wait_for_the_rest() {
+        let mut keymap = keymap_with("keymap-sequence", "[bindings]\nredo = \"Ctrl+K Ctrl+Z\"\n");
+        let now = Instant::now();
+        let pending = press(&mut keymap, "Ctrl+K", now);
+        assert!(matches!(pending, Some(KeyEvent::Pending(typed)) if typed == "Ctrl+K"));
+        assert!(matches!(press(&mut keymap, "Ctrl+Z", now), Some(KeyEvent::Matched("redo"))));
+
+        // Another key abandons the sequence
+        press(&mut keymap, "Ctrl+K", now);
+        let cancelled = press(&mut keymap, "X", now);
+        assert!(matches!(cancelled, Some(KeyEvent::Cancelled(typed)) if typed == "Ctrl+K"));
+
+        // So does waiting too long, and the late chord then runs on its own
+        press(&mut keymap, "Ctrl+K", now);
+        let late = now + SEQUENCE_TIMEOUT * 2;
+        assert!(matches!(press(&mut keymap, "Ctrl+Z", late), Some(KeyEvent::Cancelled(_))));
+        assert!(matches!(press(&mut keymap, "Ctrl+Z", late), Some(KeyEvent::Matched("undo"))));
+    }
+}
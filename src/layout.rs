@@ -0,0 +1,332 @@
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Stable identity of a pane, independent of where it sits in the layout.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+pub struct PaneId(pub u64);
+
+impl fmt::Display for PaneId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "pane {}", self.0)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Axis {
+    /// Children sit side by side.
+    Horizontal,
+    /// Children are stacked top to bottom.
+    Vertical,
+}
+
+/// Panes never shrink below this share of their parent split.
+const MIN_SHARE: f32 = 0.05;
+const DIVIDER_WIDTH: f32 = 6.0;
+
+/// Tree of splits whose leaves are panes.
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Layout {
+    Leaf(PaneId),
+    Split {
+        axis: Axis,
+        children: Vec<Layout>,
+        /// Fraction of the parent given to each child; sums to 1.
+        shares: Vec<f32>,
+    },
+}
+
+impl Layout {
+    /// All panes in reading order (left to right, top to bottom).
+    pub fn leaves(&self) -> Vec<PaneId> {
+        let mut out = Vec::new();
+        self.collect_leaves(&mut out);
+        out
+    }
+
+    fn collect_leaves(&self, out: &mut Vec<PaneId>) {
+        match self {
+            Layout::Leaf(id) => out.push(*id),
+            Layout::Split { children, .. } => {
+                for child in children {
+                    child.collect_leaves(out);
+                }
+            }
+        }
+    }
+
+    /// Whether every split has at least one child and a share for each, as
+    /// the drawing and resizing code assumes. A layout read back from disk
+    /// may not.
+    pub fn is_well_formed(&self) -> bool {
+        match self {
+            Layout::Leaf(_) => true,
+            Layout::Split { children, shares, .. } => {
+                !children.is_empty()
+                    && shares.len() == children.len()
+                    && children.iter().all(Layout::is_well_formed)
+            }
+        }
+    }
+
+    /// Split `target` along `axis`, placing `new_pane` right after it.
+    /// Splitting along the parent's own axis adds a sibling instead of nesting.
+    pub fn split(&mut self, target: PaneId, new_pane: PaneId, axis: Axis) -> bool {
+        match self {
+            Layout::Leaf(id) if *id == target => {
+                *self = Layout::Split {
+                    axis,
+                    children: vec![Layout::Leaf(target), Layout::Leaf(new_pane)],
+                    shares: vec![0.5, 0.5],
+                };
+                true
+            }
+            Layout::Leaf(_) => false,
+            Layout::Split {
+                axis: own_axis,
+                children,
+                shares,
+            } => {
+                if *own_axis == axis
+                    && let Some(idx) = children
+                        .iter()
+                        .position(|child| matches!(child, Layout::Leaf(id) if *id == target))
+                {
+                    let half = shares[idx] / 2.0;
+                    shares[idx] = half;
+                    shares.insert(idx + 1, half);
+                    children.insert(idx + 1, Layout::Leaf(new_pane));
+                    return true;
+                }
+                children
+                    .iter_mut()
+                    .any(|child| child.split(target, new_pane, axis))
+            }
+        }
+    }
+
+    /// Remove `target` from the tree, collapsing splits left with a single child.
+    /// The last remaining pane cannot be removed.
+    pub fn remove(&mut self, target: PaneId) -> bool {
+        let Layout::Split {
+            children, shares, ..
+        } = self
+        else {
+            return false;
+        };
+        let removed = if let Some(idx) = children
+            .iter()
+            .position(|child| matches!(child, Layout::Leaf(id) if *id == target))
+        {
+            children.remove(idx);
+            let freed = shares.remove(idx);
+            let rest: f32 = shares.iter().sum();
+            for share in shares.iter_mut() {
+                *share += freed * (*share / rest.max(f32::EPSILON));
+            }
+            true
+        } else {
+            children.iter_mut().any(|child| child.remove(target))
+        };
+        if removed && children.len() == 1 {
+            *self = children.pop().expect("split has one child");
+        }
+        removed
+    }
+
+    /// Exchange the positions of two panes.
+    pub fn swap(&mut self, a: PaneId, b: PaneId) {
+        match self {
+            Layout::Leaf(id) if *id == a => *id = b,
+            Layout::Leaf(id) if *id == b => *id = a,
+            Layout::Leaf(_) => {}
+            Layout::Split { children, .. } => {
+                for child in children {
+                    child.swap(a, b);
+                }
+            }
+        }
+    }
+
+    /// Lay the tree out inside `rect`, drawing draggable dividers between
+    /// children and calling `show_pane` for every leaf.
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        rect: egui::Rect,
+        id: egui::Id,
+        show_pane: &mut dyn FnMut(&mut egui::Ui, PaneId),
+    ) {
+        match self {
+            Layout::Leaf(pane) => {
+                let mut child = ui.child_ui_with_id_source(
+                    rect.shrink(2.0),
+                    egui::Layout::top_down(egui::Align::Min),
+                    pane.0,
+                );
+                child.set_clip_rect(rect.intersect(ui.clip_rect()));
+                show_pane(&mut child, *pane);
+            }
+            Layout::Split {
+                axis,
+                children,
+                shares,
+            } => {
+                let axis = *axis;
+                let total = match axis {
+                    Axis::Horizontal => rect.width(),
+                    Axis::Vertical => rect.height(),
+                };
+                let dividers = DIVIDER_WIDTH * (children.len() - 1) as f32;
+                let usable = (total - dividers).max(1.0);
+
+                let mut start = match axis {
+                    Axis::Horizontal => rect.left(),
+                    Axis::Vertical => rect.top(),
+                };
+                for idx in 0..children.len() {
+                    let len = usable * shares[idx];
+                    let child_rect = span(rect, axis, start, start + len);
+                    children[idx].show(ui, child_rect, id.with(idx), show_pane);
+                    start += len;
+
+                    if idx + 1 < children.len() {
+                        let divider_rect = span(rect, axis, start, start + DIVIDER_WIDTH);
+                        let resp =
+                            ui.interact(divider_rect, id.with(("divider", idx)), egui::Sense::drag());
+                        if resp.hovered() || resp.dragged() {
+                            ui.ctx().set_cursor_icon(match axis {
+                                Axis::Horizontal => egui::CursorIcon::ResizeHorizontal,
+                                Axis::Vertical => egui::CursorIcon::ResizeVertical,
+                            });
+                        }
+                        if resp.dragged() {
+                            let delta = match axis {
+                                Axis::Horizontal => resp.drag_delta().x,
+                                Axis::Vertical => resp.drag_delta().y,
+                            } / usable;
+                            let pair = shares[idx] + shares[idx + 1];
+                            let min = MIN_SHARE.min(pair / 2.0);
+                            let left = (shares[idx] + delta).clamp(min, pair - min);
+                            shares[idx] = left;
+                            shares[idx + 1] = pair - left;
+                        }
+                        let stroke = if resp.hovered() || resp.dragged() {
+                            ui.visuals().widgets.hovered.fg_stroke
+                        } else {
+                            ui.visuals().widgets.noninteractive.bg_stroke
+                        };
+                        let center = divider_rect.center();
+                        match axis {
+                            Axis::Horizontal => {
+                                ui.painter().vline(center.x, divider_rect.y_range(), stroke);
+                            }
+                            Axis::Vertical => {
+                                ui.painter().hline(divider_rect.x_range(), center.y, stroke);
+                            }
+                        }
+                        start += DIVIDER_WIDTH;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Sub-rectangle of `rect` between `from` and `to` along `axis`.
+fn span(rect: egui::Rect, axis: Axis, from: f32, to: f32) -> egui::Rect {
+    match axis {
+        Axis::Horizontal => {
+            egui::Rect::from_min_max(egui::pos2(from, rect.top()), egui::pos2(to, rect.bottom()))
+        }
+        Axis::Vertical => {
+            egui::Rect::from_min_max(egui::pos2(rect.left(), from), egui::pos2(rect.right(), to))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(children: Vec<Layout>, shares: Vec<f32>) -> Layout {
+        Layout::Split {
+            axis: Axis::Horizontal,
+            children,
+            shares,
+        }
+    }
+
+    #[test]
+    fn well_formed_layouts_pass() {
+        let mut layout = Layout::Leaf(PaneId(1));
+        assert!(layout.is_well_formed());
+        layout.split(PaneId(1), PaneId(2), Axis::Horizontal);
+        layout.split(PaneId(2), PaneId(3), Axis::Vertical);
+        assert!(layout.is_well_formed());
+    }
+
+    #[test]
+    fn splits_without_children_or_shares_fail() {
+        assert!(!split(Vec::new(), Vec::new()).is_well_formed());
+        let short = split(vec![Layout::Leaf(PaneId(1)), Layout::Leaf(PaneId(2))], vec![1.0]);
+        assert!(!short.is_well_formed());
+        let nested = split(vec![Layout::Leaf(PaneId(1)), split(Vec::new(), Vec::new())], vec![0.5, 0.5]);
+        assert!(!nested.is_well_formed());
+    }
+
+    fn ids(ids: &[u64]) -> Vec<PaneId> {
+        ids.iter().copied().map(PaneId).collect()
+    }
+
+    /// The top split's shares, checking they add up.
+    fn shares(layout: &Layout) -> Vec<f32> {
+        let Layout::Split { shares, .. } = layout else {
+            panic!("not a split");
+        };
+        assert!((shares.iter().sum::<f32>() - 1.0).abs() < 1e-5, "{shares:?}");
+        shares.clone()
+    }
+
+    #[test]
+    fn split_then_remove_collapses_and_keeps_shares_whole() {
+        let mut layout = Layout::Leaf(PaneId(1));
+        layout.split(PaneId(1), PaneId(2), Axis::Horizontal);
+        // Same axis: a sibling taking half of its neighbour's share
+        layout.split(PaneId(2), PaneId(3), Axis::Horizontal);
+        assert_eq!(shares(&layout), [0.5, 0.25, 0.25]);
+        // Other axis: nested
+        layout.split(PaneId(3), PaneId(4), Axis::Vertical);
+        assert_eq!(layout.leaves(), ids(&[1, 2, 3, 4]));
+        assert!(layout.remove(PaneId(4)));
+        let Layout::Split { children, .. } = &layout else {
+            panic!("not a split");
+        };
+        assert!(children.iter().all(|child| matches!(child, Layout::Leaf(_))));
+        // The freed share goes to the rest in proportion
+        assert!(layout.remove(PaneId(2)));
+        let [left, right] = shares(&layout)[..] else {
+            panic!("expected two panes");
+        };
+        assert!((left - 2.0 / 3.0).abs() < 1e-5 && (right - 1.0 / 3.0).abs() < 1e-5);
+        assert!(layout.remove(PaneId(3)));
+        assert!(matches!(layout, Layout::Leaf(PaneId(1))));
+        assert!(!layout.remove(PaneId(1)));
+        assert!(!layout.remove(PaneId(9)));
+    }
+
+    #[test]
+    fn swap_works_across_sub_trees() {
+        let mut layout = Layout::Leaf(PaneId(1));
+        layout.split(PaneId(1), PaneId(2), Axis::Horizontal);
+        layout.split(PaneId(2), PaneId(3), Axis::Vertical);
+        layout.swap(PaneId(1), PaneId(3));
+        assert_eq!(layout.leaves(), ids(&[3, 2, 1]));
+        let Layout::Split { children, .. } = &layout else {
+            panic!("not a split");
+        };
+        assert!(matches!(children[0], Layout::Leaf(PaneId(3))));
+        assert_eq!(children[1].leaves(), ids(&[2, 1]));
+        assert_eq!(shares(&layout), [0.5, 0.5]);
+    }
+}
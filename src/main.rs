@@ -3,83 +3,316 @@ use eframe::{
     NativeOptions,
     egui::{self, KeyboardShortcut, Modifiers},
 };
-use std::{fs, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+mod autosave;
+mod buffer;
+mod config;
+mod confirm;
+mod diff;
+mod editor;
+mod encoding;
+mod eol;
+mod fileio;
+mod find;
+mod fuzzy;
+mod history;
+mod keymap;
+mod layout;
+mod palette;
+mod prompt;
+mod quicksave;
+mod recent;
+mod recovery;
+mod replace;
+mod search;
+mod session;
+mod tabs;
+#[cfg(test)]
+mod testutil;
+mod undo;
+mod watch;
+
+use autosave::{Autosave, AutosaveMode};
+use config::{Config, ConfigWatcher};
+use confirm::{ConfirmChoice, ConfirmDiscard, DirtyDoc, PendingClose, confirm_discard_ui};
+use buffer::Buffer;
+use editor::{Cursors, Shading};
+use encoding::TextEncoding;
+use eol::LineEnding;
+use fileio::{BackupMode, DiskChange, DiskStamp, LoadError};
+use find::{FindBar, FindCommand};
+use history::HistoryPanel;
+use keymap::{KeyEvent, Keymap, keymap_window};
+use layout::{Axis, Layout, PaneId};
+use palette::{Candidate, PaletteItem, PaletteMode, RecentCommands, palette_list};
+use prompt::{ArgKind, ArgSpec, ArgValue, Prompt};
+use quicksave::{QuickSaveCommand, QuickSavePanel};
+use recent::RecentFiles;
+use recovery::{Journal, RecoverChoice, Recoverable, recover_dialog};
+use replace::ReplaceUndo;
+use search::{SearchCommand, SearchPanel};
+use session::{DocState, PaneState, Session};
+use tabs::{TabCommand, TabGroup, tab_bar};
+use undo::{Edit, UndoHistory};
+use watch::{ConflictChoice, DiskCompare, DiskWatcher, changed_on_disk_banner, compare_window};
 
 #[derive(Default)]
 struct Pane {
+    /// Unique per open document; keys its editor widgets.
+    doc_id: u64,
     title: String,
     path: Option<PathBuf>,
-    text: String,
-    dirty: bool,
+    text: Buffer,
+    /// Encoding (and BOM) the file was read with; saves write it back the same way.
+    encoding: TextEncoding,
+    /// Line endings written on save; the buffer itself only holds `\n`.
+    line_ending: LineEnding,
+    /// Line endings of the file as last read or written, for dirty tracking.
+    saved_line_ending: LineEnding,
+    /// The file on disk mixes LF and CRLF; the next save normalizes it.
+    mixed_endings: bool,
+    /// The file as of the last load or save, to spot changes made elsewhere.
+    disk_stamp: Option<DiskStamp>,
+    /// An outside change the user has not resolved yet; shows the banner.
+    disk_change: Option<DiskChange>,
+    history: UndoHistory,
+    /// When the text was last edited, for idle autosave.
+    last_edit: Option<Instant>,
+    /// Per-document opt-out from autosave.
+    skip_autosave: bool,
+    /// Carets and selections in the editor.
+    cursors: Cursors,
     scroll_offset: f32,
     scroll_viewport: f32,
     scroll_content: f32,
 }
 
 impl Pane {
-    fn load_from(&mut self, p: PathBuf) -> Result<()> {
-        self.text = fs::read_to_string(&p).unwrap_or_default();
+    fn load_from(&mut self, p: PathBuf) -> Result<(), LoadError> {
+        self.set_loaded(fileio::read_document(&p, None)?);
         self.title = p
             .file_name()
             .unwrap_or_default()
             .to_string_lossy()
             .to_string();
         self.path = Some(p);
-        self.dirty = false;
+        self.history.reset();
+        self.cursors = Cursors::default();
         self.scroll_offset = 0.0;
         self.scroll_viewport = 0.0;
         self.scroll_content = 0.0;
         Ok(())
     }
-    fn save_as(&mut self, p: PathBuf) -> Result<()> {
-        fs::write(&p, self.text.as_bytes())?;
+    /// Re-read the file from disk, decoding it as `encoding`.
+    fn reopen_with(&mut self, encoding: TextEncoding) -> Result<(), LoadError> {
+        let Some(p) = self.path.clone() else {
+            return Ok(());
+        };
+        self.set_loaded(fileio::read_document(&p, Some(encoding))?);
+        self.history.reset();
+        self.cursors = Cursors::default();
+        Ok(())
+    }
+    /// Pick up the file's current contents after it changed on disk. The
+    /// swap is recorded as one undo step, so unsaved edits can be undone back.
+    fn reload(&mut self) -> Result<(), LoadError> {
+        let Some(p) = self.path.clone() else {
+            return Ok(());
+        };
+        let document = fileio::read_document(&p, None)?;
+        let old = self.text.clone();
+        self.set_loaded(document);
+        if let Some(edit) = Edit::between(&old, &self.text) {
+            self.history.seal();
+            self.history.record(vec![edit], Instant::now());
+        }
+        self.history.mark_saved();
+        Ok(())
+    }
+    /// Swap in `text` wholesale as a single undo step, leaving the document dirty.
+    fn replace_text(&mut self, text: String) {
+        if let Some(edit) = Edit::between(&self.text, &Buffer::from(text)) {
+            self.replace_range(edit.at..edit.at + edit.removed.chars().count(), &edit.inserted);
+        }
+    }
+    /// Replace the chars in `range` with `with` as a single undo step.
+    fn replace_range(&mut self, range: std::ops::Range<usize>, with: &str) {
+        let edit = Edit {
+            at: range.start,
+            removed: self.text.slice(range.clone()),
+            inserted: with.to_owned(),
+        };
+        self.history.seal();
+        self.history.record(vec![edit], Instant::now());
+        self.text.replace(range, with);
+        self.last_edit = Some(Instant::now());
+    }
+    fn set_loaded(&mut self, document: fileio::Document) {
+        self.text = document.text;
+        self.encoding = document.encoding;
+        self.line_ending = document.line_ending;
+        self.saved_line_ending = document.line_ending;
+        self.mixed_endings = document.mixed_endings;
+        self.disk_stamp = Some(document.stamp);
+        self.disk_change = None;
+    }
+    /// The bytes a save writes: the buffer in this document's line endings and encoding.
+    fn encoded(&self) -> Result<Vec<u8>> {
+        self.encoding.encode(&self.line_ending.apply(&self.text.to_string()))
+    }
+    /// Keep a copy of what was just saved in the local file history. The save
+    /// itself already succeeded, so a failure there is only logged.
+    fn record_history(&self, p: &std::path::Path) {
+        history::record_later(p, &self.text);
+    }
+    fn mark_saved(&mut self, stamp: DiskStamp) {
+        self.history.mark_saved();
+        self.disk_stamp = Some(stamp);
+        self.disk_change = None;
+        self.saved_line_ending = self.line_ending;
+        self.mixed_endings = false;
+    }
+    fn save_as(&mut self, p: PathBuf, backup: BackupMode) -> Result<()> {
+        let bytes = self.encoded()?;
+        let stamp = fileio::write_atomic(&p, &bytes, backup)?;
+        self.record_history(&p);
         self.title = p
             .file_name()
             .unwrap_or_default()
             .to_string_lossy()
             .to_string();
         self.path = Some(p);
-        self.dirty = false;
+        self.mark_saved(stamp);
         self.scroll_offset = 0.0;
         self.scroll_viewport = 0.0;
         self.scroll_content = 0.0;
         Ok(())
     }
-    fn save(&mut self) -> Result<()> {
+    fn save(&mut self, backup: BackupMode) -> Result<()> {
         if let Some(p) = self.path.clone() {
-            fs::write(p, self.text.as_bytes())?;
-            self.dirty = false;
+            // Never silently clobber someone else's edits
+            if let Some(stamp) = &self.disk_stamp
+                && let Some(change @ DiskChange::Modified(_)) = fileio::check_disk(&p, stamp)
+            {
+                self.disk_change = Some(change);
+                anyhow::bail!(
+                    "{} changed on disk; reload it or keep your version before saving",
+                    self.title
+                );
+            }
+            let bytes = self.encoded()?;
+            let stamp = fileio::write_atomic(&p, &bytes, backup)?;
+            self.record_history(&p);
+            self.mark_saved(stamp);
             Ok(())
         } else {
             Err(anyhow::anyhow!("no path"))
         }
     }
+    fn untitled(doc_id: u64) -> Self {
+        Self {
+            doc_id,
+            title: "untitled".into(),
+            ..Default::default()
+        }
+    }
+    fn is_dirty(&self) -> bool {
+        // Restored scratch text has no history but still exists nowhere else
+        self.history.is_dirty()
+            || self.line_ending != self.saved_line_ending
+            || (self.path.is_none() && !self.text.is_empty())
+    }
+    fn undo(&mut self) -> bool {
+        let cursor = self.history.undo(&mut self.text);
+        if cursor.is_some() {
+            self.last_edit = Some(Instant::now());
+        }
+        if let Some(cursor) = cursor {
+            self.cursors.place(cursor);
+        }
+        cursor.is_some()
+    }
+    fn redo(&mut self) -> bool {
+        let cursor = self.history.redo(&mut self.text);
+        if cursor.is_some() {
+            self.last_edit = Some(Instant::now());
+        }
+        if let Some(cursor) = cursor {
+            self.cursors.place(cursor);
+        }
+        cursor.is_some()
+    }
 }
 
 struct App {
-    left: Pane,
-    right: Pane,
+    panes: BTreeMap<PaneId, TabGroup>,
+    layout: Layout,
+    next_pane_id: u64,
+    next_doc_id: u64,
     status: String,
     manual_path: String,
-    focused_pane: FocusedPane,
-    save_as_path: String,
-    show_save_as_input: bool,
-    show_open_input: bool,
-    open_input_path: String,
-    open_to_left: bool,
-    show_split_view: bool,
+    focused_pane: PaneId,
+    /// When set, only the focused pane is shown.
+    maximized: bool,
     word_wrap: bool,
     actions: Vec<Action>,
+    /// Key bindings for `actions`, from their defaults and `keymap.toml`.
+    keymap: Keymap,
+    keymap_watcher: Option<ConfigWatcher>,
+    show_keymap: bool,
     show_command_palette: bool,
     command_palette_query: String,
     command_palette_selected: usize,
-    pending_focus: Option<FocusedPane>,
+    recent_commands: RecentCommands,
+    /// Files under the working directory, listed when the palette first
+    /// needs them and dropped when it closes.
+    palette_files: Option<Vec<PathBuf>>,
+    /// Symbols of the focused document by its text version, so `@` does not
+    /// rescan the text every frame.
+    palette_symbols: Option<(u64, Vec<palette::Symbol>)>,
+    /// The list last shown, reused until the query or the focused text changes.
+    palette_results: Option<PaletteResults>,
+    /// Arguments being collected for an action, shown in place of the palette list.
+    palette_prompt: Option<Prompt>,
+    recent: RecentFiles,
+    pending_focus: Option<PaneId>,
+    /// Save / Discard / Cancel prompt for an operation that would lose work.
+    confirm: Option<ConfirmDiscard>,
+    /// Set once quitting has been confirmed, so the next close request goes through.
+    exit_confirmed: bool,
+    backup_mode: BackupMode,
+    encoding_picker: Option<EncodingPurpose>,
+    watcher: DiskWatcher,
+    disk_compare: Option<DiskCompare>,
+    /// Save the session on exit (off with `--no-session`).
+    session_enabled: bool,
+    /// Background snapshots of dirty buffers, for crash recovery.
+    journal: Option<Journal>,
+    /// Snapshots from a run that crashed, awaiting the user's decision.
+    recoverable: Vec<Recoverable>,
+    autosave: Autosave,
+    quick_saves: QuickSavePanel,
+    find_in_files: SearchPanel,
+    /// What the last Find in Files replace wrote, for Undo Replace.
+    replace_undo: Option<ReplaceUndo>,
+    file_history: Option<HistoryPanel>,
+    config: Config,
+    config_watcher: Option<ConfigWatcher>,
+    /// Editor font size currently set on the egui style.
+    applied_font_size: Option<f32>,
 }
 
-#[derive(PartialEq, Eq, Clone, Copy)]
-enum FocusedPane {
-    Left,
-    Right,
+/// What the encoding picker window was opened for.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum EncodingPurpose {
+    Reopen,
+    Save,
 }
 
 #[derive(Clone, Copy)]
@@ -88,6 +321,8 @@ struct Action {
     label: &'static str,
     shortcut: Option<KeyboardShortcut>,
     action: AppAction,
+    /// Asked for in the palette before the action runs.
+    args: &'static [ArgSpec],
 }
 
 impl Action {
@@ -102,173 +337,325 @@ impl Action {
             label,
             shortcut,
             action,
+            args: &[],
         }
     }
+
+    const fn with_args(mut self, args: &'static [ArgSpec]) -> Self {
+        self.args = args;
+        self
+    }
 }
 
-#[derive(Clone, Copy)]
+/// Ranked palette rows for one query.
+struct PaletteResults {
+    query: String,
+    /// `Buffer::version` of the focused document they were built from.
+    version: u64,
+    entries: Vec<(PaletteItem, PaletteTarget)>,
+}
+
+/// What picking a palette row does.
+enum PaletteTarget {
+    Action(Action),
+    File(PathBuf),
+    /// Start the Open dialog in this directory.
+    Dir(PathBuf),
+    /// Zero-based line and column in the focused document.
+    Line { line: usize, column: usize },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
 enum AppAction {
     OpenFile,
+    OpenPath,
+    OpenRecent,
+    ClearRecent,
     SaveFocused,
     SaveAsFocused,
     QuickSaveFocused,
     ManualSaveFocused,
-    CloseFocused,
+    SaveAsPath,
+    RenameTab,
+    CloseTab,
+    CloseOtherTabs,
+    NewTab,
+    NextTab,
+    PreviousTab,
+    RecentTab,
+    RecentTabBack,
     FocusNextPane,
     FocusPreviousPane,
     ShowSplitView,
-    ShowLeftOnly,
-    ShowRightOnly,
+    ShowFocusedOnly,
+    SplitRight,
+    SplitDown,
+    ClosePane,
+    MovePaneForward,
+    MovePaneBackward,
     ToggleWordWrap,
+    CycleBackupMode,
+    SetBackupMode,
+    SetAutosaveMode,
+    SetAutosaveIdle,
+    CycleAutosaveMode,
+    ShowQuickSaves,
+    OpenSettings,
+    ShowFileHistory,
+    ShowKeymap,
+    GoToFile,
+    GoToSymbol,
+    GoToLine,
+    Find,
+    Replace,
+    FindNext,
+    FindPrevious,
+    FindInFiles,
+    UndoReplace,
+    ToggleDocAutosave,
+    ReopenWithEncoding,
+    SaveWithEncoding,
+    ConvertToLf,
+    ConvertToCrlf,
+    Quit,
+    Undo,
+    Redo,
+    AddCaretAbove,
+    AddCaretBelow,
+    AddNextOccurrence,
+    SelectAllOccurrences,
 }
 
 impl Default for App {
     fn default() -> Self {
+        let (first, second) = (PaneId(1), PaneId(2));
+        let actions = Self::registered_actions();
+        let mut panes = BTreeMap::new();
+        panes.insert(first, TabGroup::new(Pane::untitled(1)));
+        panes.insert(second, TabGroup::new(Pane::untitled(2)));
         Self {
-            left: Pane {
-                title: "left".into(),
-                ..Default::default()
-            },
-            right: Pane {
-                title: "right".into(),
-                ..Default::default()
+            panes,
+            layout: Layout::Split {
+                axis: Axis::Horizontal,
+                children: vec![Layout::Leaf(first), Layout::Leaf(second)],
+                shares: vec![0.42, 0.58],
             },
+            next_pane_id: 3,
+            next_doc_id: 3,
             status: "ready".into(),
             manual_path: "target/quick_saves/output.txt".into(),
-            focused_pane: FocusedPane::Left,
-            save_as_path: "".into(),
-            show_save_as_input: false,
-            show_open_input: false,
-            open_input_path: "".into(),
-            open_to_left: true,
-            show_split_view: true,
+            focused_pane: first,
+            maximized: false,
             word_wrap: false,
-            actions: Self::registered_actions(),
+            keymap: Keymap::load(&actions, None),
+            keymap_watcher: None,
+            show_keymap: false,
+            actions,
             show_command_palette: false,
             command_palette_query: String::new(),
             command_palette_selected: 0,
-            pending_focus: Some(FocusedPane::Left),
+            recent_commands: RecentCommands::default(),
+            palette_files: None,
+            palette_symbols: None,
+            palette_results: None,
+            palette_prompt: None,
+            recent: RecentFiles::default(),
+            pending_focus: Some(first),
+            confirm: None,
+            exit_confirmed: false,
+            backup_mode: BackupMode::Off,
+            encoding_picker: None,
+            watcher: DiskWatcher::default(),
+            disk_compare: None,
+            session_enabled: false,
+            journal: None,
+            recoverable: Vec::new(),
+            autosave: Autosave::default(),
+            quick_saves: QuickSavePanel::default(),
+            find_in_files: SearchPanel::default(),
+            replace_undo: None,
+            file_history: None,
+            config: Config::default(),
+            config_watcher: None,
+            applied_font_size: None,
         }
     }
 }
 
 impl eframe::App for App {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if ctx.input(|i| i.viewport().close_requested()) && !self.exit_confirmed {
+            ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            self.request_close(PendingClose::Exit);
+        }
+        let font_size = self.config.editor.font_size as f32;
+        if self.applied_font_size != Some(font_size) {
+            ctx.style_mut(|style| {
+                if let Some(font) = style.text_styles.get_mut(&egui::TextStyle::Monospace) {
+                    font.size = font_size;
+                }
+            });
+            self.applied_font_size = Some(font_size);
+        }
         self.process_shortcuts(ctx);
+        let held = ctx.input(|i| i.modifiers.any());
+        for group in self.panes.values_mut() {
+            group.track_recent(held);
+        }
         self.handle_page_navigation(ctx);
+        self.poll_disk_changes(ctx);
+        if let Some(journal) = &mut self.journal {
+            journal.update(self.panes.values().flat_map(|group| &group.docs), Instant::now());
+        }
+        self.run_autosave(ctx);
+        // Work nobody waits on reports its failures here
+        let failure = history::take_failure()
+            .or_else(|| self.journal.as_ref().and_then(Journal::take_failure))
+            .or_else(|| self.recent.take_failure());
+        if let Some(failure) = failure {
+            self.status = failure;
+        }
 
         // Top menu
+        let modal_open = self.confirm.is_some();
+        let mut recent_choice = None;
+        let mut clear_recent = false;
         egui::TopBottomPanel::top("menu").show(ctx, |ui| {
+            ui.set_enabled(!modal_open);
             ui.horizontal_wrapped(|ui| {
                 if ui.button("📋 Command Palette").clicked() {
                     if self.show_command_palette {
                         self.close_command_palette();
                     } else {
-                        self.open_command_palette();
+                        self.open_palette(PaletteMode::Commands);
                     }
                 }
                 ui.label("(Ctrl+Shift+P)");
                 ui.separator();
+                ui.menu_button("🕘 Recent", |ui| {
+                    if self.recent.files.is_empty() && self.recent.dirs.is_empty() {
+                        ui.label("No recent files");
+                        return;
+                    }
+                    for path in &self.recent.files {
+                        if ui.button(path.display().to_string()).clicked() {
+                            recent_choice = Some(PaletteTarget::File(path.clone()));
+                            ui.close_menu();
+                        }
+                    }
+                    if !self.recent.dirs.is_empty() {
+                        ui.separator();
+                        ui.label("Folders");
+                    }
+                    for dir in &self.recent.dirs {
+                        if ui.button(prompt::dir_input(dir)).clicked() {
+                            recent_choice = Some(PaletteTarget::Dir(dir.clone()));
+                            ui.close_menu();
+                        }
+                    }
+                    ui.separator();
+                    if ui.button("Clear Recent Files").clicked() {
+                        clear_recent = true;
+                        ui.close_menu();
+                    }
+                });
+                ui.separator();
                 ui.horizontal(|ui| {
                     ui.label("Save to:");
                     ui.text_edit_singleline(&mut self.manual_path);
                     if ui.button("Save").clicked() {
-                        self.manual_save();
+                        self.manual_save(PathBuf::from(self.manual_path.trim()));
                     }
                 });
                 ui.separator();
                 if ui.button("Exit").clicked() {
-                    std::process::exit(0);
+                    self.request_close(PendingClose::Exit);
                 }
             });
         });
+        match recent_choice {
+            Some(PaletteTarget::File(path)) => {
+                if let Err(e) = self.open_path_in(self.focused_pane, path) {
+                    self.status = format!("Open error: {e}");
+                }
+            }
+            Some(PaletteTarget::Dir(dir)) => self.open_dialog(self.focused_pane, Some(dir)),
+            _ => {}
+        }
+        if clear_recent {
+            self.perform_action(AppAction::ClearRecent);
+        }
+
+        if let Some(purpose) = self.encoding_picker {
+            self.encoding_picker_ui(ctx, purpose);
+        }
 
-        // Save As dialog
-        if self.show_save_as_input {
-            let mut should_save = false;
-            let mut should_cancel = false;
-            let mut save_path = String::new();
+        if !self.recoverable.is_empty()
+            && let Some(choice) = recover_dialog(ctx, &mut self.recoverable)
+        {
+            self.resolve_recovery(choice);
+        }
 
-            egui::Window::new("Save As")
-                .open(&mut self.show_save_as_input)
-                .show(ctx, |ui| {
-                    ui.label("Enter filename:");
-                    ui.text_edit_singleline(&mut self.save_as_path);
-                    ui.horizontal(|ui| {
-                        if ui.button("Save").clicked() {
-                            if !self.save_as_path.trim().is_empty() {
-                                save_path = self.save_as_path.trim().to_string();
-                                should_save = true;
-                            }
-                        }
-                        if ui.button("Cancel").clicked() {
-                            should_cancel = true;
-                        }
-                    });
-                });
+        if self.quick_saves.open {
+            let current = &self.panes[&self.focused_pane].active().text;
+            if let Some(command) = self.quick_saves.show(ctx, current) {
+                self.handle_quick_save_command(command);
+            }
+        }
 
-            if should_save {
-                self.save_to_path(std::path::PathBuf::from(save_path));
-                self.show_save_as_input = false;
-                self.save_as_path.clear();
-            } else if should_cancel {
-                self.show_save_as_input = false;
-                self.save_as_path.clear();
+        let undo_files = self.replace_undo.as_ref().map(ReplaceUndo::files);
+        if self.find_in_files.open
+            && let Some(command) = self.find_in_files.show(ctx, &self.config.search.exclude, undo_files)
+        {
+            match command {
+                SearchCommand::ApplyReplace => self.apply_replace(),
+                SearchCommand::UndoReplace => self.undo_replace(),
+                SearchCommand::Browse => self.browse_search_root(),
+                SearchCommand::Open { path, line, column } => {
+                    if self.open_path_in(self.focused_pane, path).is_ok() {
+                        self.go_to_line(ctx, line, column);
+                    }
+                }
             }
         }
 
-        // Open File dialog fallback
-        if self.show_open_input {
-            let mut should_open = false;
-            let mut should_cancel = false;
-            let mut open_path = String::new();
+        if self.show_keymap {
+            let (open, edit) = keymap_window(ctx, &self.keymap, &self.actions);
+            self.show_keymap = open;
+            if edit {
+                self.open_keymap_file();
+            }
+        }
 
-            egui::Window::new("Open File")
-                .open(&mut self.show_open_input)
-                .show(ctx, |ui| {
-                    ui.label("Enter file path:");
-                    ui.text_edit_singleline(&mut self.open_input_path);
-                    ui.horizontal(|ui| {
-                        if ui.button("Open").clicked() {
-                            if !self.open_input_path.trim().is_empty() {
-                                open_path = self.open_input_path.trim().to_string();
-                                should_open = true;
-                            }
-                        }
-                        if ui.button("Cancel").clicked() {
-                            should_cancel = true;
-                        }
-                    });
-                });
+        if let Some(mut panel) = self.file_history.take() {
+            let doc_id = panel.doc_id;
+            // Closing the document closes its history too
+            let (open, restore) = match self.find_doc(doc_id) {
+                Some(doc) => panel.show(ctx, &doc.text),
+                None => (false, None),
+            };
+            if open {
+                self.file_history = Some(panel);
+            }
+            if let Some(text) = restore
+                && let Some(doc) = self.find_doc_mut(doc_id)
+            {
+                doc.replace_text(text);
+                self.status = format!("Restored an earlier revision of {}", doc.title);
+            }
+        }
 
-            if should_open {
-                let path = std::path::PathBuf::from(open_path);
-                let target = if self.open_to_left {
-                    &mut self.left
-                } else {
-                    &mut self.right
-                };
-                self.status = format!("Loading: {}", path.display());
-                if let Err(e) = target.load_from(path) {
-                    self.status = format!("Open error: {e}");
-                } else {
-                    self.status = "File opened".into();
-                    self.pending_focus = Some(if self.open_to_left {
-                        FocusedPane::Left
-                    } else {
-                        FocusedPane::Right
-                    });
-                    self.focused_pane = if self.open_to_left {
-                        FocusedPane::Left
-                    } else {
-                        FocusedPane::Right
-                    };
-                }
-                self.show_open_input = false;
-                self.open_input_path.clear();
-            } else if should_cancel {
-                self.show_open_input = false;
-                self.open_input_path.clear();
+        if let Some(mut compare) = self.disk_compare.take() {
+            let doc_id = compare.doc_id;
+            let (open, choice) = match self.find_doc(doc_id) {
+                Some(doc) => compare_window(ctx, &mut compare, &doc.text),
+                None => (false, None),
+            };
+            if open {
+                self.disk_compare = Some(compare);
+            }
+            if let Some(choice) = choice {
+                self.resolve_disk_conflict(doc_id, choice);
             }
         }
 
@@ -277,129 +664,229 @@ impl eframe::App for App {
             ui.horizontal(|ui| {
                 ui.label(&self.status);
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
-                    let focused = if self.focused_pane == FocusedPane::Left {
-                        "Left"
+                    let leaves = self.layout.leaves();
+                    let position = leaves
+                        .iter()
+                        .position(|id| *id == self.focused_pane)
+                        .map_or(0, |idx| idx + 1);
+                    ui.label(format!(
+                        "Focused: {} ({}/{})",
+                        self.focused_pane,
+                        position,
+                        leaves.len()
+                    ));
+                    ui.separator();
+                    let group = &self.panes[&self.focused_pane];
+                    if let Some(summary) = group.find.as_ref().and_then(FindBar::summary) {
+                        ui.label(summary);
+                        ui.separator();
+                    }
+                    let doc = self.panes[&self.focused_pane].active();
+                    if doc.cursors.count() > 1 {
+                        ui.label(format!("{} carets", doc.cursors.count()));
+                        ui.separator();
+                    }
+                    ui.label(doc.encoding.to_string());
+                    ui.separator();
+                    if doc.mixed_endings {
+                        ui.colored_label(
+                            ui.visuals().warn_fg_color,
+                            format!("Mixed → {}", doc.line_ending),
+                        )
+                        .on_hover_text(format!(
+                            "This file mixes LF and CRLF line endings; saving writes them all as {}",
+                            doc.line_ending
+                        ));
                     } else {
-                        "Right"
-                    };
-                    ui.label(format!("Focused: {}", focused));
+                        ui.label(doc.line_ending.to_string());
+                    }
+                    if self.autosave.mode != AutosaveMode::Off {
+                        ui.separator();
+                        if doc.skip_autosave {
+                            ui.weak("Autosave: off for this document");
+                        } else {
+                            ui.label(format!("Autosave: {}", self.autosave.mode.label()));
+                        }
+                    }
                 });
             });
         });
 
-        // Left / right panes
+        // Pane layout
         let pending_focus = self.pending_focus;
-        if self.show_split_view {
-            egui::SidePanel::left("left")
-                .resizable(true)
-                .default_width(420.0)
-                .show(ctx, |ui| {
-                    let want_focus = pending_focus == Some(FocusedPane::Left);
-                    if pane_widget(ui, &mut self.left, "left", want_focus, self.word_wrap) {
-                        self.focused_pane = FocusedPane::Left;
-                    }
-                });
-            egui::CentralPanel::default().show(ctx, |ui| {
-                let want_focus = pending_focus == Some(FocusedPane::Right);
-                if pane_widget(ui, &mut self.right, "right", want_focus, self.word_wrap) {
-                    self.focused_pane = FocusedPane::Right;
-                }
-            });
-        } else {
-            egui::CentralPanel::default().show(ctx, |ui| match self.focused_pane {
-                FocusedPane::Left => {
-                    let want_focus = pending_focus == Some(FocusedPane::Left);
-                    if pane_widget(ui, &mut self.left, "left", want_focus, self.word_wrap) {
-                        self.focused_pane = FocusedPane::Left;
-                    }
+        let word_wrap = self.word_wrap;
+        let panes = &mut self.panes;
+        let mut focused = self.focused_pane;
+        let mut tab_commands = Vec::new();
+        let mut conflict_choices = Vec::new();
+        let mut show_pane = |ui: &mut egui::Ui, id: PaneId| {
+            if let Some(group) = panes.get_mut(&id) {
+                let (had_focus, command, conflict) =
+                    pane_widget(ui, group, id, pending_focus == Some(id), word_wrap);
+                if had_focus {
+                    focused = id;
                 }
-                FocusedPane::Right => {
-                    let want_focus = pending_focus == Some(FocusedPane::Right);
-                    if pane_widget(ui, &mut self.right, "right", want_focus, self.word_wrap) {
-                        self.focused_pane = FocusedPane::Right;
-                    }
+                if let Some(command) = command {
+                    tab_commands.push((id, command));
                 }
-            });
-        }
+                conflict_choices.extend(conflict);
+            }
+        };
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.set_enabled(!modal_open);
+            let rect = ui.available_rect_before_wrap();
+            if self.maximized {
+                let mut single = Layout::Leaf(self.focused_pane);
+                single.show(ui, rect, egui::Id::new("pane_layout"), &mut show_pane);
+            } else {
+                self.layout
+                    .show(ui, rect, egui::Id::new("pane_layout"), &mut show_pane);
+            }
+            ui.allocate_rect(rect, egui::Sense::hover());
+        });
+        self.focused_pane = focused;
         self.pending_focus = None;
+        for (pane_id, command) in tab_commands {
+            self.handle_tab_command(pane_id, command);
+        }
+        for (doc_id, choice) in conflict_choices {
+            self.resolve_disk_conflict(doc_id, choice);
+        }
 
         if self.show_command_palette {
             self.command_palette_ui(ctx);
         }
+
+        if let Some(confirm) = &self.confirm
+            && let Some(choice) = confirm_discard_ui(ctx, confirm)
+        {
+            self.resolve_confirm(choice);
+        }
+        if self.exit_confirmed {
+            if let Some(journal) = self.journal.take() {
+                journal.finish();
+            }
+            history::flush();
+            if self.session_enabled
+                && let Err(e) = session::save(&self.capture_session())
+            {
+                eprintln!("Could not save session: {e:#}");
+            }
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+        }
     }
 }
 
-fn format_shortcut(shortcut: &KeyboardShortcut) -> String {
-    let mut parts: Vec<String> = Vec::new();
-    if shortcut.modifiers.ctrl {
-        parts.push("Ctrl".into());
-    }
-    if shortcut.modifiers.shift {
-        parts.push("Shift".into());
-    }
-    if shortcut.modifiers.alt {
-        parts.push("Alt".into());
-    }
-    if shortcut.modifiers.mac_cmd {
-        parts.push("Cmd".into());
-    }
-    parts.push(format!("{:?}", shortcut.logical_key));
-    parts.join("+")
+/// Id of a document's editor.
+fn edit_id(doc_id: u64) -> egui::Id {
+    egui::Id::new(format!("pane_edit_{doc_id}"))
 }
 
-fn pane_widget(ui: &mut egui::Ui, pane: &mut Pane, pane_id: &str, request_focus: bool, word_wrap: bool) -> bool {
-    let title = if pane.dirty {
-        format!("{} •", pane.title)
-    } else {
-        pane.title.clone()
-    };
-    ui.heading(title);
+fn pane_widget(
+    ui: &mut egui::Ui,
+    group: &mut TabGroup,
+    pane_id: PaneId,
+    request_focus: bool,
+    word_wrap: bool,
+) -> (bool, Option<TabCommand>, Option<(u64, ConflictChoice)>) {
+    let (tab_clicked, command) = tab_bar(ui, group, pane_id);
+    let mut request_focus = request_focus || tab_clicked;
     ui.add_space(6.0);
+    let pane = &mut group.docs[group.active];
+    let conflict = pane
+        .disk_change
+        .and_then(|change| changed_on_disk_banner(ui, change))
+        .map(|choice| (pane.doc_id, choice));
+    let edit_id = edit_id(pane.doc_id);
+    if let Some(bar) = &mut group.find {
+        if bar.focus {
+            // Search from the caret, for the selection if there is one
+            let range = pane.cursors.primary().range();
+            let rope = pane.text.rope();
+            let one_line = rope.char_to_line(range.start) == rope.char_to_line(range.end);
+            let selected = one_line.then(|| pane.text.slice(range.clone()));
+            bar.seed(selected.as_deref(), pane.text.char_to_byte(range.start));
+        }
+        match bar.show(ui) {
+            Some(FindCommand::Next) => bar.step(&pane.text, true),
+            Some(FindCommand::Previous) => bar.step(&pane.text, false),
+            Some(FindCommand::Replace) => {
+                if let Some((range, with)) = bar.replace_current(&pane.text) {
+                    pane.replace_range(range, &with);
+                }
+            }
+            Some(FindCommand::ReplaceAll) => {
+                if let Some(text) = bar.replace_all(&pane.text) {
+                    pane.replace_text(text);
+                }
+            }
+            Some(FindCommand::Close) => {
+                group.find = None;
+                request_focus = true;
+            }
+            None => {}
+        }
+    }
+    let find_focus = group.find.as_ref().is_some_and(|bar| bar.has_focus);
+    let shading = match &mut group.find {
+        Some(bar) => {
+            let reveal = std::mem::take(&mut bar.reveal);
+            let (ranges, current) = bar.search(&pane.text);
+            // Select the match just found, and scroll to it
+            if reveal && let Some(range) = current.map(|idx| &ranges[idx]) {
+                let chars = |b: usize| pane.text.byte_to_char(b);
+                pane.cursors.select(chars(range.start)..chars(range.end));
+            }
+            Shading { ranges, current }
+        }
+        None => Shading::default(),
+    };
     // Include word_wrap in the scroll ID so it gets recreated when word wrap changes
-    let scroll_id = egui::Id::new(format!("pane_scroll_{pane_id}_{word_wrap}"));
+    let scroll_id = egui::Id::new(format!("pane_scroll_{}_{word_wrap}", pane.doc_id));
     let mut had_focus = false;
-    let output = egui::ScrollArea::vertical()
+    let output = egui::ScrollArea::new([!word_wrap, true])
         .auto_shrink([false, false])
         .id_source(scroll_id)
         .vertical_scroll_offset(pane.scroll_offset)
         .show(ui, |ui| {
-            // Get available width to ensure TextEdit fills the panel
-            let available_width = ui.available_width();
-            
-            // Include word_wrap in the edit ID so egui knows to recreate the widget
-            let edit_id = egui::Id::new(format!("pane_edit_{pane_id}_{word_wrap}"));
-            
-            let mut edit = egui::TextEdit::multiline(&mut pane.text)
-                .code_editor()
-                .desired_rows(30)
-                .lock_focus(false)
-                .id(edit_id);
-            
-            if word_wrap {
-                // Word wrap: use available width so text wraps within the panel
-                edit = edit.desired_width(available_width);
-            } else {
-                // No word wrap: use infinite width for horizontal scrolling
-                edit = edit.desired_width(f32::INFINITY);
-            }
-            
-            let resp = ui.add(edit);
+            let output = editor::show(ui, edit_id, &mut pane.text, &mut pane.cursors, word_wrap, shading);
             if request_focus {
-                resp.request_focus();
+                output.response.request_focus();
+            }
+            if !output.edits.is_empty() {
+                pane.last_edit = Some(Instant::now());
+            }
+            for edits in output.edits {
+                pane.history.record(edits, Instant::now());
             }
-            if resp.changed() {
-                pane.dirty = true;
+            if output.moved {
+                pane.history.seal();
             }
-            had_focus = resp.has_focus();
+            had_focus = output.response.has_focus() || find_focus;
         });
     pane.scroll_offset = output.state.offset.y;
     pane.scroll_viewport = output.inner_rect.height();
     pane.scroll_content = output.content_size.y;
-    had_focus
+    (had_focus || tab_clicked, command, conflict)
 }
 
 impl App {
     fn registered_actions() -> Vec<Action> {
+        const OPEN_PATH: &[ArgSpec] = &[ArgSpec::new("File to open", ArgKind::Path)];
+        const SAVE_PATH: &[ArgSpec] = &[ArgSpec::new("Save to", ArgKind::Path)];
+        const TAB_TITLE: &[ArgSpec] = &[ArgSpec::new("Tab title", ArgKind::Text)];
+        const BACKUP_MODE: &[ArgSpec] = &[ArgSpec::new(
+            "Keep a copy of the previous version as",
+            ArgKind::Choice(&["off", "file~", "file.bak"]),
+        )];
+        const AUTOSAVE_MODE: &[ArgSpec] = &[ArgSpec::new(
+            "Save automatically",
+            ArgKind::Choice(&["off", "after idle", "on focus loss", "on pane switch"]),
+        )];
+        const IDLE_SECS: &[ArgSpec] = &[ArgSpec::new(
+            "Seconds without edits before saving",
+            ArgKind::Number { min: 1, max: 3600 },
+        )];
         let ctrl = Modifiers {
             ctrl: true,
             ..Default::default()
@@ -414,8 +901,7 @@ impl App {
             alt: true,
             ..Default::default()
         };
-        let ctrl_shift_tab = Modifiers {
-            ctrl: true,
+        let shift = Modifiers {
             shift: true,
             ..Default::default()
         };
@@ -426,6 +912,25 @@ impl App {
                 Some(KeyboardShortcut::new(ctrl, egui::Key::O)),
                 AppAction::OpenFile,
             ),
+            Action::new(
+                "open_path",
+                "Open File by Path",
+                None,
+                AppAction::OpenPath,
+            )
+            .with_args(OPEN_PATH),
+            Action::new(
+                "open_recent",
+                "Open Recent",
+                Some(KeyboardShortcut::new(ctrl, egui::Key::R)),
+                AppAction::OpenRecent,
+            ),
+            Action::new(
+                "clear_recent",
+                "Clear Recent Files",
+                None,
+                AppAction::ClearRecent,
+            ),
             Action::new(
                 "save_file",
                 "Save",
@@ -449,23 +954,74 @@ impl App {
                 "Manual Save (to path)",
                 None,
                 AppAction::ManualSaveFocused,
+            )
+            .with_args(SAVE_PATH),
+            Action::new(
+                "save_file_as_path",
+                "Save As by Path",
+                None,
+                AppAction::SaveAsPath,
+            )
+            .with_args(SAVE_PATH),
+            Action::new(
+                "new_tab",
+                "New Tab",
+                Some(KeyboardShortcut::new(ctrl, egui::Key::N)),
+                AppAction::NewTab,
             ),
             Action::new(
-                "close_file",
-                "Close File (Focused Pane)",
+                "close_tab",
+                "Close Tab",
                 Some(KeyboardShortcut::new(ctrl, egui::Key::W)),
-                AppAction::CloseFocused,
+                AppAction::CloseTab,
+            ),
+            Action::new(
+                "close_other_tabs",
+                "Close Other Tabs",
+                None,
+                AppAction::CloseOtherTabs,
+            ),
+            Action::new(
+                "rename_tab",
+                "Rename Untitled Tab",
+                None,
+                AppAction::RenameTab,
+            )
+            .with_args(TAB_TITLE),
+            Action::new(
+                "next_tab",
+                "Next Tab",
+                Some(KeyboardShortcut::new(ctrl, egui::Key::PageDown)),
+                AppAction::NextTab,
+            ),
+            Action::new(
+                "previous_tab",
+                "Previous Tab",
+                Some(KeyboardShortcut::new(ctrl, egui::Key::PageUp)),
+                AppAction::PreviousTab,
+            ),
+            Action::new(
+                "recent_tab",
+                "Switch to Recently Used Tab",
+                Some(KeyboardShortcut::new(ctrl, egui::Key::Tab)),
+                AppAction::RecentTab,
+            ),
+            Action::new(
+                "recent_tab_back",
+                "Switch to Recently Used Tab (Backwards)",
+                Some(KeyboardShortcut::new(ctrl_shift, egui::Key::Tab)),
+                AppAction::RecentTabBack,
             ),
             Action::new(
                 "focus_next",
                 "Focus Next Pane",
-                Some(KeyboardShortcut::new(ctrl, egui::Key::Tab)),
+                Some(KeyboardShortcut::new(Modifiers::NONE, egui::Key::F6)),
                 AppAction::FocusNextPane,
             ),
             Action::new(
                 "focus_previous",
                 "Focus Previous Pane",
-                Some(KeyboardShortcut::new(ctrl_shift_tab, egui::Key::Tab)),
+                Some(KeyboardShortcut::new(shift, egui::Key::F6)),
                 AppAction::FocusPreviousPane,
             ),
             Action::new(
@@ -475,16 +1031,40 @@ impl App {
                 AppAction::ShowSplitView,
             ),
             Action::new(
-                "layout_left",
-                "Show Left Only",
+                "layout_single",
+                "Show Focused Pane Only",
                 Some(KeyboardShortcut::new(ctrl, egui::Key::Num1)),
-                AppAction::ShowLeftOnly,
+                AppAction::ShowFocusedOnly,
+            ),
+            Action::new(
+                "split_right",
+                "Split Pane Right",
+                Some(KeyboardShortcut::new(ctrl, egui::Key::Backslash)),
+                AppAction::SplitRight,
             ),
             Action::new(
-                "layout_right",
-                "Show Right Only",
-                Some(KeyboardShortcut::new(ctrl, egui::Key::Num2)),
-                AppAction::ShowRightOnly,
+                "split_down",
+                "Split Pane Down",
+                Some(KeyboardShortcut::new(ctrl_alt, egui::Key::Backslash)),
+                AppAction::SplitDown,
+            ),
+            Action::new(
+                "close_pane",
+                "Close Pane",
+                Some(KeyboardShortcut::new(ctrl_shift, egui::Key::W)),
+                AppAction::ClosePane,
+            ),
+            Action::new(
+                "move_pane_forward",
+                "Move Pane Forward",
+                None,
+                AppAction::MovePaneForward,
+            ),
+            Action::new(
+                "move_pane_backward",
+                "Move Pane Backward",
+                None,
+                AppAction::MovePaneBackward,
             ),
             Action::new(
                 "toggle_word_wrap",
@@ -499,6 +1079,189 @@ impl App {
                 )),
                 AppAction::ToggleWordWrap,
             ),
+            Action::new(
+                "cycle_backup_mode",
+                "Cycle Save Backups (off / file~ / file.bak)",
+                None,
+                AppAction::CycleBackupMode,
+            ),
+            Action::new(
+                "set_backup_mode",
+                "Set Save Backups",
+                None,
+                AppAction::SetBackupMode,
+            )
+            .with_args(BACKUP_MODE),
+            Action::new(
+                "open_settings",
+                "Open Settings",
+                None,
+                AppAction::OpenSettings,
+            ),
+            Action::new(
+                "go_to_file",
+                "Go to File",
+                Some(KeyboardShortcut::new(ctrl, egui::Key::P)),
+                AppAction::GoToFile,
+            ),
+            Action::new(
+                "go_to_symbol",
+                "Go to Symbol in Document",
+                Some(KeyboardShortcut::new(ctrl_shift, egui::Key::O)),
+                AppAction::GoToSymbol,
+            ),
+            Action::new(
+                "go_to_line",
+                "Go to Line",
+                Some(KeyboardShortcut::new(ctrl, egui::Key::G)),
+                AppAction::GoToLine,
+            ),
+            Action::new(
+                "find",
+                "Find",
+                Some(KeyboardShortcut::new(ctrl, egui::Key::F)),
+                AppAction::Find,
+            ),
+            Action::new(
+                "replace",
+                "Find and Replace",
+                Some(KeyboardShortcut::new(ctrl, egui::Key::H)),
+                AppAction::Replace,
+            ),
+            Action::new(
+                "find_next",
+                "Find Next",
+                Some(KeyboardShortcut::new(Modifiers::NONE, egui::Key::F3)),
+                AppAction::FindNext,
+            ),
+            Action::new(
+                "find_previous",
+                "Find Previous",
+                Some(KeyboardShortcut::new(shift, egui::Key::F3)),
+                AppAction::FindPrevious,
+            ),
+            Action::new(
+                "find_in_files",
+                "Find in Files",
+                Some(KeyboardShortcut::new(ctrl_shift, egui::Key::F)),
+                AppAction::FindInFiles,
+            ),
+            Action::new(
+                "undo_replace",
+                "Undo Replace in Files",
+                None,
+                AppAction::UndoReplace,
+            ),
+            Action::new(
+                "show_keymap",
+                "Show Keymap",
+                None,
+                AppAction::ShowKeymap,
+            ),
+            Action::new(
+                "show_quick_saves",
+                "Show Quick Saves",
+                None,
+                AppAction::ShowQuickSaves,
+            ),
+            Action::new(
+                "show_file_history",
+                "Show File History",
+                None,
+                AppAction::ShowFileHistory,
+            ),
+            Action::new(
+                "cycle_autosave_mode",
+                "Cycle Autosave (off / idle / focus loss / pane switch)",
+                None,
+                AppAction::CycleAutosaveMode,
+            ),
+            Action::new(
+                "set_autosave_mode",
+                "Set Autosave",
+                None,
+                AppAction::SetAutosaveMode,
+            )
+            .with_args(AUTOSAVE_MODE),
+            Action::new(
+                "set_autosave_idle",
+                "Autosave After Idle Seconds",
+                None,
+                AppAction::SetAutosaveIdle,
+            )
+            .with_args(IDLE_SECS),
+            Action::new(
+                "toggle_doc_autosave",
+                "Toggle Autosave for This Document",
+                None,
+                AppAction::ToggleDocAutosave,
+            ),
+            Action::new(
+                "reopen_with_encoding",
+                "Reopen with Encoding",
+                None,
+                AppAction::ReopenWithEncoding,
+            ),
+            Action::new(
+                "save_with_encoding",
+                "Save with Encoding",
+                None,
+                AppAction::SaveWithEncoding,
+            ),
+            Action::new(
+                "line_endings_lf",
+                "Convert Line Endings to LF",
+                None,
+                AppAction::ConvertToLf,
+            ),
+            Action::new(
+                "line_endings_crlf",
+                "Convert Line Endings to CRLF",
+                None,
+                AppAction::ConvertToCrlf,
+            ),
+            Action::new(
+                "quit",
+                "Quit",
+                Some(KeyboardShortcut::new(ctrl, egui::Key::Q)),
+                AppAction::Quit,
+            ),
+            Action::new(
+                "undo",
+                "Undo",
+                Some(KeyboardShortcut::new(ctrl, egui::Key::Z)),
+                AppAction::Undo,
+            ),
+            Action::new(
+                "redo",
+                "Redo",
+                Some(KeyboardShortcut::new(ctrl_shift, egui::Key::Z)),
+                AppAction::Redo,
+            ),
+            Action::new(
+                "add_caret_above",
+                "Add Caret Above",
+                Some(KeyboardShortcut::new(ctrl_alt, egui::Key::ArrowUp)),
+                AppAction::AddCaretAbove,
+            ),
+            Action::new(
+                "add_caret_below",
+                "Add Caret Below",
+                Some(KeyboardShortcut::new(ctrl_alt, egui::Key::ArrowDown)),
+                AppAction::AddCaretBelow,
+            ),
+            Action::new(
+                "add_next_occurrence",
+                "Add Next Occurrence to Selection",
+                Some(KeyboardShortcut::new(ctrl, egui::Key::D)),
+                AppAction::AddNextOccurrence,
+            ),
+            Action::new(
+                "select_all_occurrences",
+                "Select All Occurrences",
+                Some(KeyboardShortcut::new(ctrl_shift, egui::Key::L)),
+                AppAction::SelectAllOccurrences,
+            ),
         ]
     }
 
@@ -514,12 +1277,15 @@ impl App {
     }
 
     fn process_shortcuts(&mut self, ctx: &egui::Context) {
+        if self.confirm.is_some() {
+            return;
+        }
         if ctx.input_mut(|i| i.consume_shortcut(&Self::command_palette_shortcut())) {
             if self.show_command_palette {
                 self.close_command_palette();
                 self.status = "Command palette closed".into();
             } else {
-                self.open_command_palette();
+                self.open_palette(PaletteMode::Commands);
             }
         }
 
@@ -527,18 +1293,24 @@ impl App {
             return;
         }
 
-        let actions: Vec<Action> = self.actions.iter().copied().collect();
-        for action in actions {
-            if let Some(shortcut) = action.shortcut {
-                if ctx.input_mut(|i| i.consume_shortcut(&shortcut)) {
+        match self.keymap.process(ctx, Instant::now()) {
+            Some(KeyEvent::Matched(id)) => {
+                if let Some(action) = self.actions.iter().find(|action| action.id == id) {
                     self.perform_action(action.action);
                 }
             }
+            Some(KeyEvent::Pending(typed)) => {
+                self.status = format!("{typed} pressed, waiting for the next key…");
+            }
+            Some(KeyEvent::Cancelled(typed)) => {
+                self.status = format!("{typed} is not bound to anything");
+            }
+            None => {}
         }
     }
 
     fn handle_page_navigation(&mut self, ctx: &egui::Context) {
-        if self.show_command_palette {
+        if self.show_command_palette || self.confirm.is_some() {
             return;
         }
         let (page_down, page_up) = ctx.input_mut(|i| {
@@ -549,11 +1321,7 @@ impl App {
         if !page_down && !page_up {
             return;
         }
-        let pane = if self.focused_pane == FocusedPane::Left {
-            &mut self.left
-        } else {
-            &mut self.right
-        };
+        let pane = self.focused_mut();
         let viewport = pane.scroll_viewport.max(1.0);
         let max_offset = (pane.scroll_content - viewport).max(0.0);
         let page_delta = viewport * 0.9;
@@ -565,10 +1333,12 @@ impl App {
         }
     }
 
-    fn open_command_palette(&mut self) {
+    /// Open the palette with `mode`'s prefix already typed.
+    fn open_palette(&mut self, mode: PaletteMode) {
         self.show_command_palette = true;
-        self.command_palette_query.clear();
+        self.command_palette_query = mode.prefix().to_owned();
         self.command_palette_selected = 0;
+        self.keymap.reset();
         self.status = "Command palette opened (Ctrl+Shift+P or Esc to close)".into();
     }
 
@@ -576,12 +1346,333 @@ impl App {
         self.show_command_palette = false;
         self.command_palette_query.clear();
         self.command_palette_selected = 0;
+        self.palette_files = None;
+        self.palette_symbols = None;
+        self.palette_results = None;
+        self.palette_prompt = None;
+    }
+
+    /// Where Open dialogs and prompts start: the directory a file was last
+    /// opened or saved in, else the focused document's, else the working one.
+    fn open_dir(&self) -> Option<PathBuf> {
+        let doc = self.panes[&self.focused_pane].active();
+        self.recent
+            .last_dir()
+            .or_else(|| doc.path.as_deref().and_then(Path::parent))
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .map(Path::to_path_buf)
+            .or_else(|| std::env::current_dir().ok())
+    }
+
+    /// Where Save As starts: next to the document if it has a file already.
+    fn save_dir(&self) -> Option<PathBuf> {
+        let doc = self.panes[&self.focused_pane].active();
+        doc.path
+            .as_deref()
+            .and_then(Path::parent)
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .map(Path::to_path_buf)
+            .or_else(|| self.open_dir())
+    }
+
+    /// Open the palette to ask for `action`'s arguments, the first one
+    /// prefilled with `input` or, failing that, a sensible guess.
+    fn start_prompt(&mut self, action: AppAction, input: Option<String>) {
+        let Some(action) = self.actions.iter().find(|a| a.action == action).copied() else {
+            return;
+        };
+        let doc = self.panes[&self.focused_pane].active();
+        let input = input.unwrap_or_else(|| match action.action {
+            AppAction::OpenPath => self.open_dir().as_deref().map(prompt::dir_input).unwrap_or_default(),
+            AppAction::SaveAsPath => match &doc.path {
+                Some(path) => path.display().to_string(),
+                None => self.save_dir().as_deref().map(prompt::dir_input).unwrap_or_default(),
+            },
+            AppAction::ManualSaveFocused => self.manual_path.clone(),
+            AppAction::RenameTab => doc.title.clone(),
+            _ => String::new(),
+        });
+        self.show_command_palette = true;
+        self.keymap.reset();
+        self.palette_prompt = Some(Prompt::new(action, input));
+    }
+
+    /// Run an action whose arguments the palette has collected.
+    fn perform_with_args(&mut self, action: AppAction, args: Vec<ArgValue>) {
+        match (action, args.as_slice()) {
+            (AppAction::OpenPath, [ArgValue::Path(path)]) => {
+                // Likely a typo: ask again so the path can be fixed
+                if let Err(LoadError::NotFound(_) | LoadError::PermissionDenied(_)) =
+                    self.open_path_in(self.focused_pane, path.clone())
+                {
+                    self.start_prompt(AppAction::OpenPath, Some(path.display().to_string()));
+                }
+            }
+            (AppAction::ManualSaveFocused, [ArgValue::Path(path)]) => self.manual_save(path.clone()),
+            (AppAction::SaveAsPath, [ArgValue::Path(path)]) => self.save_to_path(path.clone()),
+            (AppAction::RenameTab, [ArgValue::Text(title)]) => {
+                let doc = self.focused_mut();
+                if doc.path.is_some() {
+                    self.status = "Only untitled tabs can be renamed; use Save As for files".into();
+                } else {
+                    doc.title = title.clone();
+                    self.status = format!("Tab renamed to {title}");
+                }
+            }
+            (AppAction::SetBackupMode, [ArgValue::Choice(idx)]) => {
+                self.backup_mode = [BackupMode::Off, BackupMode::Tilde, BackupMode::Bak][*idx];
+                self.status = format!("Save backups: {}", self.backup_mode.label());
+            }
+            (AppAction::SetAutosaveMode, [ArgValue::Choice(idx)]) => {
+                let idle = match self.autosave.mode {
+                    AutosaveMode::Idle(secs) => secs,
+                    _ => self.config.saving.autosave_idle_secs,
+                };
+                self.autosave.mode = [
+                    AutosaveMode::Off,
+                    AutosaveMode::Idle(idle),
+                    AutosaveMode::FocusLoss,
+                    AutosaveMode::PaneSwitch,
+                ][*idx];
+                self.status = format!("Autosave {}", self.autosave.mode.label());
+            }
+            (AppAction::SetAutosaveIdle, [ArgValue::Number(secs)]) => {
+                self.autosave.mode = AutosaveMode::Idle(*secs as u64);
+                self.status = format!("Autosave {}", self.autosave.mode.label());
+            }
+            _ => self.status = "Unexpected arguments for this command".into(),
+        }
+    }
+
+    /// Put the cursor of the focused document at `line`/`column` (zero-based)
+    /// and scroll the line into view.
+    fn go_to_line(&mut self, ctx: &egui::Context, line: usize, column: usize) {
+        let row_height = ctx.fonts(|fonts| {
+            fonts.row_height(&egui::TextStyle::Monospace.resolve(&ctx.style()))
+        });
+        // The editor spaces rows a whole number of pixels apart
+        let row_height = (row_height * ctx.pixels_per_point()).round() / ctx.pixels_per_point();
+        let pane_id = self.focused_pane;
+        let doc = self.focused_mut();
+        let line = line.min(doc.text.line_count() - 1);
+        doc.cursors.place(doc.text.char_offset(line, column));
+        // A third of the way down the view; with word wrap this is a guess,
+        // and the editor scrolls to the caret if it missed
+        doc.scroll_offset = (line as f32 * row_height - doc.scroll_viewport / 3.0).max(0.0);
+        self.pending_focus = Some(pane_id);
+        self.status = format!("Line {}", line + 1);
+    }
+
+    /// Add a caret to the focused document on the line above or below.
+    fn add_caret(&mut self, down: bool) {
+        let doc = self.focused_mut();
+        if !doc.cursors.add_vertical(&doc.text, down) {
+            self.status = format!("No line {} the caret", if down { "below" } else { "above" });
+        }
+        self.pending_focus = Some(self.focused_pane);
+    }
+
+    /// Show the focused pane's find bar (with the replace row if `replace`)
+    /// and move the keyboard there.
+    fn open_find(&mut self, replace: bool) {
+        let bar = self.focused_group_mut().find.get_or_insert_with(FindBar::default);
+        bar.show_replace = replace;
+        bar.focus = true;
+    }
+
+    /// Go to the next or previous match in the focused pane, opening the
+    /// find bar if there is nothing to look for yet.
+    fn find_step(&mut self, forward: bool) {
+        let group = self.focused_group_mut();
+        let text = &group.docs[group.active].text;
+        if let Some(bar) = &mut group.find
+            && !bar.query.is_empty()
+        {
+            bar.step(text, forward);
+        } else {
+            self.open_find(false);
+        }
+    }
+
+    /// Show the Find in Files window, starting from the focused pane's find
+    /// query and the working directory the first time.
+    fn open_find_in_files(&mut self) {
+        let group = &self.panes[&self.focused_pane];
+        let panel = &mut self.find_in_files;
+        if panel.query.is_empty()
+            && let Some(bar) = &group.find
+        {
+            panel.query = bar.query.clone();
+            panel.options = bar.options;
+        }
+        if panel.root.is_empty() {
+            let cwd = std::env::current_dir().unwrap_or_default();
+            panel.root = cwd.display().to_string();
+        }
+        panel.open = true;
+        panel.focus = true;
+    }
+
+    /// Let the user pick the folder Find in Files searches.
+    fn browse_search_root(&mut self) {
+        let mut dialog = rfd::FileDialog::new().set_title("Find in Folder");
+        let root = PathBuf::from(self.find_in_files.root.trim());
+        if root.is_dir() {
+            dialog = dialog.set_directory(root);
+        }
+        match dialog.pick_folder() {
+            Some(dir) => self.find_in_files.root = dir.display().to_string(),
+            None => self.status = "No folder picked; type its path instead".into(),
+        }
+    }
+
+    /// Write the replacements previewed in Find in Files. Files open with
+    /// unsaved edits are left alone; clean ones reload on the next disk check.
+    fn apply_replace(&mut self) {
+        let Some(mut plans) = self.find_in_files.plans.take() else {
+            return;
+        };
+        let dirty: Vec<PathBuf> = self
+            .panes
+            .values()
+            .flat_map(|group| &group.docs)
+            .filter(|doc| doc.is_dirty())
+            .filter_map(|doc| fs::canonicalize(doc.path.as_ref()?).ok())
+            .collect();
+        let before = plans.len();
+        plans.retain(|plan| fs::canonicalize(&plan.path).is_ok_and(|path| !dirty.contains(&path)));
+        let held_back = before - plans.len();
+
+        let applied = replace::apply(&plans, self.backup_mode);
+        let files = applied.undo.files();
+        let mut status = format!(
+            "Replaced {} {} in {files} {}",
+            applied.changes,
+            find::plural(applied.changes),
+            if files == 1 { "file" } else { "files" }
+        );
+        if held_back > 0 {
+            status.push_str(&format!("; {held_back} with unsaved edits left alone"));
+        }
+        if let Some(problem) = applied.problems.first() {
+            status.push_str(&format!("; skipped {problem}"));
+            if applied.problems.len() > 1 {
+                status.push_str(&format!(" and {} more", applied.problems.len() - 1));
+            }
+        }
+        self.status = status;
+        if files > 0 {
+            self.replace_undo = Some(applied.undo);
+        }
+        self.find_in_files.rerun = true;
+    }
+
+    /// Put back every file the last Find in Files replace wrote.
+    fn undo_replace(&mut self) {
+        let Some(undo) = self.replace_undo.take() else {
+            self.status = "No replace to undo".into();
+            return;
+        };
+        let (restored, problems) = undo.undo();
+        self.status = match problems.first() {
+            None => format!("Restored {restored} files"),
+            Some(problem) => format!(
+                "Restored {restored} files; left {} alone ({problem})",
+                problems.len()
+            ),
+        };
+        self.find_in_files.rerun = true;
+    }
+
+    /// Open documents first, then recent files, then files under the
+    /// working directory.
+    fn file_candidates(&mut self) -> Vec<Candidate<PaletteTarget>> {
+        let cwd = std::env::current_dir().unwrap_or_default();
+        let open: Vec<PathBuf> = self
+            .panes
+            .values()
+            .flat_map(|group| &group.docs)
+            .filter_map(|doc| doc.path.clone())
+            .collect();
+        let display = |path: &std::path::Path| {
+            path.strip_prefix(&cwd).unwrap_or(path).display().to_string()
+        };
+        let mut candidates: Vec<Candidate<PaletteTarget>> = open
+            .iter()
+            .map(|path| Candidate {
+                label: display(path),
+                alias: None,
+                detail: Some("open".into()),
+                boost: 10,
+                target: PaletteTarget::File(path.clone()),
+            })
+            .collect();
+        let mut seen = open.clone();
+        for path in &self.recent.files {
+            if seen.contains(path) {
+                continue;
+            }
+            seen.push(path.clone());
+            candidates.push(Candidate {
+                label: display(path),
+                alias: None,
+                detail: Some("recent".into()),
+                boost: 5,
+                target: PaletteTarget::File(path.clone()),
+            });
+        }
+        let files = self
+            .palette_files
+            .get_or_insert_with(|| palette::project_files(&cwd));
+        for relative in files.iter() {
+            let path = cwd.join(relative);
+            if seen.contains(&path) {
+                continue;
+            }
+            candidates.push(Candidate {
+                label: relative.display().to_string(),
+                alias: None,
+                detail: None,
+                boost: 0,
+                target: PaletteTarget::File(path),
+            });
+        }
+        candidates
+    }
+
+    /// Recent files, most recent first, then recent folders.
+    fn recent_candidates(&self) -> Vec<Candidate<PaletteTarget>> {
+        let files = self.recent.files.iter().map(|path| Candidate {
+            label: path
+                .file_name()
+                .map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned()),
+            alias: Some(path.display().to_string()),
+            detail: path.parent().map(|dir| dir.display().to_string()),
+            boost: 0,
+            target: PaletteTarget::File(path.clone()),
+        });
+        let dirs = self.recent.dirs.iter().map(|dir| Candidate {
+            label: prompt::dir_input(dir),
+            alias: None,
+            detail: Some("folder".into()),
+            boost: 0,
+            target: PaletteTarget::Dir(dir.clone()),
+        });
+        files.chain(dirs).collect()
     }
 
     fn perform_action(&mut self, action: AppAction) {
         match action {
             AppAction::OpenFile => {
-                self.open_dialog(self.focused_pane == FocusedPane::Left);
+                self.open_dialog(self.focused_pane, None);
+            }
+            AppAction::OpenRecent => {
+                self.recent.prune();
+                self.open_palette(PaletteMode::Recent);
+            }
+            AppAction::ClearRecent => {
+                self.recent.clear();
+                self.status = "Recent files cleared".into();
             }
             AppAction::SaveFocused => {
                 self.save_focused(false);
@@ -592,62 +1683,70 @@ impl App {
             AppAction::QuickSaveFocused => {
                 self.quick_save_focused();
             }
-            AppAction::ManualSaveFocused => {
-                self.manual_save();
+            AppAction::OpenPath
+            | AppAction::ManualSaveFocused
+            | AppAction::SaveAsPath
+            | AppAction::RenameTab
+            | AppAction::SetBackupMode
+            | AppAction::SetAutosaveMode
+            | AppAction::SetAutosaveIdle => self.start_prompt(action, None),
+            AppAction::CloseTab => {
+                let idx = self.focused_group_mut().active;
+                self.handle_tab_command(self.focused_pane, TabCommand::Close(idx));
+            }
+            AppAction::CloseOtherTabs => {
+                let idx = self.focused_group_mut().active;
+                self.handle_tab_command(self.focused_pane, TabCommand::CloseOthers(idx));
             }
-            AppAction::CloseFocused => {
-                self.close_focused();
+            AppAction::NewTab => {
+                self.handle_tab_command(self.focused_pane, TabCommand::New);
+            }
+            AppAction::NextTab => {
+                self.cycle_tab(1);
+            }
+            AppAction::PreviousTab => {
+                self.cycle_tab(-1);
+            }
+            AppAction::RecentTab => {
+                self.switch_recent_tab(1);
+            }
+            AppAction::RecentTabBack => {
+                self.switch_recent_tab(-1);
             }
             AppAction::FocusNextPane => {
-                self.focused_pane = match self.focused_pane {
-                    FocusedPane::Left => FocusedPane::Right,
-                    FocusedPane::Right => FocusedPane::Left,
-                };
-                self.pending_focus = Some(self.focused_pane);
-                self.status = format!(
-                    "Focused {} pane",
-                    if self.focused_pane == FocusedPane::Left {
-                        "left"
-                    } else {
-                        "right"
-                    }
-                );
+                self.cycle_focus(1);
             }
             AppAction::FocusPreviousPane => {
-                self.focused_pane = match self.focused_pane {
-                    FocusedPane::Left => FocusedPane::Right,
-                    FocusedPane::Right => FocusedPane::Left,
-                };
-                self.pending_focus = Some(self.focused_pane);
-                self.status = format!(
-                    "Focused {} pane",
-                    if self.focused_pane == FocusedPane::Left {
-                        "left"
-                    } else {
-                        "right"
-                    }
-                );
+                self.cycle_focus(-1);
             }
             AppAction::ShowSplitView => {
-                if !self.show_split_view {
-                    self.show_split_view = true;
+                if self.maximized {
+                    self.maximized = false;
                     self.pending_focus = Some(self.focused_pane);
                     self.status = "Split view enabled".into();
                 } else {
                     self.status = "Split view already active".into();
                 }
             }
-            AppAction::ShowLeftOnly => {
-                self.show_split_view = false;
-                self.focused_pane = FocusedPane::Left;
-                self.pending_focus = Some(FocusedPane::Left);
-                self.status = "Single pane mode (showing left)".into();
+            AppAction::ShowFocusedOnly => {
+                self.maximized = true;
+                self.pending_focus = Some(self.focused_pane);
+                self.status = format!("Single pane mode (showing {})", self.focused_pane);
+            }
+            AppAction::SplitRight => {
+                self.split_focused(Axis::Horizontal);
+            }
+            AppAction::SplitDown => {
+                self.split_focused(Axis::Vertical);
             }
-            AppAction::ShowRightOnly => {
-                self.show_split_view = false;
-                self.focused_pane = FocusedPane::Right;
-                self.pending_focus = Some(FocusedPane::Right);
-                self.status = "Single pane mode (showing right)".into();
+            AppAction::ClosePane => {
+                self.close_pane();
+            }
+            AppAction::MovePaneForward => {
+                self.move_focused(1);
+            }
+            AppAction::MovePaneBackward => {
+                self.move_focused(-1);
             }
             AppAction::ToggleWordWrap => {
                 self.word_wrap = !self.word_wrap;
@@ -659,32 +1758,983 @@ impl App {
                 // Force repaint to update the UI
                 // Note: egui should auto-repaint, but we can request it explicitly if needed
             }
+            AppAction::OpenSettings => {
+                let Some(path) = config::settings_path() else {
+                    self.status = "No config directory on this system".into();
+                    return;
+                };
+                if !path.exists()
+                    && let Err(e) = config::write_defaults(&path)
+                {
+                    self.status = format!("Could not create {}: {e:#}", path.display());
+                    return;
+                }
+                let _ = self.open_path_in(self.focused_pane, path);
+            }
+            AppAction::ShowKeymap => self.show_keymap = true,
+            AppAction::GoToFile => self.open_palette(PaletteMode::Files),
+            AppAction::GoToSymbol => self.open_palette(PaletteMode::Symbols),
+            AppAction::GoToLine => self.open_palette(PaletteMode::Line),
+            AppAction::Find => self.open_find(false),
+            AppAction::Replace => self.open_find(true),
+            AppAction::FindNext => self.find_step(true),
+            AppAction::FindPrevious => self.find_step(false),
+            AppAction::FindInFiles => self.open_find_in_files(),
+            AppAction::UndoReplace => self.undo_replace(),
+            AppAction::AddCaretAbove => self.add_caret(false),
+            AppAction::AddCaretBelow => self.add_caret(true),
+            AppAction::AddNextOccurrence => {
+                let doc = self.focused_mut();
+                if !doc.cursors.add_next_occurrence(&doc.text) {
+                    self.status = "No more occurrences".into();
+                }
+                self.pending_focus = Some(self.focused_pane);
+            }
+            AppAction::SelectAllOccurrences => {
+                let doc = self.focused_mut();
+                let count = doc.cursors.select_all_occurrences(&doc.text);
+                self.status = match count {
+                    0 => "Nothing to select at the caret".into(),
+                    count => format!("{count} occurrences selected"),
+                };
+                self.pending_focus = Some(self.focused_pane);
+            }
+            AppAction::ShowQuickSaves => {
+                self.quick_saves.open = true;
+                self.handle_quick_save_command(QuickSaveCommand::Refresh);
+            }
+            AppAction::ShowFileHistory => {
+                let doc = self.focused_mut();
+                match doc.path.clone() {
+                    Some(path) => {
+                        let panel = HistoryPanel::new(doc.doc_id, doc.title.clone(), &path);
+                        self.status = format!(
+                            "{} saved revision(s) of {}",
+                            panel.revisions.len(),
+                            panel.title
+                        );
+                        self.file_history = Some(panel);
+                    }
+                    None => self.status = "Only saved files have a history".into(),
+                }
+            }
+            AppAction::CycleAutosaveMode => {
+                self.autosave.mode = self.autosave.mode.next();
+                self.status = format!("Autosave: {}", self.autosave.mode.label());
+            }
+            AppAction::ToggleDocAutosave => {
+                let doc = self.focused_mut();
+                doc.skip_autosave = !doc.skip_autosave;
+                self.status = if doc.skip_autosave {
+                    format!("Autosave off for {}", doc.title)
+                } else {
+                    format!("Autosave on for {}", doc.title)
+                };
+            }
+            AppAction::CycleBackupMode => {
+                self.backup_mode = self.backup_mode.next();
+                self.status = format!("Save backups: {}", self.backup_mode.label());
+            }
+            AppAction::ReopenWithEncoding => {
+                if self.focused_mut().path.is_some() {
+                    self.encoding_picker = Some(EncodingPurpose::Reopen);
+                } else {
+                    self.status = "Only files on disk can be reopened".into();
+                }
+            }
+            AppAction::SaveWithEncoding => {
+                self.encoding_picker = Some(EncodingPurpose::Save);
+            }
+            AppAction::ConvertToLf => self.convert_line_endings(LineEnding::Lf),
+            AppAction::ConvertToCrlf => self.convert_line_endings(LineEnding::Crlf),
+            AppAction::Quit => {
+                self.request_close(PendingClose::Exit);
+            }
+            AppAction::Undo => {
+                self.status = if self.focused_mut().undo() {
+                    "Undo".into()
+                } else {
+                    "Nothing to undo".into()
+                };
+                self.pending_focus = Some(self.focused_pane);
+            }
+            AppAction::Redo => {
+                self.status = if self.focused_mut().redo() {
+                    "Redo".into()
+                } else {
+                    "Nothing to redo".into()
+                };
+                self.pending_focus = Some(self.focused_pane);
+            }
+        }
+    }
+
+    /// Snapshot open documents and window state for the next start.
+    fn capture_session(&self) -> Session {
+        let doc_state = |doc: &Pane| DocState {
+            path: doc.path.clone(),
+            text: (doc.path.is_none() && !doc.text.is_empty()).then(|| doc.text.to_string()),
+            cursor: Some(doc.cursors.primary().head),
+            scroll: doc.scroll_offset,
+        };
+        Session {
+            layout: self.layout.clone(),
+            panes: self
+                .panes
+                .iter()
+                .map(|(id, group)| PaneState {
+                    id: *id,
+                    docs: group.docs.iter().map(doc_state).collect(),
+                    active: group.active,
+                })
+                .collect(),
+            focused: self.focused_pane,
+            maximized: self.maximized,
+            word_wrap: self.word_wrap,
+            manual_path: self.manual_path.clone(),
+        }
+    }
+
+    /// Rebuild the app from a saved session. Files that can no longer be
+    /// opened are dropped and reported in the status bar.
+    fn restore_session(self, session: Session) -> Self {
+        let mut app = self;
+        if !session.is_usable() {
+            app.status = "The saved session does not match its layout; starting afresh".into();
+            return app;
+        }
+        let mut panes = BTreeMap::new();
+        let mut failed = Vec::new();
+        for state in session.panes {
+            let mut docs = Vec::new();
+            for saved in state.docs {
+                let mut doc = app.new_document();
+                if let Some(path) = saved.path {
+                    if let Err(e) = doc.load_from(path) {
+                        failed.push(e.to_string());
+                        continue;
+                    }
+                } else {
+                    doc.text = Buffer::from(saved.text.unwrap_or_default());
+                }
+                if let Some(cursor) = saved.cursor {
+                    doc.cursors = Cursors::at(cursor);
+                }
+                doc.scroll_offset = saved.scroll;
+                docs.push(doc);
+            }
+            if docs.is_empty() {
+                docs.push(app.new_document());
+            }
+            let active = state.active.min(docs.len() - 1);
+            panes.insert(state.id, TabGroup::restore(docs, active));
+        }
+        let focused = if panes.contains_key(&session.focused) {
+            session.focused
+        } else {
+            session.layout.leaves().first().copied().unwrap_or(app.focused_pane)
+        };
+        app.next_pane_id = panes.keys().map(|id| id.0).max().unwrap_or(0) + 1;
+        app.panes = panes;
+        app.layout = session.layout;
+        app.focused_pane = focused;
+        app.pending_focus = Some(focused);
+        app.maximized = session.maximized;
+        app.word_wrap = session.word_wrap;
+        app.manual_path = session.manual_path;
+        app.status = if failed.is_empty() {
+            "Session restored".into()
+        } else {
+            format!("Session restored; could not reopen {}", failed.join("; "))
+        };
+        app
+    }
+
+    fn focused_group_mut(&mut self) -> &mut TabGroup {
+        self.panes
+            .get_mut(&self.focused_pane)
+            .expect("focused pane is always in the layout")
+    }
+
+    /// The active document of the focused pane.
+    fn focused_mut(&mut self) -> &mut Pane {
+        self.focused_group_mut().active_mut()
+    }
+
+    fn new_document(&mut self) -> Pane {
+        let doc = Pane::untitled(self.next_doc_id);
+        self.next_doc_id += 1;
+        doc
+    }
+
+    fn cycle_tab(&mut self, step: isize) {
+        let group = self.focused_group_mut();
+        group.cycle(step);
+        let title = group.active().title.clone();
+        self.pending_focus = Some(self.focused_pane);
+        self.status = format!("Switched to {title}");
+    }
+
+    fn switch_recent_tab(&mut self, step: isize) {
+        let group = self.focused_group_mut();
+        group.switch_recent(step);
+        let title = group.active().title.clone();
+        self.pending_focus = Some(self.focused_pane);
+        self.status = format!("Switched to {title}");
+    }
+
+    fn handle_tab_command(&mut self, pane_id: PaneId, command: TabCommand) {
+        let Some(group) = self.panes.get(&pane_id) else {
+            return;
+        };
+        match command {
+            TabCommand::Close(idx) => {
+                if let Some(doc) = group.docs.get(idx) {
+                    let doc_id = doc.doc_id;
+                    self.request_close(PendingClose::Tab {
+                        pane: pane_id,
+                        doc_id,
+                    });
+                }
+            }
+            TabCommand::CloseOthers(idx) => {
+                if let Some(doc) = group.docs.get(idx) {
+                    let keep = doc.doc_id;
+                    self.request_close(PendingClose::OtherTabs {
+                        pane: pane_id,
+                        keep,
+                    });
+                }
+            }
+            TabCommand::New => {
+                let doc = self.new_document();
+                if let Some(group) = self.panes.get_mut(&pane_id) {
+                    group.open(doc);
+                    self.status = "New tab".into();
+                    self.focus_pane(pane_id);
+                }
+            }
+        }
+    }
+
+    /// Run `op` right away if it loses no work, otherwise ask the user first.
+    fn request_close(&mut self, op: PendingClose) {
+        let docs = self.docs_discarded_by(op);
+        if docs.is_empty() {
+            self.execute_close(op);
+        } else {
+            self.status = format!("{} document(s) have unsaved changes", docs.len());
+            self.confirm = Some(ConfirmDiscard { op, docs });
+        }
+    }
+
+    /// The unsaved documents `op` would throw away, in layout order.
+    fn docs_discarded_by(&self, op: PendingClose) -> Vec<DirtyDoc> {
+        let in_pane = |pane: PaneId| self.panes.get(&pane).into_iter().flat_map(|g| &g.docs);
+        let docs: Vec<&Pane> = match op {
+            PendingClose::Tab { pane, doc_id } | PendingClose::Reopen { pane, doc_id, .. } => {
+                in_pane(pane).filter(|doc| doc.doc_id == doc_id).collect()
+            }
+            PendingClose::OtherTabs { pane, keep } => {
+                in_pane(pane).filter(|doc| doc.doc_id != keep).collect()
+            }
+            PendingClose::Pane(pane) => in_pane(pane).collect(),
+            // Untitled scratch buffers come back with the session, so only
+            // ask about them when it is off
+            PendingClose::Exit => self
+                .layout
+                .leaves()
+                .into_iter()
+                .flat_map(in_pane)
+                .filter(|doc| !self.session_enabled || doc.path.is_some())
+                .collect(),
+        };
+        docs.into_iter()
+            .filter(|doc| doc.is_dirty())
+            .map(|doc| DirtyDoc {
+                doc_id: doc.doc_id,
+                title: doc.title.clone(),
+                location: doc
+                    .path
+                    .as_ref()
+                    .map_or_else(|| "Not saved yet".to_owned(), |p| p.display().to_string()),
+            })
+            .collect()
+    }
+
+    fn execute_close(&mut self, op: PendingClose) {
+        match op {
+            PendingClose::Tab { pane, doc_id } => self.close_tab_now(pane, doc_id),
+            PendingClose::OtherTabs { pane, keep } => {
+                if let Some(group) = self.panes.get_mut(&pane)
+                    && let Some(idx) = group.docs.iter().position(|doc| doc.doc_id == keep)
+                {
+                    group.close_others(idx);
+                    self.status = "Closed other tabs".into();
+                    self.focus_pane(pane);
+                }
+            }
+            PendingClose::Pane(pane) => self.close_pane_now(pane),
+            PendingClose::Reopen {
+                pane,
+                doc_id,
+                encoding,
+            } => {
+                let Some(doc) = self
+                    .panes
+                    .get_mut(&pane)
+                    .and_then(|group| group.docs.iter_mut().find(|doc| doc.doc_id == doc_id))
+                else {
+                    return;
+                };
+                self.status = match doc.reopen_with(encoding) {
+                    Ok(()) if doc.mixed_endings => format!(
+                        "Reopened {} as {encoding}; it mixes LF and CRLF line endings",
+                        doc.title
+                    ),
+                    Ok(()) => format!("Reopened {} as {encoding}", doc.title),
+                    Err(e) => format!("Reopen error: {e}"),
+                };
+            }
+            PendingClose::Exit => self.exit_confirmed = true,
+        }
+    }
+
+    fn resolve_confirm(&mut self, choice: ConfirmChoice) {
+        let Some(mut confirm) = self.confirm.take() else {
+            return;
+        };
+        match choice {
+            ConfirmChoice::Cancel => {
+                self.status = "Cancelled; nothing was closed".into();
+            }
+            ConfirmChoice::Discard => self.execute_close(confirm.op),
+            ConfirmChoice::Save => {
+                for entry in &confirm.docs {
+                    if let Err(e) = self.save_doc_for_close(entry.doc_id) {
+                        self.status = format!("Could not save {}: {e}", entry.title);
+                        break;
+                    }
+                }
+                // Keep asking about whatever is still unsaved
+                confirm.docs = self.docs_discarded_by(confirm.op);
+                if confirm.docs.is_empty() {
+                    self.execute_close(confirm.op);
+                } else {
+                    self.confirm = Some(confirm);
+                }
+            }
+        }
+    }
+
+    /// Save a document before closing it, asking for a path if it has none.
+    fn save_doc_for_close(&mut self, doc_id: u64) -> Result<()> {
+        let backup = self.backup_mode;
+        let dialogs = self.config.dialogs.clone();
+        let Some(doc) = self.find_doc_mut(doc_id) else {
+            return Ok(());
+        };
+        if doc.path.is_some() {
+            return doc.save(backup);
+        }
+        match dialogs.file_dialog(format!("Save {}", doc.title)).save_file()
+        {
+            Some(p) => doc.save_as(p, backup),
+            None => Err(anyhow::anyhow!("save cancelled")),
+        }
+    }
+
+    fn find_doc(&self, doc_id: u64) -> Option<&Pane> {
+        self.panes
+            .values()
+            .flat_map(|group| &group.docs)
+            .find(|doc| doc.doc_id == doc_id)
+    }
+
+    fn find_doc_mut(&mut self, doc_id: u64) -> Option<&mut Pane> {
+        self.panes
+            .values_mut()
+            .flat_map(|group| &mut group.docs)
+            .find(|doc| doc.doc_id == doc_id)
+    }
+
+    /// Check open files for outside changes: clean documents are reloaded
+    /// quietly, dirty ones get the "changed on disk" banner.
+    fn poll_disk_changes(&mut self, ctx: &egui::Context) {
+        ctx.request_repaint_after(watch::POLL_INTERVAL);
+        if !self.watcher.due(Instant::now()) {
+            return;
+        }
+        self.check_config();
+        for doc in self.panes.values_mut().flat_map(|group| &mut group.docs) {
+            let (Some(path), Some(stamp)) = (&doc.path, doc.disk_stamp) else {
+                continue;
+            };
+            // Compare against the newest version we already know about
+            let known = match doc.disk_change {
+                Some(DiskChange::Modified(newer)) => newer,
+                _ => stamp,
+            };
+            match fileio::check_disk(path, &known) {
+                None => {
+                    if doc.disk_change == Some(DiskChange::Deleted) {
+                        doc.disk_change = None;
+                    }
+                }
+                Some(change @ DiskChange::Modified(_)) if !doc.is_dirty() => {
+                    self.status = match doc.reload() {
+                        Ok(()) => format!("Reloaded {} (changed on disk)", doc.title),
+                        Err(e) => {
+                            doc.disk_change = Some(change);
+                            format!("Reload error: {e}")
+                        }
+                    };
+                }
+                Some(change) => doc.disk_change = Some(change),
+            }
+        }
+    }
+
+    /// Re-read the settings file if it changed. Invalid settings are reported
+    /// and the previous ones stay in effect.
+    fn check_config(&mut self) {
+        if let Some(watcher) = &mut self.keymap_watcher
+            && watcher.changed()
+        {
+            self.keymap = Keymap::load(&self.actions, Some(&watcher.path));
+            self.status = self.keymap_status("Keymap reloaded");
+        }
+        let Some(watcher) = &mut self.config_watcher else {
+            return;
+        };
+        if !watcher.changed() {
+            return;
+        }
+        match config::read(&watcher.path) {
+            Ok(config) => {
+                self.apply_config(config, false);
+                self.status = "Settings reloaded".into();
+            }
+            Err(e) => self.status = e,
+        }
+    }
+
+    /// `ok`, or the first keymap problem and how many there are in total.
+    fn keymap_status(&self, ok: &str) -> String {
+        match self.keymap.problems.as_slice() {
+            [] => ok.into(),
+            [only] => format!("Keymap: {only}"),
+            [first, rest @ ..] => format!(
+                "Keymap: {first} (+{} more, see Show Keymap)",
+                rest.len()
+            ),
+        }
+    }
+
+    /// Open `keymap.toml` in the focused pane, creating a template first.
+    fn open_keymap_file(&mut self) {
+        let Some(path) = keymap::keymap_path() else {
+            self.status = "No config directory on this system".into();
+            return;
+        };
+        if !path.exists()
+            && let Err(e) = keymap::write_template(&path)
+        {
+            self.status = format!("Could not create {}: {e:#}", path.display());
+            return;
+        }
+        let _ = self.open_path_in(self.focused_pane, path);
+    }
+
+    /// Take on new settings. At startup everything applies; on a reload only
+    /// the values that changed do, so runtime toggles survive edits to other keys.
+    fn apply_config(&mut self, config: Config, startup: bool) {
+        let old = std::mem::replace(&mut self.config, config);
+        let (new, old) = (&self.config, &old);
+        if startup || new.editor.word_wrap != old.editor.word_wrap {
+            self.word_wrap = new.editor.word_wrap;
+        }
+        if startup || new.saving.manual_path != old.saving.manual_path {
+            self.manual_path = new.saving.manual_path.clone();
+        }
+        if startup || new.saving.backup != old.saving.backup {
+            self.backup_mode = new.saving.backup;
+        }
+        if startup || new.saving.autosave_mode() != old.saving.autosave_mode() {
+            self.autosave.mode = new.saving.autosave_mode();
+        }
+        if startup || new.saving.retention() != old.saving.retention() {
+            self.quick_saves.policy = new.saving.retention();
+        }
+        if startup && let Layout::Split { shares, .. } = &mut self.layout {
+            let share = new.layout.split_share as f32;
+            *shares = vec![share, 1.0 - share];
+        }
+    }
+
+    fn handle_quick_save_command(&mut self, command: QuickSaveCommand) {
+        let dir = match quick_save_dir() {
+            Ok(dir) => dir,
+            Err(e) => {
+                self.status = format!("Quick saves unavailable: {e}");
+                return;
+            }
+        };
+        match command {
+            QuickSaveCommand::Restore(path) => {
+                let Some(text) = self.quick_saves.selected_text(&path).map(str::to_owned) else {
+                    return;
+                };
+                let doc = self.focused_mut();
+                doc.replace_text(text);
+                self.status = format!("Restored {} into {}", path.display(), self.focused_pane);
+                self.pending_focus = Some(self.focused_pane);
+            }
+            QuickSaveCommand::Delete(path) => {
+                self.status = match fs::remove_file(&path) {
+                    Ok(()) => format!("Deleted {}", path.display()),
+                    Err(e) => format!("Could not delete {}: {e}", path.display()),
+                };
+            }
+            QuickSaveCommand::Prune => self.prune_quick_saves(&dir),
+            QuickSaveCommand::Refresh => {}
+        }
+        self.quick_saves.refresh(&dir);
+    }
+
+    /// Apply the retention policy, sparing snapshots still open in a tab.
+    fn prune_quick_saves(&mut self, dir: &std::path::Path) {
+        let open: Vec<PathBuf> = self
+            .panes
+            .values()
+            .flat_map(|group| &group.docs)
+            .filter_map(|doc| doc.path.clone())
+            .collect();
+        match self.quick_saves.policy.prune(dir, &open) {
+            Ok(0) => {}
+            Ok(n) => self.status = format!("Pruned {n} old quick save(s)"),
+            Err(e) => self.status = format!("Pruning quick saves failed: {e}"),
+        }
+    }
+
+    fn run_autosave(&mut self, ctx: &egui::Context) {
+        if self.autosave.mode == AutosaveMode::Off {
+            return;
+        }
+        let focus = (
+            self.focused_pane,
+            self.panes[&self.focused_pane].active().doc_id,
+        );
+        let due = self.autosave.due(
+            self.panes.values().flat_map(|group| &group.docs),
+            ctx.input(|i| i.focused),
+            focus,
+            Instant::now(),
+        );
+        for doc_id in due {
+            self.autosave_doc(doc_id);
+        }
+    }
+
+    /// Save a document in place. Untitled ones keep no file of their own:
+    /// they go to the recovery journal, which offers them back after a crash.
+    fn autosave_doc(&mut self, doc_id: u64) {
+        let backup = self.backup_mode;
+        let Some(doc) = self
+            .panes
+            .values_mut()
+            .flat_map(|group| &mut group.docs)
+            .find(|doc| doc.doc_id == doc_id)
+        else {
+            return;
+        };
+        let result = if doc.path.is_some() {
+            doc.save(backup).map(|()| format!("Autosaved {}", doc.title))
+        } else if let Some(journal) = &mut self.journal {
+            journal.write(doc);
+            // Still dirty, so wait for the next edit rather than writing every frame
+            doc.last_edit = None;
+            Ok(format!("Autosaved {} to the recovery journal", doc.title))
+        } else {
+            Err(anyhow::anyhow!("no state directory for the recovery journal"))
+        };
+        self.status = match result {
+            Ok(status) => status,
+            Err(e) => {
+                // Wait for the next edit rather than retrying every frame
+                doc.last_edit = None;
+                format!("Autosave of {} failed: {e}", doc.title)
+            }
+        };
+    }
+
+    /// Act on the recover dialog. A recovered file is reopened and the
+    /// snapshot applied as one undoable edit, leaving it dirty.
+    fn resolve_recovery(&mut self, choice: RecoverChoice) {
+        let (idx, recover) = match choice {
+            RecoverChoice::Recover(idx) => (idx, true),
+            RecoverChoice::Discard(idx) => (idx, false),
+            RecoverChoice::Later => {
+                self.recoverable.clear();
+                self.status = "Unsaved documents will be offered again next start".into();
+                return;
+            }
+        };
+        let item = self.recoverable.remove(idx);
+        if let Err(e) = recovery::remove_snapshot(&item.file) {
+            self.status = format!("Recovery error: {e:#}");
+        }
+        if !recover {
+            self.status = format!("Discarded recovered {}", item.snapshot.title);
+            return;
+        }
+        let snapshot = item.snapshot;
+        let open_doc = snapshot.path.as_ref().and_then(|path| {
+            self.panes
+                .values_mut()
+                .flat_map(|group| &mut group.docs)
+                .find(|doc| doc.path.as_ref() == Some(path))
+        });
+        if let Some(doc) = open_doc {
+            doc.replace_text(snapshot.text);
+            self.status = format!("Recovered unsaved changes to {}", doc.title);
+            return;
+        }
+        let mut doc = self.new_document();
+        let loaded = snapshot.path.map(|path| doc.load_from(path));
+        self.status = match &loaded {
+            Some(Err(e)) => format!("Recovered {} as untitled: {e}", snapshot.title),
+            _ => format!("Recovered {}", snapshot.title),
+        };
+        if !matches!(loaded, Some(Ok(()))) {
+            doc.title = snapshot.title;
+        }
+        doc.replace_text(snapshot.text);
+        self.focused_group_mut().open(doc);
+        self.pending_focus = Some(self.focused_pane);
+    }
+
+    fn resolve_disk_conflict(&mut self, doc_id: u64, choice: ConflictChoice) {
+        let Some(doc) = self.find_doc_mut(doc_id) else {
+            return;
+        };
+        match choice {
+            ConflictChoice::Reload => {
+                self.status = match doc.reload() {
+                    Ok(()) => format!("Reloaded {}; Undo brings your edits back", doc.title),
+                    Err(e) => format!("Reload error: {e}"),
+                };
+                self.disk_compare = None;
+            }
+            ConflictChoice::KeepMine => {
+                match doc.disk_change.take() {
+                    Some(DiskChange::Modified(stamp)) => doc.disk_stamp = Some(stamp),
+                    // Nothing left to compare against; saving recreates the file
+                    Some(DiskChange::Deleted) => doc.disk_stamp = None,
+                    None => {}
+                }
+                self.status = format!("Keeping your version of {}", doc.title);
+                self.disk_compare = None;
+            }
+            ConflictChoice::Compare => {
+                let Some(path) = doc.path.clone() else {
+                    return;
+                };
+                match fileio::read_normalized(&path) {
+                    Ok(disk_text) => {
+                        self.disk_compare = Some(DiskCompare {
+                            doc_id,
+                            title: doc.title.clone(),
+                            disk_text,
+                            diff: Default::default(),
+                        });
+                    }
+                    Err(e) => self.status = format!("Compare error: {e}"),
+                }
+            }
+        }
+    }
+
+    fn close_tab_now(&mut self, pane_id: PaneId, doc_id: u64) {
+        let new_doc = self.new_document();
+        let Some(group) = self.panes.get_mut(&pane_id) else {
+            return;
+        };
+        let Some(idx) = group.docs.iter().position(|doc| doc.doc_id == doc_id) else {
+            return;
+        };
+        let closed = if group.docs.len() == 1 {
+            std::mem::replace(&mut group.docs[0], new_doc)
+        } else {
+            group.close(idx)
+        };
+        self.status = format!("Closed {}", closed.title);
+        self.focus_pane(pane_id);
+    }
+
+    /// Open `path` as a tab in `pane_id`, or switch to it if it is already open there.
+    /// Failures are reported in the status bar and returned so callers can react.
+    fn open_path_in(&mut self, pane_id: PaneId, path: PathBuf) -> Result<(), LoadError> {
+        let mut doc = self.new_document();
+        let Some(group) = self.panes.get_mut(&pane_id) else {
+            self.status = format!("Open error: {pane_id} was closed");
+            return Ok(());
+        };
+        if let Some(idx) = group.find_path(&path) {
+            group.active = idx;
+            self.status = format!("{} is already open", path.display());
+            self.focus_pane(pane_id);
+            return Ok(());
+        }
+        self.status = format!("Loading: {}", path.display());
+        match doc.load_from(path.clone()) {
+            Ok(()) => {
+                self.recent.add(&path);
+                self.status = if doc.mixed_endings {
+                    format!(
+                        "File opened; it mixes LF and CRLF line endings, saving will use {}",
+                        doc.line_ending
+                    )
+                } else {
+                    "File opened".into()
+                };
+                group.open(doc);
+                self.focus_pane(pane_id);
+                Ok(())
+            }
+            Err(e) => {
+                if matches!(e, LoadError::NotFound(_)) {
+                    self.recent.remove(&path);
+                }
+                self.status = format!("Open error: {e}");
+                Err(e)
+            }
+        }
+    }
+
+    fn focus_pane(&mut self, id: PaneId) {
+        self.focused_pane = id;
+        self.pending_focus = Some(id);
+    }
+
+    /// Move focus `step` leaves forward (or backward) in layout order, wrapping around.
+    fn cycle_focus(&mut self, step: isize) {
+        let leaves = self.layout.leaves();
+        let idx = leaves
+            .iter()
+            .position(|id| *id == self.focused_pane)
+            .unwrap_or(0);
+        let next = leaves[(idx as isize + step).rem_euclid(leaves.len() as isize) as usize];
+        self.focus_pane(next);
+        self.status = format!("Focused {next}");
+    }
+
+    fn split_focused(&mut self, axis: Axis) {
+        let id = PaneId(self.next_pane_id);
+        self.next_pane_id += 1;
+        if !self.layout.split(self.focused_pane, id, axis) {
+            self.status = format!("Could not split {}", self.focused_pane);
+            return;
+        }
+        let doc = self.new_document();
+        self.panes.insert(id, TabGroup::new(doc));
+        self.maximized = false;
+        self.focus_pane(id);
+        self.status = format!(
+            "Split {} {}",
+            self.focused_pane,
+            match axis {
+                Axis::Horizontal => "right",
+                Axis::Vertical => "down",
+            }
+        );
+    }
+
+    fn close_pane(&mut self) {
+        let id = self.focused_pane;
+        if self.panes.len() <= 1 {
+            self.status = "Cannot close the last pane".into();
+            return;
+        }
+        self.request_close(PendingClose::Pane(id));
+    }
+
+    fn close_pane_now(&mut self, id: PaneId) {
+        let leaves = self.layout.leaves();
+        let idx = leaves.iter().position(|leaf| *leaf == id).unwrap_or(0);
+        if !self.layout.remove(id) {
+            self.status = format!("Could not close {id}");
+            return;
+        }
+        self.panes.remove(&id);
+        let remaining = self.layout.leaves();
+        self.focus_pane(remaining[idx.min(remaining.len() - 1)]);
+        self.status = format!("{id} closed");
+    }
+
+    /// Swap the focused pane with its neighbour `step` leaves away.
+    fn move_focused(&mut self, step: isize) {
+        let leaves = self.layout.leaves();
+        let Some(idx) = leaves.iter().position(|id| *id == self.focused_pane) else {
+            return;
+        };
+        let other = idx as isize + step;
+        if other < 0 || other as usize >= leaves.len() {
+            self.status = format!("{} cannot move further", self.focused_pane);
+            return;
+        }
+        self.layout.swap(self.focused_pane, leaves[other as usize]);
+        self.pending_focus = Some(self.focused_pane);
+        self.status = format!("Moved {}", self.focused_pane);
+    }
+
+    /// Rank what the palette offers for the current query.
+    fn palette_entries(&mut self) -> Vec<(PaletteItem, PaletteTarget)> {
+        let (mode, query) = PaletteMode::parse(&self.command_palette_query);
+        let query = query.to_owned();
+        match mode {
+            // Recently used commands get a boost, which also puts them on
+            // top while the query is empty.
+            PaletteMode::Commands => palette::rank(
+                &query,
+                self.actions.iter().map(|action| Candidate {
+                    // "…" marks commands that ask for something before running
+                    label: if action.args.is_empty() {
+                        action.label.to_owned()
+                    } else {
+                        format!("{}…", action.label)
+                    },
+                    alias: Some(action.id.to_owned()),
+                    detail: self.keymap.describe(action.id),
+                    boost: self.recent_commands.boost(action.id),
+                    target: PaletteTarget::Action(*action),
+                }),
+            ),
+            PaletteMode::Files => {
+                let candidates = self.file_candidates();
+                palette::rank(&query, candidates)
+            }
+            PaletteMode::Recent => palette::rank(&query, self.recent_candidates()),
+            PaletteMode::Symbols => {
+                let text = &self.panes[&self.focused_pane].active().text;
+                if self
+                    .palette_symbols
+                    .as_ref()
+                    .is_none_or(|(version, _)| *version != text.version())
+                {
+                    self.palette_symbols = Some((text.version(), palette::symbols(&text.to_string())));
+                }
+                let symbols = self.palette_symbols.as_ref().map_or(&[][..], |(_, symbols)| symbols);
+                palette::rank(
+                    &query,
+                    symbols.iter().map(|symbol| Candidate {
+                        label: symbol.label.clone(),
+                        alias: None,
+                        detail: Some(format!("line {}", symbol.line + 1)),
+                        boost: 0,
+                        target: PaletteTarget::Line {
+                            line: symbol.line,
+                            column: 0,
+                        },
+                    }),
+                )
+            }
+            PaletteMode::Line => {
+                let lines = self.panes[&self.focused_pane].active().text.line_count();
+                palette::parse_line_query(&query)
+                    .map(|(line, column)| {
+                        let item = PaletteItem {
+                            label: format!("Go to line {line}, column {column}"),
+                            matches: Vec::new(),
+                            detail: Some(format!("of {lines}")),
+                        };
+                        let target = PaletteTarget::Line {
+                            line: line - 1,
+                            column: column - 1,
+                        };
+                        (item, target)
+                    })
+                    .into_iter()
+                    .collect()
+            }
         }
     }
 
     fn command_palette_ui(&mut self, ctx: &egui::Context) {
         use egui::Align2;
 
-        let actions: Vec<Action> = {
-            let query = self.command_palette_query.to_lowercase();
-            self.actions
-                .iter()
-                .copied()
-                .filter(|action| {
-                    query.is_empty()
-                        || action.label.to_lowercase().contains(query.as_str())
-                        || action.id.contains(query.as_str())
-                })
-                .collect()
+        if let Some(prompt) = &mut self.palette_prompt {
+            let mut finished = None;
+            let mut cancel = false;
+            egui::Window::new(prompt.title())
+                .id(egui::Id::new("command_palette"))
+                .pivot(Align2::CENTER_CENTER)
+                .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    finished = prompt.show(ui);
+                    cancel = ui.input(|i| i.key_pressed(egui::Key::Escape));
+                });
+            if let Some((action, args)) = finished {
+                self.close_command_palette();
+                self.perform_with_args(action.action, args);
+            } else if cancel {
+                self.close_command_palette();
+                self.status = "Command cancelled".into();
+            }
+            return;
+        }
+
+        let (mode, _) = PaletteMode::parse(&self.command_palette_query);
+        let version = self.panes[&self.focused_pane].active().text.version();
+        let results = match self.palette_results.take() {
+            Some(results) if results.query == self.command_palette_query && results.version == version => {
+                results
+            }
+            _ => PaletteResults {
+                query: self.command_palette_query.clone(),
+                version,
+                entries: self.palette_entries(),
+            },
+        };
+        let (title, hint, empty) = match mode {
+            PaletteMode::Commands => (
+                "Command Palette",
+                "Type to filter commands. Enter runs the selected one.",
+                "No matching commands.",
+            ),
+            PaletteMode::Files => (
+                "Go to File",
+                "Open a file from the working directory. Prefix > for commands, @ for symbols, : for a line.",
+                "No matching files.",
+            ),
+            PaletteMode::Symbols => (
+                "Go to Symbol",
+                "Jump to a heading or definition in the focused document.",
+                "No matching headings or definitions.",
+            ),
+            PaletteMode::Recent => (
+                "Open Recent",
+                "Reopen a recent file, or pick a folder to open a file from.",
+                "No recent files.",
+            ),
+            PaletteMode::Line => (
+                "Go to Line",
+                "Type a line number, optionally followed by :column.",
+                "Type a line number, e.g. :42 or :42:7",
+            ),
         };
 
-        egui::Window::new("Command Palette")
+        let mut picked = None;
+        egui::Window::new(title)
+            .id(egui::Id::new("command_palette"))
             .pivot(Align2::CENTER_CENTER)
             .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
             .collapsible(false)
             .resizable(false)
             .show(ctx, |ui| {
-                ui.label("Type to filter commands. Enter runs the first result.");
+                ui.label(hint);
                 let text_response = ui.text_edit_singleline(&mut self.command_palette_query);
                 if !text_response.has_focus() {
                     text_response.request_focus();
@@ -694,48 +2744,11 @@ impl App {
                 }
                 ui.separator();
 
-                if actions.is_empty() {
-                    ui.label("No matching commands.");
+                let items: Vec<&PaletteItem> = results.entries.iter().map(|(item, _)| item).collect();
+                if items.is_empty() {
+                    ui.label(empty);
                 } else {
-                    if self.command_palette_selected >= actions.len() {
-                        self.command_palette_selected = actions.len().saturating_sub(1);
-                    }
-
-                    let down = ctx.input(|i| i.key_pressed(egui::Key::ArrowDown));
-                    let up = ctx.input(|i| i.key_pressed(egui::Key::ArrowUp));
-                    if down {
-                        self.command_palette_selected =
-                            (self.command_palette_selected + 1) % actions.len();
-                    } else if up {
-                        if self.command_palette_selected == 0 {
-                            self.command_palette_selected = actions.len() - 1;
-                        } else {
-                            self.command_palette_selected -= 1;
-                        }
-                    }
-
-                    for (idx, action) in actions.iter().enumerate() {
-                        let mut label = action.label.to_string();
-                        if let Some(shortcut) = action.shortcut {
-                            label.push_str(" (");
-                            label.push_str(&format_shortcut(&shortcut));
-                            label.push(')');
-                        }
-                        let resp = ui.selectable_label(idx == self.command_palette_selected, label);
-                        if resp.clicked() {
-                            self.command_palette_selected = idx;
-                            self.perform_action(action.action);
-                            self.close_command_palette();
-                            return;
-                        }
-                    }
-
-                    if ctx.input(|i| i.key_pressed(egui::Key::Enter)) {
-                        if let Some(action) = actions.get(self.command_palette_selected) {
-                            self.perform_action(action.action);
-                            self.close_command_palette();
-                        }
-                    }
+                    picked = palette_list(ui, &items, &mut self.command_palette_selected);
                 }
 
                 if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
@@ -743,14 +2756,109 @@ impl App {
                     self.status = "Command palette closed".into();
                 }
             });
+
+        let Some(idx) = picked else {
+            if self.show_command_palette {
+                self.palette_results = Some(results);
+            }
+            return;
+        };
+        let Some((_, target)) = results.entries.into_iter().nth(idx) else {
+            return;
+        };
+        self.close_command_palette();
+        match target {
+            PaletteTarget::Action(action) => {
+                if let Err(e) = self.recent_commands.record(action.id) {
+                    eprintln!("Could not save recent commands: {e:#}");
+                }
+                self.perform_action(action.action);
+            }
+            PaletteTarget::File(path) => {
+                if let Err(e) = self.open_path_in(self.focused_pane, path) {
+                    self.status = format!("Open error: {e}");
+                }
+            }
+            PaletteTarget::Dir(dir) => self.open_dialog(self.focused_pane, Some(dir)),
+            PaletteTarget::Line { line, column } => self.go_to_line(ctx, line, column),
+        }
     }
 
-    fn save_focused(&mut self, force_as: bool) {
-        let (pane_name, target) = if self.focused_pane == FocusedPane::Left {
-            ("left", &mut self.left)
+    fn encoding_picker_ui(&mut self, ctx: &egui::Context, purpose: EncodingPurpose) {
+        let title = match purpose {
+            EncodingPurpose::Reopen => "Reopen with Encoding",
+            EncodingPurpose::Save => "Save with Encoding",
+        };
+        let current = self.panes[&self.focused_pane].active().encoding;
+        let mut open = true;
+        let mut chosen = None;
+        egui::Window::new(title)
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(format!("Current: {current}"));
+                ui.separator();
+                for (label, encoding) in encoding::CHOICES {
+                    if ui.selectable_label(*encoding == current, *label).clicked() {
+                        chosen = Some(*encoding);
+                    }
+                }
+            });
+        if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+            open = false;
+        }
+        if let Some(encoding) = chosen {
+            self.encoding_picker = None;
+            self.apply_encoding_choice(purpose, encoding);
+        } else if !open {
+            self.encoding_picker = None;
+        }
+    }
+
+    fn apply_encoding_choice(&mut self, purpose: EncodingPurpose, encoding: TextEncoding) {
+        let pane = self.focused_pane;
+        match purpose {
+            EncodingPurpose::Reopen => {
+                let doc_id = self.focused_mut().doc_id;
+                self.request_close(PendingClose::Reopen {
+                    pane,
+                    doc_id,
+                    encoding,
+                });
+            }
+            EncodingPurpose::Save => {
+                let doc = self.focused_mut();
+                // Check up front so a failed save leaves the old encoding in place
+                if let Err(e) = encoding.encode(&doc.text.to_string()) {
+                    self.status = format!("Save error: {e}");
+                    return;
+                }
+                doc.encoding = encoding;
+                self.save_focused(false);
+            }
+        }
+    }
+
+    /// Switch the focused document's line endings; the change lands on the next save.
+    fn convert_line_endings(&mut self, ending: LineEnding) {
+        let doc = self.focused_mut();
+        doc.line_ending = ending;
+        self.status = if doc.mixed_endings {
+            format!("Line endings will be normalized to {ending} on save")
         } else {
-            ("right", &mut self.right)
+            format!("Line endings set to {ending}")
         };
+    }
+
+    fn save_focused(&mut self, force_as: bool) {
+        let pane_name = self.focused_pane;
+        let target = self
+            .panes
+            .get_mut(&pane_name)
+            .expect("focused pane is always in the layout")
+            .active_mut();
 
         if force_as || target.path.is_none() {
             // Try file dialog first, fall back to input if it fails
@@ -758,8 +2866,8 @@ impl App {
         } else {
             // Direct save to existing path
             self.status = format!("Saving to: {}", target.path.as_ref().unwrap().display());
-            match target.save() {
-                Ok(_) => self.status = format!("{} pane saved", pane_name),
+            match target.save(self.backup_mode) {
+                Ok(_) => self.status = format!("{} saved", pane_name),
                 Err(e) => self.status = format!("Save error: {e}"),
             }
         }
@@ -767,192 +2875,200 @@ impl App {
 
     fn save_as_focused(&mut self) {
         // Try native file dialog first
-        match rfd::FileDialog::new()
-            .set_title("Save As")
-            .add_filter("Text/Markdown", &["txt", "md", "log"])
-            .add_filter("All Files", &["*"])
-            .save_file()
-        {
+        let mut dialog = self.config.dialogs.file_dialog("Save As");
+        if let Some(dir) = self.save_dir() {
+            dialog = dialog.set_directory(dir);
+        }
+        match dialog.save_file() {
             Some(p) => {
                 self.save_to_path(p);
             }
             None => {
-                // File dialog failed, show input dialog
-                self.show_save_as_input = true;
-                self.status = "File dialog not available, using input dialog".into();
+                // File dialog failed, ask in the palette instead
+                self.start_prompt(AppAction::SaveAsPath, None);
+                self.status = "File dialog not available, type a path instead".into();
             }
         }
     }
 
     fn save_to_path(&mut self, path: std::path::PathBuf) {
-        let (pane_name, target) = if self.focused_pane == FocusedPane::Left {
-            ("left", &mut self.left)
-        } else {
-            ("right", &mut self.right)
-        };
+        let pane_name = self.focused_pane;
+        let target = self
+            .panes
+            .get_mut(&pane_name)
+            .expect("focused pane is always in the layout")
+            .active_mut();
 
-        self.status = format!("Saving {} pane to: {}", pane_name, path.display());
+        self.status = format!("Saving {} to: {}", pane_name, path.display());
 
-        match target.save_as(path) {
-            Ok(_) => self.status = format!("{} pane saved", pane_name),
+        match target.save_as(path.clone(), self.backup_mode) {
+            Ok(_) => {
+                self.recent.add(&path);
+                self.status = format!("{} saved", pane_name);
+            }
             Err(e) => self.status = format!("Save error: {e}"),
         }
     }
 
-    fn open_dialog(&mut self, to_left: bool) {
+    /// Pick a file to open in `target_id`, starting in `dir` or wherever
+    /// files were last opened.
+    fn open_dialog(&mut self, target_id: PaneId, dir: Option<PathBuf>) {
         self.status = "Opening file dialog...".into();
 
-        match rfd::FileDialog::new()
-            .set_title("Open")
-            .add_filter("Text/Markdown", &["txt", "md", "log"])
-            .add_filter("All Files", &["*"])
-            .pick_file()
-        {
+        let dir = dir.or_else(|| self.open_dir());
+        let mut dialog = self.config.dialogs.file_dialog("Open");
+        if let Some(dir) = &dir {
+            dialog = dialog.set_directory(dir);
+        }
+        match dialog.pick_file() {
             Some(p) => {
-                let target = if to_left {
-                    &mut self.left
-                } else {
-                    &mut self.right
-                };
-                self.status = format!("Loading: {}", p.display());
-                if let Err(e) = target.load_from(p) {
-                    self.status = format!("open error: {e}");
-                } else {
-                    self.status = "opened".into();
-                    self.pending_focus = Some(if to_left {
-                        FocusedPane::Left
-                    } else {
-                        FocusedPane::Right
-                    });
-                    self.focused_pane = if to_left {
-                        FocusedPane::Left
-                    } else {
-                        FocusedPane::Right
-                    };
-                }
+                // The status bar already explains any failure
+                let _ = self.open_path_in(target_id, p);
             }
             None => {
-                // File dialog failed (common in WSL), show input dialog as fallback
-                self.show_open_input = true;
-                self.open_to_left = to_left;
-                self.status = "File dialog not available, using input dialog".into();
+                // File dialog failed (common in WSL), ask in the palette instead
+                self.focus_pane(target_id);
+                self.start_prompt(AppAction::OpenPath, dir.as_deref().map(prompt::dir_input));
+                self.status = "File dialog not available, type a path instead".into();
             }
         }
     }
 
     fn quick_save_focused(&mut self) {
-        let (pane_name, target) = if self.focused_pane == FocusedPane::Left {
-            ("left", &mut self.left)
-        } else {
-            ("right", &mut self.right)
-        };
-
-        let mut quick_save_dir = std::env::current_dir()
-            .map(|dir| dir.join("target").join("quick_saves"))
-            .unwrap_or_else(|_| std::env::temp_dir().join("nust_quick_saves"));
-        if let Err(primary_err) = fs::create_dir_all(&quick_save_dir) {
-            let fallback_dir = std::env::temp_dir().join("nust_quick_saves");
-            if quick_save_dir != fallback_dir {
-                match fs::create_dir_all(&fallback_dir) {
-                    Ok(_) => quick_save_dir = fallback_dir,
-                    Err(fallback_err) => {
-                        self.status = format!(
-                            "Quick save failed: {primary_err}; fallback failed: {fallback_err}"
-                        );
-                        return;
-                    }
-                }
-            } else {
-                self.status = format!("Quick save failed: {primary_err}");
+        let pane_name = self.focused_pane;
+        let target = self
+            .panes
+            .get_mut(&pane_name)
+            .expect("focused pane is always in the layout")
+            .active_mut();
+
+        let quick_save_dir = match quick_save_dir() {
+            Ok(dir) => dir,
+            Err(e) => {
+                self.status = format!("Quick save failed: {e}");
                 return;
             }
-        }
+        };
 
         // Quick save without file dialog - save to a timestamped file
         let timestamp = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_secs();
-        let filename = format!("nust_{}_{}.txt", pane_name, timestamp);
+        let filename = format!("nust_pane{}_{}.txt", pane_name.0, timestamp);
         let save_path = quick_save_dir.join(filename);
 
         self.status = format!(
-            "Quick saving {} pane to {}...",
+            "Quick saving {} to {}...",
             pane_name,
             save_path.display()
         );
 
-        match target.save_as(save_path.clone()) {
+        match target.save_as(save_path.clone(), self.backup_mode) {
             Ok(_) => {
                 self.status = format!(
-                    "{} pane quick save successful: {}",
+                    "{} quick save successful: {}",
                     pane_name,
                     save_path.display()
-                )
+                );
+                self.prune_quick_saves(&quick_save_dir);
             }
             Err(e) => self.status = format!("Quick save failed: {e}"),
         };
     }
 
-    fn close_focused(&mut self) {
-        let (pane_name, target, default_title) = if self.focused_pane == FocusedPane::Left {
-            ("left", &mut self.left, "left")
-        } else {
-            ("right", &mut self.right, "right")
-        };
-
-        target.text.clear();
-        target.path = None;
-        target.dirty = false;
-        target.title = default_title.into();
-        target.scroll_offset = 0.0;
-        target.scroll_viewport = 0.0;
-        target.scroll_content = 0.0;
-
-        self.status = format!("{pane_name} pane cleared");
-        self.pending_focus = Some(self.focused_pane);
-    }
-
-    fn manual_save(&mut self) {
-        if self.manual_path.trim().is_empty() {
+    fn manual_save(&mut self, save_path: PathBuf) {
+        if save_path.as_os_str().is_empty() {
             self.status = "Please enter a filename".into();
             return;
         }
 
-        let (pane_name, target) = if self.focused_pane == FocusedPane::Left {
-            ("left", &mut self.left)
-        } else {
-            ("right", &mut self.right)
-        };
-
-        let save_path = std::path::PathBuf::from(self.manual_path.trim());
+        let pane_name = self.focused_pane;
+        let target = self
+            .panes
+            .get_mut(&pane_name)
+            .expect("focused pane is always in the layout")
+            .active_mut();
 
         // Create parent directory if it doesn't exist
-        if let Some(parent) = save_path.parent() {
-            if let Err(e) = fs::create_dir_all(parent) {
-                self.status = format!("Failed to create directory: {e}").into();
-                return;
-            }
+        if let Some(parent) = save_path.parent()
+            && let Err(e) = fs::create_dir_all(parent)
+        {
+            self.status = format!("Failed to create directory: {e}");
+            return;
         }
 
-        self.status = format!("Saving {} pane to {}...", pane_name, save_path.display());
+        self.status = format!("Saving {} to {}...", pane_name, save_path.display());
 
-        match target.save_as(save_path) {
-            Ok(_) => self.status = "Manual save successful!".into(),
-            Err(e) => self.status = format!("Manual save failed: {e}").into(),
+        match target.save_as(save_path.clone(), self.backup_mode) {
+            Ok(_) => {
+                self.recent.add(&save_path);
+                self.manual_path = save_path.display().to_string();
+                self.status = "Manual save successful!".into();
+            }
+            Err(e) => self.status = format!("Manual save failed: {e}"),
         }
     }
 }
 
+/// Directory for quick saves and autosaved untitled buffers:
+/// `target/quick_saves/`, or the system temp dir if that cannot be created.
+fn quick_save_dir() -> Result<PathBuf> {
+    let quick_save_dir = std::env::current_dir()
+        .map(|dir| dir.join("target").join("quick_saves"))
+        .unwrap_or_else(|_| std::env::temp_dir().join("nust_quick_saves"));
+    let Err(primary_err) = fs::create_dir_all(&quick_save_dir) else {
+        return Ok(quick_save_dir);
+    };
+    let fallback_dir = std::env::temp_dir().join("nust_quick_saves");
+    if quick_save_dir == fallback_dir {
+        return Err(primary_err.into());
+    }
+    match fs::create_dir_all(&fallback_dir) {
+        Ok(_) => Ok(fallback_dir),
+        Err(fallback_err) => Err(anyhow::anyhow!(
+            "{primary_err}; fallback failed: {fallback_err}"
+        )),
+    }
+}
+
 fn main() -> Result<()> {
+    let settings_path = config::settings_path();
+    let (config, config_error) = match settings_path.as_deref().map(config::read) {
+        Some(Ok(config)) => (config, None),
+        Some(Err(e)) => (Config::default(), Some(e)),
+        None => (Config::default(), None),
+    };
     let opts = NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_decorations(false)
             .with_resizable(true)
-            .with_inner_size([1000.0, 700.0]),
+            .with_inner_size([
+                config.layout.window_width as f32,
+                config.layout.window_height as f32,
+            ]),
         ..Default::default()
     };
-    eframe::run_native("Nust", opts, Box::new(|_| Box::new(App::default())))
+    let mut app = App::default();
+    app.apply_config(config, true);
+    let session_enabled = !std::env::args().any(|arg| arg == session::NO_SESSION_FLAG);
+    if let Some(session) = session_enabled.then(session::load).flatten() {
+        app = app.restore_session(session);
+    }
+    app.session_enabled = session_enabled;
+    app.config_watcher = settings_path.map(ConfigWatcher::new);
+    let keymap_path = keymap::keymap_path();
+    app.keymap = Keymap::load(&app.actions, keymap_path.as_deref());
+    app.keymap_watcher = keymap_path.map(ConfigWatcher::new);
+    app.status = match config_error {
+        Some(e) => e,
+        None => app.keymap_status(&app.status),
+    };
+    app.recent_commands = RecentCommands::load();
+    app.recent = RecentFiles::load();
+    app.recoverable = recovery::leftovers();
+    app.journal = Journal::start();
+    eframe::run_native("Nust", opts, Box::new(|_| Box::new(app)))
         .map_err(|e| anyhow::anyhow!("eframe error: {}", e))?;
     Ok(())
 }
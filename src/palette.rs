@@ -0,0 +1,343 @@
+use anyhow::{Context, Result};
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::fileio::{self, BackupMode};
+use crate::fuzzy::{fuzzy_match, highlight};
+
+/// Commands remembered as recently used.
+pub const MAX_RECENT: usize = 10;
+/// Rows shown before the result list scrolls.
+const LIST_HEIGHT: f32 = 360.0;
+/// Results kept after ranking; more would only slow drawing down.
+const MAX_RESULTS: usize = 200;
+/// Files listed from the working directory before the walk gives up.
+const MAX_FILES: usize = 5000;
+/// Directories the file walk never enters.
+const SKIPPED_DIRS: &[&str] = &["target", "node_modules"];
+
+/// What the palette lists, chosen by the first character of the query.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PaletteMode {
+    /// `>`: run a command.
+    Commands,
+    /// No prefix: open a file from the working directory or a recent one.
+    Files,
+    /// `@`: jump to a heading or definition in the focused document.
+    Symbols,
+    /// `:`: go to a line (and optionally column).
+    Line,
+    /// `~`: reopen a recent file or browse a recent folder.
+    Recent,
+}
+
+impl PaletteMode {
+    /// Split `query` into its mode and the text after the prefix.
+    pub fn parse(query: &str) -> (Self, &str) {
+        if let Some(rest) = query.strip_prefix('>') {
+            (PaletteMode::Commands, rest)
+        } else if let Some(rest) = query.strip_prefix('@') {
+            (PaletteMode::Symbols, rest)
+        } else if let Some(rest) = query.strip_prefix(':') {
+            (PaletteMode::Line, rest)
+        } else if let Some(rest) = query.strip_prefix('~') {
+            (PaletteMode::Recent, rest)
+        } else {
+            (PaletteMode::Files, query)
+        }
+    }
+
+    pub fn prefix(self) -> &'static str {
+        match self {
+            PaletteMode::Commands => ">",
+            PaletteMode::Files => "",
+            PaletteMode::Symbols => "@",
+            PaletteMode::Line => ":",
+            PaletteMode::Recent => "~",
+        }
+    }
+}
+
+/// Ids of the commands last run from the palette, most recent first, kept
+/// in the state directory so the ordering survives restarts.
+#[derive(Default, Serialize, Deserialize)]
+pub struct RecentCommands {
+    ids: Vec<String>,
+}
+
+fn recent_path() -> Option<PathBuf> {
+    fileio::state_dir().map(|dir| dir.join("recent_commands.json"))
+}
+
+impl RecentCommands {
+    pub fn load() -> Self {
+        recent_path()
+            .and_then(|path| fs::read(path).ok())
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    /// Move `id` to the front and write the list out.
+    pub fn record(&mut self, id: &str) -> Result<()> {
+        self.ids.retain(|recent| recent != id);
+        self.ids.insert(0, id.to_owned());
+        self.ids.truncate(MAX_RECENT);
+
+        let path = recent_path().context("no per-user state directory")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+        }
+        fileio::write_atomic(&path, &serde_json::to_vec(self)?, BackupMode::Off)?;
+        Ok(())
+    }
+
+    /// Score boost for `id`: most for the latest command, none if not recent.
+    pub fn boost(&self, id: &str) -> i32 {
+        self.ids
+            .iter()
+            .position(|recent| recent == id)
+            .map_or(0, |rank| (MAX_RECENT - rank) as i32 * 2)
+    }
+}
+
+/// Something the palette can list, before it is matched against the query.
+pub struct Candidate<T> {
+    pub label: String,
+    /// Also matched, without highlighting, when the label does not match.
+    pub alias: Option<String>,
+    pub detail: Option<String>,
+    /// Added to the match score, e.g. for recently used entries.
+    pub boost: i32,
+    pub target: T,
+}
+
+/// Fuzzy-match every candidate against `query`, best first. Equal scores
+/// keep the order the candidates came in.
+pub fn rank<T>(query: &str, candidates: impl IntoIterator<Item = Candidate<T>>) -> Vec<(PaletteItem, T)> {
+    let mut ranked: Vec<(i32, PaletteItem, T)> = candidates
+        .into_iter()
+        .filter_map(|candidate| {
+            let (score, matches) = match fuzzy_match(query, &candidate.label) {
+                Some(found) => (found.score, found.positions),
+                None => (fuzzy_match(query, candidate.alias.as_deref()?)?.score, Vec::new()),
+            };
+            let item = PaletteItem {
+                label: candidate.label,
+                matches,
+                detail: candidate.detail,
+            };
+            Some((score + candidate.boost, item, candidate.target))
+        })
+        .collect();
+    ranked.sort_by_key(|(score, _, _)| std::cmp::Reverse(*score));
+    ranked.truncate(MAX_RESULTS);
+    ranked.into_iter().map(|(_, item, target)| (item, target)).collect()
+}
+
+/// Files under `root`, skipping hidden entries and build output, as paths
+/// relative to `root`. Stops after `MAX_FILES`.
+pub fn project_files(root: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries: Vec<_> = fs::read_dir(&dir).into_iter().flatten().flatten().collect();
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with('.') {
+                continue;
+            }
+            let Ok(kind) = entry.file_type() else {
+                continue;
+            };
+            let path = entry.path();
+            if kind.is_dir() {
+                if !SKIPPED_DIRS.contains(&name.as_ref()) {
+                    dirs.push(path);
+                }
+            } else if let Ok(relative) = path.strip_prefix(root) {
+                found.push(relative.to_path_buf());
+                if found.len() >= MAX_FILES {
+                    return found;
+                }
+            }
+        }
+    }
+    found
+}
+
+/// A heading or definition the `@` mode can jump to.
+pub struct Symbol {
+    pub label: String,
+    /// Zero-based line number.
+    pub line: usize,
+}
+
+/// Keywords that start a definition worth listing, after any `pub`.
+const DEFINITIONS: &[&str] = &[
+    "fn ", "struct ", "enum ", "trait ", "impl ", "mod ", "type ", "const ", "class ", "def ",
+    "function ", "interface ",
+];
+
+/// Markdown headings (indented by level) and definition lines in `text`.
+pub fn symbols(text: &str) -> Vec<Symbol> {
+    let mut found = Vec::new();
+    let mut in_fence = false;
+    for (line, content) in text.lines().enumerate() {
+        let trimmed = content.trim_start();
+        if trimmed.starts_with("```") {
+            in_fence = !in_fence;
+            continue;
+        }
+        let level = trimmed.chars().take_while(|c| *c == '#').count();
+        if !in_fence && (1..=6).contains(&level) && trimmed[level..].starts_with(' ') {
+            let indent = "  ".repeat(level - 1);
+            found.push(Symbol {
+                label: format!("{indent}{}", trimmed[level..].trim()),
+                line,
+            });
+            continue;
+        }
+        let bare = trimmed.strip_prefix("pub ").unwrap_or(trimmed);
+        let bare = bare.strip_prefix("async ").unwrap_or(bare);
+        if DEFINITIONS.iter().any(|keyword| bare.starts_with(keyword)) {
+            let label = trimmed.trim_end_matches(['{', ':', ' ']).to_owned();
+            found.push(Symbol { label, line });
+        }
+    }
+    found
+}
+
+/// Parse the `:` mode's `line` or `line:column` (both one-based).
+pub fn parse_line_query(query: &str) -> Option<(usize, usize)> {
+    let (line, column) = query.trim().split_once(':').unwrap_or((query.trim(), "1"));
+    let line = line.trim().parse().ok().filter(|line| *line >= 1)?;
+    let column = column.trim().parse().ok().filter(|column| *column >= 1)?;
+    Some((line, column))
+}
+
+/// One row of the palette's result list.
+pub struct PaletteItem {
+    pub label: String,
+    /// Char indices of `label` matched by the query, drawn highlighted.
+    pub matches: Vec<usize>,
+    /// Shown dimmed after the label, e.g. the key bindings.
+    pub detail: Option<String>,
+}
+
+/// Draw the result list with keyboard navigation: Up/Down move `selected`,
+/// Enter or a click picks a row. Returns the index of the picked row.
+pub fn palette_list(ui: &mut egui::Ui, items: &[&PaletteItem], selected: &mut usize) -> Option<usize> {
+    if items.is_empty() {
+        return None;
+    }
+    *selected = (*selected).min(items.len() - 1);
+    let (down, up, enter) = ui.input(|i| {
+        (
+            i.key_pressed(egui::Key::ArrowDown),
+            i.key_pressed(egui::Key::ArrowUp),
+            i.key_pressed(egui::Key::Enter),
+        )
+    });
+    if down {
+        *selected = (*selected + 1) % items.len();
+    } else if up {
+        *selected = selected.checked_sub(1).unwrap_or(items.len() - 1);
+    }
+
+    let mut picked = enter.then_some(*selected);
+    egui::ScrollArea::vertical()
+        .max_height(LIST_HEIGHT)
+        .id_source("palette_list")
+        .show(ui, |ui| {
+            for (idx, item) in items.iter().enumerate() {
+                let mut job = highlight(ui, &item.label, &item.matches);
+                if let Some(detail) = &item.detail {
+                    job.append(
+                        &format!("  {detail}"),
+                        0.0,
+                        egui::TextFormat {
+                            font_id: egui::TextStyle::Button.resolve(ui.style()),
+                            color: ui.visuals().weak_text_color(),
+                            ..Default::default()
+                        },
+                    );
+                }
+                let resp = ui.selectable_label(idx == *selected, job);
+                if idx == *selected && (up || down) {
+                    resp.scroll_to_me(None);
+                }
+                if resp.clicked() {
+                    *selected = idx;
+                    picked = Some(idx);
+                }
+            }
+        });
+    picked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(text: &str) -> Vec<(String, usize)> {
+        symbols(text).into_iter().map(|symbol| (symbol.label, symbol.line)).collect()
+    }
+
+    #[test]
+    fn line_queries_take_an_optional_column() {
+        assert_eq!(parse_line_query("12"), Some((12, 1)));
+        assert_eq!(parse_line_query(" 12 : 7 "), Some((12, 7)));
+        assert_eq!(parse_line_query("12:"), None);
+        assert_eq!(parse_line_query("0"), None);
+        assert_eq!(parse_line_query("3:0"), None);
+        assert_eq!(parse_line_query("-1"), None);
+        assert_eq!(parse_line_query("ten"), None);
+        assert_eq!(parse_line_query(""), None);
+    }
+
+    #[test]
+    fn the_prefix_picks_the_mode() {
+        assert_eq!(PaletteMode::parse(">save"), (PaletteMode::Commands, "save"));
+        assert_eq!(PaletteMode::parse("@main"), (PaletteMode::Symbols, "main"));
+        assert_eq!(PaletteMode::parse(":4"), (PaletteMode::Line, "4"));
+        assert_eq!(PaletteMode::parse("~notes"), (PaletteMode::Recent, "notes"));
+        assert_eq!(PaletteMode::parse("main.rs"), (PaletteMode::Files, "main.rs"));
+        for mode in [PaletteMode::Commands, PaletteMode::Symbols, PaletteMode::Line, PaletteMode::Recent] {
+            assert_eq!(PaletteMode::parse(&format!("{}x", mode.prefix())), (mode, "x"));
+        }
+    }
+
+    #[test]
+    fn headings_are_indented_by_level() {
+        let text = "# Title\ntext\n## Part\n#### Deep\n####### Too deep\n#hashtag\n";
+        assert_eq!(
+            labels(text),
+            [("Title".into(), 0), ("  Part".into(), 2), ("      Deep".into(), 3)]
+        );
+    }
+
+    #[test]
+    fn headings_inside_fences_are_skipped() {
+        let text = "# Setup\n```sh\n# install it\nmake\n```\n## After\n  ```\n# indented fence\n  ```\n";
+        assert_eq!(labels(text), [("Setup".into(), 0), ("  After".into(), 5)]);
+    }
+
+    #[test]
+    fn definitions_keep_their_keyword() {
+        let text = "pub struct Pane {\n    fn helper() {}\n}\n\
+                    async fn run() {\nlet fn_count = 1;\ndef go():\n";
+        assert_eq!(
+            labels(text),
+            [
+                ("pub struct Pane".into(), 0),
+                ("fn helper() {}".into(), 1),
+                ("async fn run()".into(), 3),
+                ("def go()".into(), 5),
+            ]
+        );
+    }
+}
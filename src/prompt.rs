@@ -0,0 +1,331 @@
+use eframe::egui;
+use std::fs;
+use std::path::{MAIN_SEPARATOR, Path, PathBuf};
+
+use crate::Action;
+use crate::palette::{self, Candidate, PaletteItem, palette_list};
+
+/// What kind of value an action argument takes.
+#[derive(Clone, Copy, Debug)]
+pub enum ArgKind {
+    /// A file path, with completion from the filesystem.
+    Path,
+    Text,
+    /// A whole number within the bounds, inclusive.
+    Number { min: i64, max: i64 },
+    /// One of the listed options; the value is its index.
+    Choice(&'static [&'static str]),
+}
+
+/// One argument an action asks for before it runs.
+#[derive(Clone, Copy, Debug)]
+pub struct ArgSpec {
+    pub prompt: &'static str,
+    pub kind: ArgKind,
+}
+
+impl ArgSpec {
+    pub const fn new(prompt: &'static str, kind: ArgKind) -> Self {
+        Self { prompt, kind }
+    }
+}
+
+/// A collected argument, matching its `ArgKind`.
+#[derive(Clone, Debug)]
+pub enum ArgValue {
+    Path(PathBuf),
+    Text(String),
+    Number(i64),
+    Choice(usize),
+}
+
+/// What a row of the prompt's list does when picked.
+enum Pick {
+    Value(ArgValue),
+    /// Replace the input with this and keep prompting (a directory).
+    Complete(String),
+}
+
+/// The palette asking for an action's arguments one at a time.
+pub struct Prompt {
+    pub action: Action,
+    values: Vec<ArgValue>,
+    input: String,
+    selected: usize,
+    /// Set when the input was replaced, so the cursor moves to its end.
+    moved: bool,
+}
+
+impl Prompt {
+    /// Start prompting for `action`'s arguments, the first prefilled with `input`.
+    pub fn new(action: Action, input: String) -> Self {
+        Self {
+            action,
+            values: Vec::new(),
+            input,
+            selected: 0,
+            moved: true,
+        }
+    }
+
+    fn spec(&self) -> ArgSpec {
+        self.action.args[self.values.len()]
+    }
+
+    pub fn title(&self) -> String {
+        let spec = self.spec();
+        if self.action.args.len() > 1 {
+            format!(
+                "{} ({}/{}): {}",
+                self.action.label,
+                self.values.len() + 1,
+                self.action.args.len(),
+                spec.prompt
+            )
+        } else {
+            format!("{}: {}", self.action.label, spec.prompt)
+        }
+    }
+
+    /// Rows for the current argument given what has been typed.
+    fn rows(&self) -> Vec<(PaletteItem, Pick)> {
+        let input = self.input.trim();
+        let typed = |label: String, value: ArgValue| {
+            let item = PaletteItem {
+                label,
+                matches: Vec::new(),
+                detail: None,
+            };
+            (item, Pick::Value(value))
+        };
+        match self.spec().kind {
+            ArgKind::Path => {
+                let mut rows = Vec::new();
+                // A bare directory is almost never the answer; offer its entries
+                if !input.is_empty() && !input.ends_with(['/', MAIN_SEPARATOR]) {
+                    rows.push(typed(format!("Use {input}"), ArgValue::Path(expand_home(input))));
+                }
+                rows.extend(path_completions(&self.input));
+                rows
+            }
+            ArgKind::Text => (!input.is_empty())
+                .then(|| typed(format!("Use \"{input}\""), ArgValue::Text(input.to_owned())))
+                .into_iter()
+                .collect(),
+            ArgKind::Number { min, max } => input
+                .parse::<i64>()
+                .ok()
+                .filter(|n| (min..=max).contains(n))
+                .map(|n| typed(format!("Use {n}"), ArgValue::Number(n)))
+                .into_iter()
+                .collect(),
+            ArgKind::Choice(options) => palette::rank(
+                input,
+                options.iter().enumerate().map(|(idx, option)| Candidate {
+                    label: (*option).to_owned(),
+                    alias: None,
+                    detail: None,
+                    boost: 0,
+                    target: Pick::Value(ArgValue::Choice(idx)),
+                }),
+            ),
+        }
+    }
+
+    fn empty_hint(&self) -> String {
+        match self.spec().kind {
+            ArgKind::Path => "Type a path; Tab completes.".into(),
+            ArgKind::Text => "Type some text.".into(),
+            ArgKind::Number { min, max } => format!("Type a number from {min} to {max}."),
+            ArgKind::Choice(_) => "No matching option.".into(),
+        }
+    }
+
+    /// Draw the input and its list. Enter or a click picks a row; Tab on a
+    /// path completes the selected row without picking it. Returns the
+    /// action and its arguments once the last one is in.
+    pub fn show(&mut self, ui: &mut egui::Ui) -> Option<(Action, Vec<ArgValue>)> {
+        let complete = ui.input_mut(|i| i.consume_key(egui::Modifiers::NONE, egui::Key::Tab));
+        let edit_id = egui::Id::new("palette_prompt_input");
+        let response = ui.add(egui::TextEdit::singleline(&mut self.input).id(edit_id));
+        if !response.has_focus() {
+            response.request_focus();
+        }
+        if response.changed() {
+            self.selected = 0;
+        }
+        if std::mem::take(&mut self.moved) {
+            let mut state = egui::TextEdit::load_state(ui.ctx(), edit_id).unwrap_or_default();
+            let end = egui::text::CCursor::new(self.input.chars().count());
+            state
+                .cursor
+                .set_char_range(Some(egui::text::CCursorRange::one(end)));
+            egui::TextEdit::store_state(ui.ctx(), edit_id, state);
+        }
+        ui.separator();
+
+        let rows = self.rows();
+        if rows.is_empty() {
+            ui.label(self.empty_hint());
+            return None;
+        }
+        let items: Vec<&PaletteItem> = rows.iter().map(|(item, _)| item).collect();
+        let picked = palette_list(ui, &items, &mut self.selected);
+        let (idx, tab) = match (picked, complete) {
+            (Some(idx), _) => (idx, false),
+            (None, true) => (self.selected.min(rows.len() - 1), true),
+            (None, false) => return None,
+        };
+        match rows.into_iter().nth(idx).map(|(_, pick)| pick) {
+            Some(Pick::Complete(path)) => self.set_input(path),
+            Some(Pick::Value(ArgValue::Path(path))) if tab => {
+                self.set_input(path.display().to_string());
+            }
+            Some(Pick::Value(value)) => {
+                self.values.push(value);
+                if self.values.len() == self.action.args.len() {
+                    return Some((self.action, std::mem::take(&mut self.values)));
+                }
+                self.set_input(String::new());
+            }
+            None => {}
+        }
+        None
+    }
+
+    fn set_input(&mut self, input: String) {
+        self.input = input;
+        self.selected = 0;
+        self.moved = true;
+    }
+}
+
+/// `~/notes` → the home directory joined with `notes`.
+fn expand_home(input: &str) -> PathBuf {
+    match input.strip_prefix("~/").zip(dirs::home_dir()) {
+        Some((rest, home)) => home.join(rest),
+        None => PathBuf::from(input),
+    }
+}
+
+/// Entries of the directory `input` points into, fuzzy-matched against the
+/// partial name after its last separator. Directories complete the input;
+/// files are picked as the value.
+fn path_completions(input: &str) -> Vec<(PaletteItem, Pick)> {
+    let (dir_part, name_part) = match input.rfind(['/', MAIN_SEPARATOR]) {
+        Some(idx) => input.split_at(idx + 1),
+        None => ("", input),
+    };
+    let dir = if dir_part.is_empty() {
+        PathBuf::from(".")
+    } else {
+        expand_home(dir_part)
+    };
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Vec::new();
+    };
+    let mut entries: Vec<(String, bool)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_str()?.to_owned();
+            // Hidden entries only when asked for
+            (!name.starts_with('.') || name_part.starts_with('.'))
+                .then(|| (name, entry.file_type().is_ok_and(|kind| kind.is_dir())))
+        })
+        .collect();
+    entries.sort();
+    palette::rank(
+        name_part,
+        entries.into_iter().map(|(name, is_dir)| {
+            let full = format!("{dir_part}{name}");
+            let (label, pick) = if is_dir {
+                (format!("{name}/"), Pick::Complete(format!("{full}/")))
+            } else {
+                (name, Pick::Value(ArgValue::Path(expand_home(&full))))
+            };
+            Candidate {
+                label,
+                alias: None,
+                detail: None,
+                boost: 0,
+                target: pick,
+            }
+        }),
+    )
+}
+
+/// Text to prefill a path argument with: `dir` with a trailing separator,
+/// ready for completion.
+pub fn dir_input(dir: &Path) -> String {
+    let mut text = dir.display().to_string();
+    if !text.ends_with(['/', MAIN_SEPARATOR]) {
+        text.push(MAIN_SEPARATOR);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    /// Each completion's label and what picking it does, as text.
+    fn complete(input: &str) -> Vec<(String, String)> {
+        path_completions(input)
+            .into_iter()
+            .map(|(item, pick)| {
+                let pick = match pick {
+                    Pick::Complete(input) => format!("complete {input}"),
+                    Pick::Value(ArgValue::Path(path)) => format!("open {}", path.display()),
+                    Pick::Value(value) => panic!("not a path: {value:?}"),
+                };
+                (item.label, pick)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn directories_complete_and_files_are_picked() {
+        let dir = TempDir::new("prompt-complete");
+        dir.file("notes.md", "");
+        dir.file(".hidden", "");
+        fs::create_dir(dir.path().join("drafts")).unwrap();
+        let input = dir_input(dir.path());
+        assert_eq!(
+            complete(&input),
+            [
+                ("drafts/".into(), format!("complete {input}drafts/")),
+                ("notes.md".into(), format!("open {input}notes.md")),
+            ]
+        );
+    }
+
+    #[test]
+    fn the_partial_name_filters_and_dots_show_hidden_entries() {
+        let dir = TempDir::new("prompt-filter");
+        dir.file("notes.md", "");
+        dir.file("todo.txt", "");
+        dir.file(".hidden", "");
+        let input = dir_input(dir.path());
+        let labels = |partial: &str| -> Vec<String> {
+            complete(&format!("{input}{partial}")).into_iter().map(|(label, _)| label).collect()
+        };
+        assert_eq!(labels("todo"), ["todo.txt"]);
+        assert_eq!(labels(".hid"), [".hidden"]);
+        assert!(complete(&format!("{input}missing/")).is_empty());
+    }
+
+    #[test]
+    fn dir_input_ends_with_one_separator() {
+        assert_eq!(dir_input(Path::new("notes")), format!("notes{MAIN_SEPARATOR}"));
+        assert_eq!(dir_input(Path::new("notes/")), "notes/");
+    }
+
+    #[test]
+    fn home_is_expanded() {
+        if let Some(home) = dirs::home_dir() {
+            assert_eq!(expand_home("~/notes"), home.join("notes"));
+        }
+        assert_eq!(expand_home("notes/~/x"), PathBuf::from("notes/~/x"));
+    }
+}
@@ -0,0 +1,431 @@
+use anyhow::Result;
+use eframe::egui;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::buffer::Buffer;
+use crate::diff::DiffView;
+use crate::fileio;
+
+/// Lines shown in a snapshot's preview.
+const PREVIEW_LINES: usize = 20;
+
+/// Which document a quick save came from, recovered from its file name.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Source {
+    /// `nust_pane{N}_{timestamp}.txt`, written by Quick Save.
+    Pane(u64),
+}
+
+impl Source {
+    fn label(self) -> String {
+        match self {
+            Source::Pane(n) => format!("Pane {n}"),
+        }
+    }
+}
+
+/// One snapshot file in the quick-save directory.
+pub struct QuickSave {
+    pub path: PathBuf,
+    pub source: Source,
+    /// Seconds since the Unix epoch.
+    pub saved_at: u64,
+    pub size: u64,
+}
+
+fn parse_name(name: &str) -> Option<(Source, u64)> {
+    let stem = name.strip_prefix("nust_")?.strip_suffix(".txt")?;
+    let (kind, timestamp) = stem.rsplit_once('_')?;
+    let timestamp = timestamp.parse().ok()?;
+    let pane = kind.strip_prefix("pane")?.parse().ok()?;
+    Some((Source::Pane(pane), timestamp))
+}
+
+/// Every recognised snapshot in `dir`, newest first.
+pub fn scan(dir: &Path) -> Vec<QuickSave> {
+    let mut found: Vec<QuickSave> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let (source, saved_at) = parse_name(entry.file_name().to_str()?)?;
+            Some(QuickSave {
+                path: entry.path(),
+                source,
+                saved_at,
+                size: entry.metadata().ok()?.len(),
+            })
+        })
+        .collect();
+    found.sort_by_key(|save| std::cmp::Reverse(save.saved_at));
+    found
+}
+
+/// Limits applied when pruning old snapshots. A zero turns that limit off.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RetentionPolicy {
+    /// Snapshots kept per pane/document.
+    pub keep_last: usize,
+    pub max_age_days: u64,
+    /// Total size of the directory, oldest snapshots going first.
+    pub max_total_mb: u64,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            keep_last: 20,
+            max_age_days: 30,
+            max_total_mb: 100,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    /// Delete snapshots in `dir` that fall outside the policy, never touching
+    /// `protected` (files still open in a tab). Returns how many went.
+    pub fn prune(&self, dir: &Path, protected: &[PathBuf]) -> Result<usize> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let max_age = self.max_age_days * 24 * 60 * 60;
+        let mut per_source = std::collections::HashMap::new();
+        let mut total = 0u64;
+        let mut doomed = Vec::new();
+        // Newest first, so counts and running totals favour recent snapshots
+        for save in scan(dir) {
+            let seen = per_source.entry(save.source).or_insert(0usize);
+            *seen += 1;
+            total += save.size;
+            let too_many = self.keep_last > 0 && *seen > self.keep_last;
+            let too_old = self.max_age_days > 0 && now.saturating_sub(save.saved_at) > max_age;
+            let too_big = self.max_total_mb > 0 && total > self.max_total_mb * 1024 * 1024;
+            if (too_many || too_old || too_big) && !protected.contains(&save.path) {
+                total -= save.size;
+                doomed.push(save.path);
+            }
+        }
+        for path in &doomed {
+            fs::remove_file(path)?;
+        }
+        Ok(doomed.len())
+    }
+}
+
+/// What the panel shows for the selected snapshot.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum View {
+    Preview,
+    Diff,
+}
+
+/// Requests from the panel that need the app.
+pub enum QuickSaveCommand {
+    /// Replace the focused document's text with this snapshot's.
+    Restore(PathBuf),
+    Delete(PathBuf),
+    Prune,
+    Refresh,
+}
+
+/// State of the "Quick Saves" window.
+pub struct QuickSavePanel {
+    pub open: bool,
+    pub entries: Vec<QuickSave>,
+    pub policy: RetentionPolicy,
+    /// The selected snapshot and its text, or why it could not be read.
+    selected: Option<(PathBuf, Result<String, String>)>,
+    /// Counts selections, so picking a snapshot again re-diffs it.
+    selection: u64,
+    view: View,
+    diff: DiffView<(u64, u64)>,
+}
+
+impl Default for QuickSavePanel {
+    fn default() -> Self {
+        Self {
+            open: false,
+            entries: Vec::new(),
+            policy: RetentionPolicy::default(),
+            selected: None,
+            selection: 0,
+            view: View::Preview,
+            diff: DiffView::default(),
+        }
+    }
+}
+
+/// "5m ago"-style age of a Unix timestamp in seconds.
+pub fn format_age(saved_at: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    match now.saturating_sub(saved_at) {
+        secs if secs < 60 => format!("{secs}s ago"),
+        secs if secs < 60 * 60 => format!("{}m ago", secs / 60),
+        secs if secs < 24 * 60 * 60 => format!("{}h ago", secs / 3600),
+        secs => format!("{}d ago", secs / 86400),
+    }
+}
+
+impl QuickSavePanel {
+    pub fn refresh(&mut self, dir: &Path) {
+        self.entries = scan(dir);
+        if let Some((path, _)) = &self.selected
+            && !self.entries.iter().any(|save| &save.path == path)
+        {
+            self.selected = None;
+        }
+    }
+
+    /// Draw the window. `current` is the focused document's text, for diffs.
+    pub fn show(&mut self, ctx: &egui::Context, current: &Buffer) -> Option<QuickSaveCommand> {
+        let mut command = None;
+        let mut open = self.open;
+        egui::Window::new("Quick Saves")
+            .open(&mut open)
+            .default_size([720.0, 460.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Keep last");
+                    ui.add(egui::DragValue::new(&mut self.policy.keep_last).clamp_range(0..=1000));
+                    ui.label("per source, max age (days)");
+                    ui.add(egui::DragValue::new(&mut self.policy.max_age_days).clamp_range(0..=3650));
+                    ui.label("max total (MB)");
+                    ui.add(egui::DragValue::new(&mut self.policy.max_total_mb).clamp_range(0..=100_000));
+                    if ui.button("Prune now").on_hover_text("0 turns a limit off").clicked() {
+                        command = Some(QuickSaveCommand::Prune);
+                    }
+                    if ui.button("Refresh").clicked() {
+                        command = Some(QuickSaveCommand::Refresh);
+                    }
+                });
+                ui.separator();
+                if self.entries.is_empty() {
+                    ui.label("No quick saves yet.");
+                    return;
+                }
+                ui.columns(2, |columns| {
+                    self.list_ui(&mut columns[0], &mut command);
+                    self.detail_ui(&mut columns[1], current, &mut command);
+                });
+            });
+        self.open = open;
+        command
+    }
+
+    fn list_ui(&mut self, ui: &mut egui::Ui, command: &mut Option<QuickSaveCommand>) {
+        let mut sources: Vec<Source> = self.entries.iter().map(|save| save.source).collect();
+        sources.sort();
+        sources.dedup();
+        egui::ScrollArea::vertical()
+            .id_source("quick_save_list")
+            .show(ui, |ui| {
+                for source in sources {
+                    egui::CollapsingHeader::new(source.label())
+                        .default_open(true)
+                        .show(ui, |ui| {
+                            for save in self.entries.iter().filter(|save| save.source == source) {
+                                let selected = self
+                                    .selected
+                                    .as_ref()
+                                    .is_some_and(|(path, _)| path == &save.path);
+                                let label = format!(
+                                    "{}  ({:.1} KB)",
+                                    format_age(save.saved_at),
+                                    save.size as f64 / 1024.0
+                                );
+                                let resp = ui
+                                    .selectable_label(selected, label)
+                                    .on_hover_text(save.path.display().to_string());
+                                if resp.clicked() {
+                                    let text =
+                                        fileio::read_normalized(&save.path).map_err(|e| e.to_string());
+                                    self.selected = Some((save.path.clone(), text));
+                                    self.selection += 1;
+                                }
+                                resp.context_menu(|ui| {
+                                    if ui.button("Delete").clicked() {
+                                        *command = Some(QuickSaveCommand::Delete(save.path.clone()));
+                                        ui.close_menu();
+                                    }
+                                });
+                            }
+                        });
+                }
+            });
+    }
+
+    fn detail_ui(&mut self, ui: &mut egui::Ui, current: &Buffer, command: &mut Option<QuickSaveCommand>) {
+        let Some((path, text)) = &self.selected else {
+            ui.label("Select a snapshot to preview it.");
+            return;
+        };
+        let readable = text.is_ok();
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.view, View::Preview, "Preview");
+            ui.add_enabled_ui(readable, |ui| {
+                ui.selectable_value(&mut self.view, View::Diff, "Diff vs. current");
+            });
+            if ui
+                .add_enabled(readable, egui::Button::new("Restore"))
+                .on_hover_text("Replace the focused document's text (undoable)")
+                .clicked()
+            {
+                *command = Some(QuickSaveCommand::Restore(path.clone()));
+            }
+            if ui.button("Delete").clicked() {
+                *command = Some(QuickSaveCommand::Delete(path.clone()));
+            }
+        });
+        ui.separator();
+        let text = match text {
+            Ok(text) => text,
+            Err(e) => {
+                let error = ui.visuals().error_fg_color;
+                ui.colored_label(error, format!("Could not read the snapshot: {e}"));
+                return;
+            }
+        };
+        match self.view {
+            View::Preview => {
+                let preview: Vec<&str> = text.lines().take(PREVIEW_LINES).collect();
+                egui::ScrollArea::both()
+                    .id_source("quick_save_preview")
+                    .show(ui, |ui| {
+                        ui.monospace(preview.join("\n"));
+                        if text.lines().nth(PREVIEW_LINES).is_some() {
+                            ui.weak("…");
+                        }
+                    });
+            }
+            // Old = current buffer, new = snapshot: shows what Restore would change
+            View::Diff => {
+                let key = (current.version(), self.selection);
+                self.diff.show(ui, key, || (current.to_string(), text));
+            }
+        }
+    }
+
+    /// Text of the selected snapshot, if `path` is the selected one and it
+    /// could be read.
+    pub fn selected_text(&self, path: &Path) -> Option<&str> {
+        self.selected
+            .as_ref()
+            .filter(|(selected, _)| selected == path)
+            .and_then(|(_, text)| text.as_deref().ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    /// The snapshots `scan` finds in `dir`, by name.
+    fn names(dir: &TempDir) -> Vec<String> {
+        let mut names: Vec<String> = scan(dir.path())
+            .into_iter()
+            .map(|save| save.path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[test]
+    fn parses_snapshot_names() {
+        assert_eq!(parse_name("nust_pane3_1700000000.txt"), Some((Source::Pane(3), 1_700_000_000)));
+        for name in [
+            "nust_pane3_1700000000.md",
+            "other_pane3_1700000000.txt",
+            "nust_pane_1700000000.txt",
+            "nust_paneX_1700000000.txt",
+            "nust_pane3_soon.txt",
+            "nust_autosave_12_1700000000.txt",
+            "nust_backup_1_1700000000.txt",
+        ] {
+            assert_eq!(parse_name(name), None, "{name}");
+        }
+    }
+
+    #[test]
+    fn scan_lists_newest_first_and_skips_other_files() {
+        let dir = TempDir::new("quicksave-scan");
+        dir.file("nust_pane1_100.txt", b"x");
+        dir.file("nust_pane1_300.txt", b"x");
+        dir.file("nust_pane2_200.txt", b"x");
+        dir.file("notes.txt", b"x");
+        let found: Vec<(Source, u64)> = scan(dir.path())
+            .iter()
+            .map(|save| (save.source, save.saved_at))
+            .collect();
+        assert_eq!(found, [(Source::Pane(1), 300), (Source::Pane(2), 200), (Source::Pane(1), 100)]);
+    }
+
+    #[test]
+    fn prune_keeps_the_newest_per_source() {
+        let dir = TempDir::new("quicksave-keep-last");
+        let now = now();
+        for age in 0..4 {
+            dir.file(&format!("nust_pane1_{}.txt", now - age), b"x");
+        }
+        dir.file(&format!("nust_pane2_{}.txt", now - 10), b"x");
+        let policy = RetentionPolicy {
+            keep_last: 2,
+            max_age_days: 0,
+            max_total_mb: 0,
+        };
+        assert_eq!(policy.prune(dir.path(), &[]).unwrap(), 2);
+        let expected = [
+            format!("nust_pane1_{}.txt", now - 1),
+            format!("nust_pane1_{now}.txt"),
+            format!("nust_pane2_{}.txt", now - 10),
+        ];
+        assert_eq!(names(&dir), expected);
+    }
+
+    #[test]
+    fn prune_drops_old_snapshots_but_not_protected_ones() {
+        let dir = TempDir::new("quicksave-max-age");
+        let now = now();
+        let day = 24 * 60 * 60;
+        dir.file(&format!("nust_pane1_{now}.txt"), b"x");
+        dir.file(&format!("nust_pane1_{}.txt", now - 3 * day), b"x");
+        let open = dir.file(&format!("nust_pane2_{}.txt", now - 5 * day), b"x");
+        let policy = RetentionPolicy {
+            keep_last: 0,
+            max_age_days: 2,
+            max_total_mb: 0,
+        };
+        assert_eq!(policy.prune(dir.path(), &[open]).unwrap(), 1);
+        let expected = [format!("nust_pane1_{now}.txt"), format!("nust_pane2_{}.txt", now - 5 * day)];
+        assert_eq!(names(&dir), expected);
+    }
+
+    #[test]
+    fn prune_drops_the_oldest_over_the_size_limit() {
+        let dir = TempDir::new("quicksave-max-total");
+        let now = now();
+        let mb = 1024 * 1024;
+        dir.file(&format!("nust_pane1_{now}.txt"), vec![b'x'; mb / 2]);
+        dir.file(&format!("nust_pane2_{}.txt", now - 1), vec![b'x'; mb / 2]);
+        dir.file(&format!("nust_pane1_{}.txt", now - 2), vec![b'x'; mb / 2]);
+        let policy = RetentionPolicy {
+            keep_last: 0,
+            max_age_days: 0,
+            max_total_mb: 1,
+        };
+        assert_eq!(policy.prune(dir.path(), &[]).unwrap(), 1);
+        let expected = [format!("nust_pane1_{now}.txt"), format!("nust_pane2_{}.txt", now - 1)];
+        assert_eq!(names(&dir), expected);
+    }
+}
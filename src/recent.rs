@@ -0,0 +1,191 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::fileio::{self, BackupMode};
+
+/// Files remembered, most recent first.
+const MAX_FILES: usize = 20;
+/// Directories remembered, most recent first.
+const MAX_DIRS: usize = 10;
+
+/// Files opened or saved lately and the directories they live in, kept in
+/// the state directory across runs.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecentFiles {
+    pub files: Vec<PathBuf>,
+    pub dirs: Vec<PathBuf>,
+    /// Where the lists were loaded from and are saved to; `None` keeps them
+    /// in memory only.
+    #[serde(skip)]
+    file: Option<PathBuf>,
+    /// Why the lists could not be saved, until the app shows it.
+    #[serde(skip)]
+    failure: Option<String>,
+}
+
+fn recent_path() -> Option<PathBuf> {
+    fileio::state_dir().map(|dir| dir.join("recent_files.json"))
+}
+
+/// Put `item` at the front of `list`, dropping an older copy and the overflow.
+fn push_front(list: &mut Vec<PathBuf>, item: PathBuf, max: usize) {
+    list.retain(|existing| *existing != item);
+    list.insert(0, item);
+    list.truncate(max);
+}
+
+impl RecentFiles {
+    /// The saved lists, minus entries that no longer exist.
+    pub fn load() -> Self {
+        recent_path().map(Self::load_from).unwrap_or_default()
+    }
+
+    fn load_from(file: PathBuf) -> Self {
+        let mut recent: Self = fs::read(&file)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        recent.file = Some(file);
+        recent.prune();
+        recent
+    }
+
+    /// Note that `path` was just opened or saved, and remember its directory.
+    pub fn add(&mut self, path: &Path) {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if let Some(dir) = path.parent() {
+            push_front(&mut self.dirs, dir.to_path_buf(), MAX_DIRS);
+        }
+        push_front(&mut self.files, path, MAX_FILES);
+        self.save_or_report();
+    }
+
+    /// Forget `path`, e.g. after it failed to open.
+    pub fn remove(&mut self, path: &Path) {
+        let before = self.files.len();
+        self.files.retain(|existing| existing != path);
+        if self.files.len() != before {
+            self.save_or_report();
+        }
+    }
+
+    /// Drop entries whose file or directory is gone. Returns how many went.
+    pub fn prune(&mut self) -> usize {
+        let before = self.files.len() + self.dirs.len();
+        self.files.retain(|path| path.is_file());
+        self.dirs.retain(|path| path.is_dir());
+        let pruned = before - self.files.len() - self.dirs.len();
+        if pruned > 0 {
+            self.save_or_report();
+        }
+        pruned
+    }
+
+    pub fn clear(&mut self) {
+        self.files.clear();
+        self.dirs.clear();
+        self.save_or_report();
+    }
+
+    /// Where file dialogs should start.
+    pub fn last_dir(&self) -> Option<&Path> {
+        self.dirs.first().map(PathBuf::as_path)
+    }
+
+    /// Why saving last failed, once, for the status bar.
+    pub fn take_failure(&mut self) -> Option<String> {
+        self.failure.take()
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+        }
+        fileio::write_atomic(path, &serde_json::to_vec_pretty(self)?, BackupMode::Off)?;
+        Ok(())
+    }
+
+    fn save_or_report(&mut self) {
+        if let Err(e) = self.save() {
+            self.failure = Some(format!("Could not save recent files: {e:#}"));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn newest_first_without_duplicates_and_capped() {
+        let dir = TempDir::new("recent-mru");
+        let mut recent = RecentFiles::default();
+        let files: Vec<PathBuf> = (0..=MAX_FILES)
+            .map(|n| dir.file(&format!("{n}.txt"), "").canonicalize().unwrap())
+            .collect();
+        for file in &files {
+            recent.add(file);
+        }
+        recent.add(&files[5]);
+        assert_eq!(recent.files.len(), MAX_FILES);
+        assert_eq!(recent.files[..2], [files[5].clone(), files[MAX_FILES].clone()]);
+        assert_eq!(recent.files.iter().filter(|file| **file == files[5]).count(), 1);
+        assert!(!recent.files.contains(&files[0]));
+        assert_eq!(recent.last_dir(), files[5].parent());
+        assert_eq!(recent.dirs.len(), 1);
+    }
+
+    #[test]
+    fn gone_entries_are_pruned_and_saved() {
+        let dir = TempDir::new("recent-prune");
+        let store = dir.path().join("recent_files.json");
+        let mut recent = RecentFiles::load_from(store.clone());
+        let kept = dir.file("kept.txt", "");
+        let gone = dir.file("gone.txt", "");
+        let gone_dir = dir.path().join("gone");
+        fs::create_dir(&gone_dir).unwrap();
+        let inside = dir.file("gone/inside.txt", "");
+        for file in [&kept, &gone, &inside] {
+            recent.add(file);
+        }
+        fs::remove_file(&gone).unwrap();
+        fs::remove_dir_all(&gone_dir).unwrap();
+        assert_eq!(recent.prune(), 3);
+        assert_eq!(recent.prune(), 0);
+
+        let reloaded = RecentFiles::load_from(store);
+        assert_eq!(reloaded.files, [kept.canonicalize().unwrap()]);
+        assert_eq!(reloaded.dirs, [dir.path().canonicalize().unwrap()]);
+        assert_eq!(recent.take_failure(), None);
+    }
+
+    #[test]
+    fn loading_drops_what_went_missing_since() {
+        let dir = TempDir::new("recent-load");
+        let store = dir.path().join("recent_files.json");
+        let gone = dir.file("gone.txt", "");
+        RecentFiles::load_from(store.clone()).add(&gone);
+        fs::remove_file(&gone).unwrap();
+        let recent = RecentFiles::load_from(store);
+        assert!(recent.files.is_empty());
+        assert_eq!(recent.dirs.len(), 1);
+    }
+
+    #[test]
+    fn failed_saves_are_kept_for_the_status_bar() {
+        let dir = TempDir::new("recent-failure");
+        let not_a_dir = dir.file("state", "");
+        let mut recent = RecentFiles::load_from(not_a_dir.join("recent_files.json"));
+        recent.add(&not_a_dir);
+        let failure = recent.take_failure().unwrap();
+        assert!(failure.starts_with("Could not save recent files: "), "{failure}");
+        assert_eq!(recent.take_failure(), None);
+    }
+}
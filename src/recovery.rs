@@ -0,0 +1,444 @@
+use anyhow::{Context, Result};
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::Pane;
+use crate::buffer::Buffer;
+use crate::diff::DiffView;
+use crate::fileio::{self, BackupMode};
+
+/// How often dirty buffers are copied into the journal.
+pub const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A dirty buffer as written to the recovery directory.
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    pub title: String,
+    pub path: Option<PathBuf>,
+    pub text: String,
+    /// Seconds since the Unix epoch.
+    pub saved_at: u64,
+}
+
+/// A snapshot left behind by an earlier run, offered in the recover dialog.
+pub struct Recoverable {
+    pub file: PathBuf,
+    pub snapshot: Snapshot,
+    /// Loaded on demand when the user asks for the diff, or why it could
+    /// not be.
+    pub disk_text: Option<Result<String, String>>,
+    pub show_diff: bool,
+    pub diff: DiffView<()>,
+}
+
+enum Job {
+    /// Write `text` (cheap to clone, costly to copy out) as the snapshot's.
+    Write { doc_id: u64, snapshot: Snapshot, text: Buffer },
+    Remove(u64),
+    /// Clean exit: nothing needs recovering.
+    Clear,
+}
+
+/// Background writer for recovery snapshots. The UI thread only decides what
+/// changed; copying buffers to disk happens on the journal's own thread.
+pub struct Journal {
+    jobs: Sender<Job>,
+    worker: Option<JoinHandle<()>>,
+    /// Per document, the buffer version last sent to the worker.
+    written: HashMap<u64, u64>,
+    last_snapshot: Option<Instant>,
+    /// Held for as long as we run, so other instances can tell our
+    /// snapshots are not theirs to recover.
+    lock: Option<(File, PathBuf)>,
+    /// What went wrong writing snapshots, for the status bar.
+    failures: Receiver<String>,
+}
+
+fn recovery_dir() -> Option<PathBuf> {
+    fileio::state_dir().map(|dir| dir.join("recovery"))
+}
+
+/// Snapshot files are named `<pid>-<doc_id>.json` so a later run can tell
+/// its own from those an earlier one left behind.
+fn snapshot_file(dir: &Path, doc_id: u64) -> PathBuf {
+    dir.join(format!("{}-{doc_id}.json", std::process::id()))
+}
+
+/// Each run holds an exclusive lock on `<pid>.lock` while it is up.
+fn lock_file(dir: &Path, pid: u32) -> PathBuf {
+    dir.join(format!("{pid}.lock"))
+}
+
+/// Create and lock this run's lock file.
+fn take_lock(dir: &Path) -> Result<(File, PathBuf)> {
+    fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    let path = lock_file(dir, std::process::id());
+    let file = File::create(&path).with_context(|| format!("creating {}", path.display()))?;
+    file.try_lock().with_context(|| format!("locking {}", path.display()))?;
+    Ok((file, path))
+}
+
+impl Journal {
+    /// Start the writer thread; `None` if there is no per-user state directory.
+    pub fn start() -> Option<Self> {
+        let dir = recovery_dir()?;
+        let (report, failures) = mpsc::channel();
+        let lock = take_lock(&dir)
+            .map_err(|e| report.send(format!("Recovery journal: {e:#}")))
+            .ok();
+        let (jobs, rx) = mpsc::channel();
+        let worker = std::thread::Builder::new()
+            .name("recovery-journal".into())
+            .spawn(move || run_worker(&dir, rx, &report))
+            .ok()?;
+        Some(Self {
+            jobs,
+            worker: Some(worker),
+            written: HashMap::new(),
+            last_snapshot: None,
+            lock,
+            failures,
+        })
+    }
+
+    /// Queue snapshots of documents that changed since the last round and
+    /// drop those that were saved or closed. Cheap when nothing changed.
+    pub fn update<'a>(&mut self, docs: impl Iterator<Item = &'a Pane>, now: Instant) {
+        if self
+            .last_snapshot
+            .is_some_and(|last| now.duration_since(last) < SNAPSHOT_INTERVAL)
+        {
+            return;
+        }
+        self.last_snapshot = Some(now);
+        let mut live = Vec::new();
+        for doc in docs.filter(|doc| doc.is_dirty()) {
+            live.push(doc.doc_id);
+            self.write(doc);
+        }
+        let jobs = &self.jobs;
+        self.written.retain(|doc_id, _| {
+            let keep = live.contains(doc_id);
+            if !keep {
+                let _ = jobs.send(Job::Remove(*doc_id));
+            }
+            keep
+        });
+    }
+
+    /// Snapshot `doc` unless the journal already holds this version of it.
+    /// Autosave calls this for untitled documents, which have no file to
+    /// be saved to.
+    pub fn write(&mut self, doc: &Pane) {
+        let version = doc.text.version();
+        if self.written.insert(doc.doc_id, version) == Some(version) {
+            return;
+        }
+        let snapshot = Snapshot {
+            title: doc.title.clone(),
+            path: doc.path.clone(),
+            text: String::new(),
+            saved_at: unix_now(),
+        };
+        let _ = self.jobs.send(Job::Write {
+            doc_id: doc.doc_id,
+            snapshot,
+            text: doc.text.clone(),
+        });
+    }
+
+    /// The latest failure since the last call, if any.
+    pub fn take_failure(&self) -> Option<String> {
+        self.failures.try_iter().last()
+    }
+
+    /// Delete this run's snapshots and wait for the writer to finish.
+    pub fn finish(mut self) {
+        let _ = self.jobs.send(Job::Clear);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+        if let Some((file, path)) = self.lock.take() {
+            drop(file);
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+fn run_worker(dir: &Path, jobs: Receiver<Job>, report: &Sender<String>) {
+    for job in jobs {
+        let result = match job {
+            Job::Write {
+                doc_id,
+                mut snapshot,
+                text,
+            } => {
+                snapshot.text = text.to_string();
+                write_snapshot(dir, doc_id, &snapshot)
+            }
+            Job::Remove(doc_id) => remove_snapshot(&snapshot_file(dir, doc_id)),
+            Job::Clear => {
+                let prefix = format!("{}-", std::process::id());
+                for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+                    if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                        let _ = fs::remove_file(entry.path());
+                    }
+                }
+                return;
+            }
+        };
+        if let Err(e) = result {
+            let _ = report.send(format!("Recovery journal: {e:#}"));
+        }
+    }
+}
+
+fn write_snapshot(dir: &Path, doc_id: u64, snapshot: &Snapshot) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    let json = serde_json::to_vec(snapshot)?;
+    fileio::write_atomic(&snapshot_file(dir, doc_id), &json, BackupMode::Off)?;
+    Ok(())
+}
+
+pub fn remove_snapshot(file: &Path) -> Result<()> {
+    match fs::remove_file(file) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("removing {}", file.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Whether the run that wrote a snapshot is still going (so its buffers are
+/// not ours to recover): its lock file is there and still locked.
+fn owner_running(file: &Path) -> bool {
+    let pid = file
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.split('-').next())
+        .and_then(|pid| pid.parse::<u32>().ok());
+    let (Some(pid), Some(dir)) = (pid, file.parent()) else {
+        return false;
+    };
+    if pid == std::process::id() {
+        return true;
+    }
+    // When unsure, leave the snapshot for a later start rather than risk
+    // offering up a live run's buffers
+    match File::open(lock_file(dir, pid)) {
+        Ok(lock) => lock.try_lock().is_err(),
+        Err(e) => e.kind() != std::io::ErrorKind::NotFound,
+    }
+}
+
+/// Snapshots left by runs that did not exit cleanly, oldest first.
+pub fn leftovers() -> Vec<Recoverable> {
+    recovery_dir().map_or_else(Vec::new, |dir| leftovers_in(&dir))
+}
+
+fn leftovers_in(dir: &Path) -> Vec<Recoverable> {
+    let mut found: Vec<Recoverable> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|file| file.extension().is_some_and(|ext| ext == "json") && !owner_running(file))
+        .filter_map(|file| {
+            let snapshot = serde_json::from_slice(&fs::read(&file).ok()?).ok()?;
+            Some(Recoverable {
+                file,
+                snapshot,
+                disk_text: None,
+                show_diff: false,
+                diff: DiffView::default(),
+            })
+        })
+        .collect();
+    found.sort_by_key(|item| item.snapshot.saved_at);
+    found
+}
+
+/// What the user picked for one entry of the recover dialog.
+pub enum RecoverChoice {
+    Recover(usize),
+    Discard(usize),
+    /// Close the dialog; the snapshots are offered again next start.
+    Later,
+}
+
+/// The "Recover unsaved documents" dialog.
+pub fn recover_dialog(ctx: &egui::Context, items: &mut [Recoverable]) -> Option<RecoverChoice> {
+    let mut choice = None;
+    let mut open = true;
+    egui::Window::new("Recover unsaved documents")
+        .open(&mut open)
+        .default_size([640.0, 420.0])
+        .show(ctx, |ui| {
+            ui.label("nust did not exit cleanly last time. These documents had unsaved changes:");
+            ui.add_space(4.0);
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for (idx, item) in items.iter_mut().enumerate() {
+                    ui.separator();
+                    let location = item
+                        .snapshot
+                        .path
+                        .as_ref()
+                        .map_or_else(|| "untitled".to_owned(), |p| p.display().to_string());
+                    ui.horizontal(|ui| {
+                        ui.strong(&item.snapshot.title);
+                        ui.weak(location);
+                    });
+                    ui.horizontal(|ui| {
+                        if ui.button("Recover").clicked() {
+                            choice = Some(RecoverChoice::Recover(idx));
+                        }
+                        if ui.button("Discard").clicked() {
+                            choice = Some(RecoverChoice::Discard(idx));
+                        }
+                        if item.snapshot.path.is_some() {
+                            ui.checkbox(&mut item.show_diff, "Diff against file on disk");
+                        }
+                    });
+                    if item.show_diff {
+                        let disk = item.disk_text.get_or_insert_with(|| match &item.snapshot.path {
+                            Some(path) => fileio::read_normalized(path).map_err(|e| e.to_string()),
+                            None => Ok(String::new()),
+                        });
+                        let disk = match disk {
+                            Ok(disk) => disk,
+                            Err(e) => {
+                                let error = ui.visuals().error_fg_color;
+                                ui.colored_label(error, format!("Could not read the file on disk: {e}"));
+                                continue;
+                            }
+                        };
+                        ui.push_id(idx, |ui| {
+                            ui.set_max_height(240.0);
+                            let snapshot = &item.snapshot.text;
+                            item.diff.show(ui, (), || (&*disk, snapshot));
+                        });
+                    }
+                }
+            });
+            ui.separator();
+            if ui.button("Decide later").clicked() {
+                choice = Some(RecoverChoice::Later);
+            }
+        });
+    if !open {
+        choice = Some(RecoverChoice::Later);
+    }
+    choice
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    fn snapshot(title: &str, saved_at: u64) -> Snapshot {
+        Snapshot {
+            title: title.to_owned(),
+            path: None,
+            text: format!("{title} text"),
+            saved_at,
+        }
+    }
+
+    /// Write `snapshot` as if a run with `pid` had left it behind.
+    fn leave(dir: &TempDir, pid: u32, doc_id: u64, snapshot: &Snapshot) -> PathBuf {
+        dir.file(&format!("{pid}-{doc_id}.json"), serde_json::to_vec(snapshot).unwrap())
+    }
+
+    fn titles(dir: &TempDir) -> Vec<String> {
+        leftovers_in(dir.path()).into_iter().map(|item| item.snapshot.title).collect()
+    }
+
+    #[test]
+    fn snapshots_are_named_after_the_run_and_document() {
+        let file = snapshot_file(Path::new("recovery"), 7);
+        assert_eq!(file, Path::new("recovery").join(format!("{}-7.json", std::process::id())));
+        assert!(owner_running(&file));
+        assert!(!owner_running(Path::new("recovery/notes.json")));
+    }
+
+    #[test]
+    fn leftovers_skip_runs_that_hold_their_lock() {
+        let dir = TempDir::new("recovery-leftovers");
+        // Pids no live process has, standing in for other runs
+        let (crashed, live) = (u32::MAX - 1, u32::MAX - 2);
+        leave(&dir, crashed, 1, &snapshot("newer", 200));
+        leave(&dir, crashed, 2, &snapshot("older", 100));
+        leave(&dir, live, 1, &snapshot("live", 50));
+        leave(&dir, std::process::id(), 1, &snapshot("ours", 10));
+        dir.file("garbled.json", "{");
+        let lock = File::create(lock_file(dir.path(), live)).unwrap();
+        lock.try_lock().unwrap();
+        assert_eq!(titles(&dir), ["older", "newer"]);
+        // Once that run is gone its snapshots are up for recovery too
+        drop(lock);
+        assert_eq!(titles(&dir), ["live", "older", "newer"]);
+    }
+
+    #[test]
+    fn discarding_removes_the_snapshot() {
+        let dir = TempDir::new("recovery-discard");
+        let file = leave(&dir, u32::MAX - 1, 1, &snapshot("gone", 1));
+        remove_snapshot(&file).unwrap();
+        assert!(titles(&dir).is_empty());
+        // Already gone is fine
+        remove_snapshot(&file).unwrap();
+    }
+
+    #[test]
+    fn worker_writes_removes_and_clears_our_snapshots() {
+        let dir = TempDir::new("recovery-worker");
+        let other = leave(&dir, u32::MAX - 1, 1, &snapshot("other", 1));
+        let (report, failures) = mpsc::channel();
+        let (jobs, rx) = mpsc::channel();
+        for doc_id in [1, 2] {
+            let (snapshot, text) = (snapshot("mine", 1), Buffer::from("unsaved"));
+            jobs.send(Job::Write { doc_id, snapshot, text }).unwrap();
+        }
+        jobs.send(Job::Remove(2)).unwrap();
+        drop(jobs);
+        run_worker(dir.path(), rx, &report);
+        let written = fs::read(snapshot_file(dir.path(), 1)).unwrap();
+        let written: Snapshot = serde_json::from_slice(&written).unwrap();
+        assert_eq!(written.text, "unsaved");
+        assert!(!snapshot_file(dir.path(), 2).exists());
+
+        let (jobs, rx) = mpsc::channel();
+        jobs.send(Job::Clear).unwrap();
+        run_worker(dir.path(), rx, &report);
+        assert!(!snapshot_file(dir.path(), 1).exists());
+        assert!(other.exists());
+        assert_eq!(failures.try_recv().ok(), None);
+    }
+
+    #[test]
+    fn worker_reports_failed_writes() {
+        let dir = TempDir::new("recovery-failure");
+        let not_a_dir = dir.file("recovery", "");
+        let (report, failures) = mpsc::channel();
+        let (jobs, rx) = mpsc::channel();
+        let (snapshot, text) = (snapshot("mine", 1), Buffer::from("unsaved"));
+        jobs.send(Job::Write { doc_id: 1, snapshot, text }).unwrap();
+        drop(jobs);
+        run_worker(&not_a_dir, rx, &report);
+        let failure = failures.try_recv().unwrap();
+        assert!(failure.starts_with("Recovery journal: "), "{failure}");
+    }
+}
@@ -0,0 +1,276 @@
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+use std::fs;
+use std::ops::Range;
+use std::path::PathBuf;
+
+use crate::encoding::TextEncoding;
+use crate::fileio::{self, BackupMode, DiskStamp};
+use crate::find;
+use crate::search::preview;
+
+/// One proposed replacement in a file.
+pub struct Change {
+    /// Zero-based line number.
+    pub line: usize,
+    /// Byte range in the file's text.
+    range: Range<usize>,
+    replacement: String,
+    /// The line before and after this change alone, cut to fit the preview.
+    pub old_line: String,
+    pub new_line: String,
+    pub include: bool,
+}
+
+/// The replacements proposed for one file, and the text they were worked
+/// out against.
+pub struct FilePlan {
+    pub path: PathBuf,
+    text: String,
+    encoding: TextEncoding,
+    pub changes: Vec<Change>,
+}
+
+impl FilePlan {
+    pub fn included(&self) -> usize {
+        self.changes.iter().filter(|change| change.include).count()
+    }
+
+    /// The file's text with the included changes made.
+    fn replaced_text(&self) -> String {
+        let mut text = String::with_capacity(self.text.len());
+        let mut last = 0;
+        for change in self.changes.iter().filter(|change| change.include) {
+            text.push_str(&self.text[last..change.range.start]);
+            text.push_str(&change.replacement);
+            last = change.range.end;
+        }
+        text.push_str(&self.text[last..]);
+        text
+    }
+}
+
+/// Work out every replacement of `regex` in `paths`, line by line as Find
+/// in Files matches. With `groups`, `$1`-style references in `replacement`
+/// are expanded. Files that cannot be read as text are left out and named
+/// in the returned problems.
+pub fn plan(
+    paths: impl IntoIterator<Item = PathBuf>,
+    regex: &Regex,
+    replacement: &str,
+    groups: bool,
+) -> (Vec<FilePlan>, Vec<String>) {
+    let mut plans = Vec::new();
+    let mut problems = Vec::new();
+    for path in paths {
+        let (text, encoding, _) = match fileio::read_text(&path, None) {
+            Ok(read) => read,
+            Err(e) => {
+                problems.push(e.to_string());
+                continue;
+            }
+        };
+        let mut changes = Vec::new();
+        let mut offset = 0;
+        for (line_no, raw) in text.split_inclusive('\n').enumerate() {
+            let line = raw.strip_suffix('\n').unwrap_or(raw);
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            for m in regex.find_iter(line).filter(|m| !m.is_empty()) {
+                let with = find::expand(regex, line, m.range(), replacement, groups);
+                let new_line = format!("{}{with}{}", &line[..m.start()], &line[m.end()..]);
+                changes.push(Change {
+                    line: line_no,
+                    range: offset + m.start()..offset + m.end(),
+                    old_line: preview(line, m.range()).0,
+                    new_line: preview(&new_line, m.start()..m.start() + with.len()).0,
+                    replacement: with,
+                    include: true,
+                });
+            }
+            offset += raw.len();
+        }
+        if !changes.is_empty() {
+            plans.push(FilePlan {
+                path,
+                text,
+                encoding,
+                changes,
+            });
+        }
+    }
+    (plans, problems)
+}
+
+/// A file a replace rewrote: what it held before, and what we wrote.
+struct Touched {
+    path: PathBuf,
+    before: Vec<u8>,
+    after: DiskStamp,
+}
+
+/// Everything one replace wrote, so "Undo Replace" can put it all back.
+pub struct ReplaceUndo {
+    files: Vec<Touched>,
+}
+
+/// The outcome of `apply`.
+pub struct Applied {
+    pub undo: ReplaceUndo,
+    pub changes: usize,
+    /// Files that were skipped, with the reason.
+    pub problems: Vec<String>,
+}
+
+fn apply_file(plan: &FilePlan, backup: BackupMode) -> Result<Touched> {
+    let before = fs::read(&plan.path).with_context(|| format!("reading {}", plan.path.display()))?;
+    if plan.encoding.decode(&before).as_deref() != Some(plan.text.as_str()) {
+        bail!("{} changed since the preview", plan.path.display());
+    }
+    let bytes = plan.encoding.encode(&plan.replaced_text())?;
+    let after = fileio::write_atomic(&plan.path, &bytes, backup)
+        .with_context(|| format!("writing {}", plan.path.display()))?;
+    Ok(Touched {
+        path: plan.path.clone(),
+        before,
+        after,
+    })
+}
+
+/// Write the included changes, one atomic write per file. A file that
+/// changed since its plan was made is skipped rather than overwritten.
+pub fn apply(plans: &[FilePlan], backup: BackupMode) -> Applied {
+    let mut files = Vec::new();
+    let mut changes = 0;
+    let mut problems = Vec::new();
+    for plan in plans.iter().filter(|plan| plan.included() > 0) {
+        match apply_file(plan, backup) {
+            Ok(touched) => {
+                files.push(touched);
+                changes += plan.included();
+            }
+            Err(e) => problems.push(format!("{e:#}")),
+        }
+    }
+    Applied {
+        undo: ReplaceUndo { files },
+        changes,
+        problems,
+    }
+}
+
+fn undo_file(touched: &Touched) -> Result<()> {
+    if fileio::check_disk(&touched.path, &touched.after).is_some() {
+        bail!("{} changed after the replace", touched.path.display());
+    }
+    fileio::write_atomic(&touched.path, &touched.before, BackupMode::Off)
+        .with_context(|| format!("restoring {}", touched.path.display()))?;
+    Ok(())
+}
+
+impl ReplaceUndo {
+    pub fn files(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Put every touched file back as it was, leaving alone any that
+    /// changed again since. Returns how many were restored, and the rest.
+    pub fn undo(self) -> (usize, Vec<String>) {
+        let mut restored = 0;
+        let mut problems = Vec::new();
+        for touched in &self.files {
+            match undo_file(touched) {
+                Ok(()) => restored += 1,
+                Err(e) => problems.push(format!("{e:#}")),
+            }
+        }
+        (restored, problems)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::find::FindOptions;
+    use crate::testutil::TempDir;
+
+    fn regex(query: &str) -> Regex {
+        find::build_regex(query, FindOptions::default()).unwrap()
+    }
+
+    fn plan_one(path: &std::path::Path, query: &str, replacement: &str) -> FilePlan {
+        let (mut plans, problems) = plan([path.to_path_buf()], &regex(query), replacement, false);
+        assert!(problems.is_empty(), "{problems:?}");
+        plans.remove(0)
+    }
+
+    #[test]
+    fn plan_finds_byte_ranges_across_crlf_and_multibyte_text() {
+        let dir = TempDir::new("replace-plan");
+        let path = dir.file("notes.txt", "héllo wörld\r\n\r\nwörld, wörld\r\n");
+        let plan = plan_one(&path, "wörld", "earth");
+        let found: Vec<(usize, &str)> = plan
+            .changes
+            .iter()
+            .map(|change| (change.line, &plan.text[change.range.clone()]))
+            .collect();
+        assert_eq!(found, [(0, "wörld"), (2, "wörld"), (2, "wörld")]);
+        let first = &plan.changes[0];
+        assert_eq!((first.old_line.as_str(), first.new_line.as_str()), ("héllo wörld", "héllo earth"));
+        assert_eq!(plan.replaced_text(), "héllo earth\r\n\r\nearth, earth\r\n");
+    }
+
+    #[test]
+    fn plan_names_unreadable_files_and_skips_ones_without_hits() {
+        let dir = TempDir::new("replace-problems");
+        let paths = [
+            dir.file("binary.bin", b"x\0y"),
+            dir.file("other.txt", "nothing here"),
+            dir.path().join("missing.txt"),
+        ];
+        let (plans, problems) = plan(paths, &regex("x"), "y", false);
+        assert!(plans.is_empty());
+        assert_eq!(problems.len(), 2);
+    }
+
+    #[test]
+    fn excluded_hits_are_left_alone() {
+        let dir = TempDir::new("replace-excluded");
+        let path = dir.file("list.txt", "a a a\n");
+        let mut plans = vec![plan_one(&path, "a", "b")];
+        plans[0].changes[1].include = false;
+        assert_eq!(plans[0].included(), 2);
+        assert_eq!(plans[0].replaced_text(), "b a b\n");
+        let applied = apply(&plans, BackupMode::Off);
+        assert_eq!((applied.changes, applied.undo.files()), (2, 1));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "b a b\n");
+    }
+
+    #[test]
+    fn apply_skips_files_changed_since_the_preview() {
+        let dir = TempDir::new("replace-stale");
+        let path = dir.file("stale.txt", "old\n");
+        let plans = vec![plan_one(&path, "old", "new")];
+        fs::write(&path, "old, edited elsewhere\n").unwrap();
+        let applied = apply(&plans, BackupMode::Off);
+        assert_eq!((applied.changes, applied.undo.files()), (0, 0));
+        assert!(applied.problems[0].contains("changed since the preview"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old, edited elsewhere\n");
+    }
+
+    #[test]
+    fn undo_restores_the_old_bytes_unless_the_file_changed_again() {
+        let dir = TempDir::new("replace-undo");
+        let kept = dir.file("kept.txt", b"caf\xe9 one\r\n");
+        let edited = dir.file("edited.txt", "one\n");
+        let plans = vec![plan_one(&kept, "one", "two"), plan_one(&edited, "one", "two")];
+        let applied = apply(&plans, BackupMode::Off);
+        assert_eq!(fs::read(&kept).unwrap(), b"caf\xe9 two\r\n");
+        // Edited again after the replace: undo must not clobber that
+        fs::write(&edited, "two, and more\n").unwrap();
+        let (restored, problems) = applied.undo.undo();
+        assert_eq!(restored, 1);
+        assert!(problems[0].contains("changed after the replace"));
+        assert_eq!(fs::read(&kept).unwrap(), b"caf\xe9 one\r\n");
+        assert_eq!(fs::read_to_string(&edited).unwrap(), "two, and more\n");
+    }
+}
@@ -0,0 +1,624 @@
+use eframe::egui;
+use ignore::WalkBuilder;
+use ignore::overrides::OverrideBuilder;
+use regex::Regex;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+
+use crate::diff::line_change;
+use crate::fileio;
+use crate::find::{self, FindOptions};
+use crate::fuzzy::highlight;
+use crate::replace::{self, FilePlan};
+
+/// The search stops collecting after this many hits.
+const MAX_HITS: usize = 5000;
+/// Files larger than this are skipped.
+const MAX_FILE_BYTES: u64 = 20 * 1024 * 1024;
+/// Characters of a hit's line shown in the results.
+const PREVIEW_CHARS: usize = 160;
+/// Characters kept before the match when a long line is cut.
+const PREVIEW_LEAD: usize = 40;
+
+/// One matching line.
+pub struct Hit {
+    /// Zero-based line number.
+    pub line: usize,
+    /// Zero-based char column of the match.
+    pub column: usize,
+    /// The line, trimmed and cut to fit, for the results list.
+    pub preview: String,
+    /// Char range of the match within `preview`.
+    pub matched: Range<usize>,
+}
+
+/// The hits in one file, in line order.
+pub struct FileHits {
+    pub path: PathBuf,
+    pub hits: Vec<Hit>,
+}
+
+enum SearchEvent {
+    File(FileHits),
+    Finished { files: usize, truncated: bool },
+}
+
+/// A search running on its own thread. Results arrive through `poll`;
+/// dropping it stops the thread.
+pub struct Search {
+    events: Receiver<SearchEvent>,
+    cancel: Arc<AtomicBool>,
+    pub root: PathBuf,
+    /// What was searched for, for working out replacements later.
+    regex: Regex,
+    options: FindOptions,
+    pub results: Vec<FileHits>,
+    /// Files looked at, once the walk is over.
+    pub finished: Option<usize>,
+    /// The walk stopped at `MAX_HITS`.
+    pub truncated: bool,
+}
+
+impl Search {
+    /// Start searching `root` for `query`, skipping what .gitignore files
+    /// and the `exclude` globs leave out. The context is repainted as
+    /// results come in.
+    pub fn start(
+        ctx: &egui::Context,
+        root: PathBuf,
+        query: &str,
+        options: FindOptions,
+        exclude: &[String],
+    ) -> Result<Self, String> {
+        let regex = find::build_regex(query, options).map_err(|e| e.to_string())?;
+        let mut overrides = OverrideBuilder::new(&root);
+        for glob in exclude {
+            overrides
+                .add(&format!("!{glob}"))
+                .map_err(|e| format!("search.exclude \"{glob}\": {e}"))?;
+        }
+        let overrides = overrides.build().map_err(|e| e.to_string())?;
+        let walk = WalkBuilder::new(&root)
+            // Notes folders are rarely git checkouts; honour .gitignore anyway
+            .require_git(false)
+            .overrides(overrides)
+            .build();
+
+        let (tx, events) = mpsc::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let stop = Arc::clone(&cancel);
+        let ctx = ctx.clone();
+        let pattern = regex.clone();
+        std::thread::Builder::new()
+            .name("find-in-files".into())
+            .spawn(move || run_search(walk, &pattern, &tx, &stop, &ctx))
+            .map_err(|e| format!("Could not start the search: {e}"))?;
+        Ok(Self {
+            events,
+            cancel,
+            root,
+            regex,
+            options,
+            results: Vec::new(),
+            finished: None,
+            truncated: false,
+        })
+    }
+
+    /// Take in whatever the search thread found since the last call.
+    pub fn poll(&mut self) {
+        for event in self.events.try_iter() {
+            match event {
+                SearchEvent::File(file) => self.results.push(file),
+                SearchEvent::Finished { files, truncated } => {
+                    self.finished = Some(files);
+                    self.truncated = truncated;
+                }
+            }
+        }
+    }
+
+    pub fn running(&self) -> bool {
+        self.finished.is_none() && !self.cancel.load(Ordering::Relaxed)
+    }
+
+    pub fn stop(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn hit_count(&self) -> usize {
+        self.results.iter().map(|file| file.hits.len()).sum()
+    }
+}
+
+impl Drop for Search {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn run_search(walk: ignore::Walk, regex: &Regex, tx: &Sender<SearchEvent>, cancel: &AtomicBool, ctx: &egui::Context) {
+    let mut files = 0;
+    let mut total = 0;
+    let mut truncated = false;
+    for entry in walk.flatten() {
+        if cancel.load(Ordering::Relaxed) {
+            break;
+        }
+        if !entry.file_type().is_some_and(|kind| kind.is_file()) {
+            continue;
+        }
+        let Some(text) = read_text(entry.path()) else {
+            continue;
+        };
+        files += 1;
+        let hits = search_text(&text, regex, MAX_HITS - total);
+        if hits.is_empty() {
+            continue;
+        }
+        total += hits.len();
+        let file = FileHits {
+            path: entry.into_path(),
+            hits,
+        };
+        if tx.send(SearchEvent::File(file)).is_err() {
+            return;
+        }
+        ctx.request_repaint();
+        if total >= MAX_HITS {
+            truncated = true;
+            break;
+        }
+    }
+    let _ = tx.send(SearchEvent::Finished { files, truncated });
+    ctx.request_repaint();
+}
+
+/// The file as text, or `None` if it is too big, unreadable or binary.
+/// Decoded just as a replace will read it (Latin-1 logs come out as
+/// Windows-1252), so every hit can be previewed and replaced.
+fn read_text(path: &Path) -> Option<String> {
+    if fs::metadata(path).ok()?.len() > MAX_FILE_BYTES {
+        return None;
+    }
+    fileio::read_text(path, None).ok().map(|(text, _, _)| text)
+}
+
+/// Up to `limit` hits in `text`, one per match.
+pub fn search_text(text: &str, regex: &Regex, limit: usize) -> Vec<Hit> {
+    let mut hits = Vec::new();
+    for (line_no, line) in text.lines().enumerate() {
+        for m in regex.find_iter(line).filter(|m| !m.is_empty()) {
+            if hits.len() >= limit {
+                return hits;
+            }
+            let (preview, matched) = preview(line, m.range());
+            hits.push(Hit {
+                line: line_no,
+                column: line[..m.start()].chars().count(),
+                preview,
+                matched,
+            });
+        }
+    }
+    hits
+}
+
+/// `line` without its indentation, cut to `PREVIEW_CHARS` around the byte
+/// range `range`, and where the match sits in it (in chars).
+pub fn preview(line: &str, range: Range<usize>) -> (String, Range<usize>) {
+    let start = line[..range.start].chars().count();
+    let len = line[range].chars().count();
+    let indent = line.chars().take_while(|c| c.is_whitespace()).count().min(start);
+    let from = start.saturating_sub(PREVIEW_LEAD).max(indent);
+    let mut preview: String = line.chars().skip(from).take(PREVIEW_CHARS).collect();
+    let mut offset = start - from;
+    if from > indent {
+        preview.insert(0, '…');
+        offset += 1;
+    }
+    let end = (offset + len).min(preview.chars().count());
+    (preview, offset.min(end)..end)
+}
+
+/// What the Find in Files window asks the app to do.
+pub enum SearchCommand {
+    /// Pick the folder to search with a dialog.
+    Browse,
+    /// Open `path` in the focused pane at the (zero-based) line and column.
+    Open { path: PathBuf, line: usize, column: usize },
+    /// Write the previewed replacements; see `SearchPanel::plans`.
+    ApplyReplace,
+    UndoReplace,
+}
+
+/// The Find in Files window: a query, the folder to search and the
+/// results grouped by file, or a preview of replacing them.
+#[derive(Default)]
+pub struct SearchPanel {
+    pub open: bool,
+    pub query: String,
+    pub options: FindOptions,
+    /// The folder to search, as typed.
+    pub root: String,
+    /// Move the keyboard to the query next frame.
+    pub focus: bool,
+    /// Search again next frame, e.g. after files were rewritten.
+    pub rerun: bool,
+    show_replace: bool,
+    replacement: String,
+    /// The replacements being previewed, instead of the results.
+    pub plans: Option<Vec<FilePlan>>,
+    /// Replacements still being worked out on their own thread.
+    planning: Option<Receiver<(Vec<FilePlan>, Vec<String>)>>,
+    search: Option<Search>,
+    /// What went wrong starting the search or reading files for the preview.
+    problems: Vec<String>,
+}
+
+impl SearchPanel {
+    /// Search again with the current query, folder and options.
+    fn start(&mut self, ctx: &egui::Context, exclude: &[String]) {
+        self.search = None;
+        self.plans = None;
+        self.planning = None;
+        self.problems.clear();
+        if self.query.is_empty() {
+            return;
+        }
+        let root = PathBuf::from(self.root.trim());
+        if !root.is_dir() {
+            self.problems.push(format!("{} is not a folder", root.display()));
+            return;
+        }
+        match Search::start(ctx, root, &self.query, self.options, exclude) {
+            Ok(search) => self.search = Some(search),
+            Err(e) => self.problems.push(e),
+        }
+    }
+
+    /// Work out the replacements for every hit, to preview before writing.
+    /// Every file is read again, so this happens off the UI thread.
+    fn preview(&mut self, ctx: &egui::Context) {
+        let Some(search) = &self.search else {
+            return;
+        };
+        let paths: Vec<PathBuf> = search.results.iter().map(|file| file.path.clone()).collect();
+        let regex = search.regex.clone();
+        let (replacement, groups) = (self.replacement.clone(), search.options.regex);
+        let (tx, rx) = mpsc::channel();
+        let ctx = ctx.clone();
+        let spawned = std::thread::Builder::new()
+            .name("replace-preview".into())
+            .spawn(move || {
+                let _ = tx.send(replace::plan(paths, &regex, &replacement, groups));
+                ctx.request_repaint();
+            });
+        match spawned {
+            Ok(_) => self.planning = Some(rx),
+            Err(e) => self.problems.push(format!("Could not work out the replacements: {e}")),
+        }
+    }
+
+    /// Take the preview once its thread is done.
+    fn poll_planning(&mut self) {
+        let Some(planning) = &self.planning else {
+            return;
+        };
+        match planning.try_recv() {
+            Ok((plans, problems)) => {
+                self.plans = Some(plans);
+                self.problems = problems;
+            }
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => {}
+        }
+        self.planning = None;
+    }
+
+    fn summary(&self) -> String {
+        let Some(search) = &self.search else {
+            return "Enter searches the folder.".into();
+        };
+        let hits = search.hit_count();
+        let mut summary = format!(
+            "{hits} {} in {} files",
+            find::plural(hits),
+            search.results.len()
+        );
+        match search.finished {
+            Some(_) if search.truncated => summary.push_str(&format!(" (stopped at {MAX_HITS})")),
+            Some(files) => summary.push_str(&format!(", {files} searched")),
+            None if search.running() => summary.push_str(", searching…"),
+            None => summary.push_str(", stopped"),
+        }
+        summary
+    }
+
+    /// Draw the window. `exclude` is the configured list of globs to skip;
+    /// `undo_files` is how many files the last replace touched, if it can
+    /// still be undone.
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        exclude: &[String],
+        undo_files: Option<usize>,
+    ) -> Option<SearchCommand> {
+        if std::mem::take(&mut self.rerun) && self.search.is_some() {
+            self.start(ctx, exclude);
+        }
+        if let Some(search) = &mut self.search {
+            search.poll();
+        }
+        self.poll_planning();
+        let mut command = None;
+        let mut open = self.open;
+        egui::Window::new("Find in Files")
+            .id(egui::Id::new("find_in_files"))
+            .open(&mut open)
+            .default_size([720.0, 460.0])
+            .show(ctx, |ui| {
+                let enter = ui.input(|i| i.key_pressed(egui::Key::Enter));
+                let mut start = false;
+                ui.horizontal(|ui| {
+                    let query = ui.add(
+                        egui::TextEdit::singleline(&mut self.query)
+                            .hint_text("Search for")
+                            .desired_width(320.0),
+                    );
+                    if std::mem::take(&mut self.focus) {
+                        query.request_focus();
+                    }
+                    start |= query.lost_focus() && enter;
+                    find::option_toggles(ui, &mut self.options);
+                    ui.toggle_value(&mut self.show_replace, "Replace…");
+                });
+                if self.show_replace {
+                    self.replace_row(ui, undo_files, &mut command);
+                }
+                ui.horizontal(|ui| {
+                    ui.label("In:");
+                    let root = ui.add(egui::TextEdit::singleline(&mut self.root).desired_width(320.0));
+                    start |= root.lost_focus() && enter;
+                    if ui.button("Browse…").clicked() {
+                        command = Some(SearchCommand::Browse);
+                    }
+                    if self.search.as_ref().is_some_and(Search::running) {
+                        if ui.button("Stop").clicked()
+                            && let Some(search) = &self.search
+                        {
+                            search.stop();
+                        }
+                    } else if ui.button("Search").clicked() {
+                        start = true;
+                    }
+                });
+                let skipped = if exclude.is_empty() {
+                    "Skips hidden and .gitignored files.".to_owned()
+                } else {
+                    format!("Skips hidden and .gitignored files, and {}.", exclude.join(", "))
+                };
+                ui.weak(skipped);
+                if start {
+                    self.start(ctx, exclude);
+                }
+                ui.separator();
+                for problem in &self.problems {
+                    ui.colored_label(ui.visuals().error_fg_color, problem);
+                }
+                if let Some(plans) = &mut self.plans {
+                    let cancel = preview_ui(ui, plans, &mut command);
+                    if cancel || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                        self.plans = None;
+                    }
+                    return;
+                }
+                ui.label(self.summary());
+                if let Some(search) = &self.search {
+                    results_ui(ui, search, &mut command);
+                }
+            });
+        self.open = open;
+        if !self.open {
+            self.search = None;
+            self.plans = None;
+            self.planning = None;
+        }
+        command
+    }
+
+    fn replace_row(&mut self, ui: &mut egui::Ui, undo_files: Option<usize>, command: &mut Option<SearchCommand>) {
+        ui.horizontal(|ui| {
+            let hint = if self.options.regex {
+                "Replace with ($1 for groups)"
+            } else {
+                "Replace with"
+            };
+            let replacement = ui.add(
+                egui::TextEdit::singleline(&mut self.replacement)
+                    .hint_text(hint)
+                    .desired_width(320.0),
+            );
+            if replacement.changed() {
+                // The preview showed the old replacement
+                self.plans = None;
+                self.planning = None;
+            }
+            let ready = self
+                .search
+                .as_ref()
+                .is_some_and(|search| search.finished.is_some() && !search.results.is_empty());
+            if self.planning.is_some() {
+                ui.spinner();
+                ui.weak("Working out the replacements…");
+            } else if ui
+                .add_enabled(ready, egui::Button::new("Preview"))
+                .on_disabled_hover_text("Search first, and let it finish")
+                .clicked()
+            {
+                self.preview(ui.ctx());
+            }
+            if let Some(files) = undo_files
+                && ui
+                    .button(format!("Undo Replace ({files} {})", if files == 1 { "file" } else { "files" }))
+                    .clicked()
+            {
+                *command = Some(SearchCommand::UndoReplace);
+            }
+        });
+    }
+}
+
+/// Every proposed change as a two-line diff with a checkbox, grouped by
+/// file, and the buttons to apply or drop them. Returns whether the
+/// preview was cancelled.
+fn preview_ui(ui: &mut egui::Ui, plans: &mut [FilePlan], command: &mut Option<SearchCommand>) -> bool {
+    let mut cancel = false;
+    let total: usize = plans.iter().map(|plan| plan.changes.len()).sum();
+    let included: usize = plans.iter().map(FilePlan::included).sum();
+    let files = plans.iter().filter(|plan| plan.included() > 0).count();
+    ui.horizontal(|ui| {
+        ui.label(format!("Replace {included} of {total} matches in {files} files?"));
+        if ui
+            .add_enabled(included > 0, egui::Button::new("Apply"))
+            .clicked()
+        {
+            *command = Some(SearchCommand::ApplyReplace);
+        }
+        cancel = ui
+            .button("Cancel")
+            .on_hover_text("Back to the results (Escape)")
+            .clicked();
+    });
+    egui::ScrollArea::vertical()
+        .auto_shrink([false, false])
+        .id_source("find_in_files_preview")
+        .show(ui, |ui| {
+            for plan in plans.iter_mut() {
+                let header = format!("{} ({}/{})", plan.path.display(), plan.included(), plan.changes.len());
+                egui::CollapsingHeader::new(header)
+                    .id_source(("replace", &plan.path))
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            if ui.small_button("All").clicked() {
+                                plan.changes.iter_mut().for_each(|change| change.include = true);
+                            }
+                            if ui.small_button("None").clicked() {
+                                plan.changes.iter_mut().for_each(|change| change.include = false);
+                            }
+                        });
+                        for change in &mut plan.changes {
+                            ui.horizontal(|ui| {
+                                ui.checkbox(&mut change.include, format!("{:>5}", change.line + 1));
+                                ui.vertical(|ui| line_change(ui, &change.old_line, &change.new_line));
+                            });
+                        }
+                    });
+            }
+        });
+    cancel
+}
+
+fn results_ui(ui: &mut egui::Ui, search: &Search, command: &mut Option<SearchCommand>) {
+    egui::ScrollArea::vertical()
+        .auto_shrink([false, false])
+        .id_source("find_in_files_results")
+        .show(ui, |ui| {
+            for file in &search.results {
+                let shown = file.path.strip_prefix(&search.root).unwrap_or(&file.path);
+                let header = format!("{} ({})", shown.display(), file.hits.len());
+                egui::CollapsingHeader::new(header)
+                    .id_source(&file.path)
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        for hit in &file.hits {
+                            let label = format!("{:>5}  ", hit.line + 1);
+                            let shift = label.chars().count();
+                            let positions: Vec<usize> =
+                                hit.matched.clone().map(|idx| idx + shift).collect();
+                            let job = highlight(ui, &format!("{label}{}", hit.preview), &positions);
+                            if ui.selectable_label(false, job).clicked() {
+                                *command = Some(SearchCommand::Open {
+                                    path: file.path.clone(),
+                                    line: hit.line,
+                                    column: hit.column,
+                                });
+                            }
+                        }
+                    });
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    fn regex(query: &str) -> Regex {
+        find::build_regex(query, FindOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn search_text_reports_lines_and_char_columns() {
+        let text = "one two\r\nnothing\r\nüber two two\n";
+        let hits = search_text(text, &regex("two"), usize::MAX);
+        let found: Vec<(usize, usize)> = hits.iter().map(|hit| (hit.line, hit.column)).collect();
+        assert_eq!(found, [(0, 4), (2, 5), (2, 9)]);
+        assert_eq!(hits[1].preview, "über two two");
+        assert_eq!(hits[1].matched, 5..8);
+        assert_eq!(search_text(text, &regex("two"), 2).len(), 2);
+        // Empty matches are no use as hits
+        let empty = find::build_regex("x*", FindOptions { regex: true, ..Default::default() }).unwrap();
+        assert!(search_text(text, &empty, usize::MAX).is_empty());
+    }
+
+    #[test]
+    fn preview_trims_indentation_and_counts_chars() {
+        let line = "    let é = 1;";
+        let start = line.find('=').unwrap();
+        assert_eq!(preview(line, start..start + 1), ("let é = 1;".to_owned(), 6..7));
+        // A match inside the indentation is not cut off
+        assert_eq!(preview("  x", 1..2), (" x".to_owned(), 0..1));
+    }
+
+    #[test]
+    fn preview_cuts_long_lines_around_the_match() {
+        let line = format!("{}needle{}", "ä".repeat(100), "b".repeat(200));
+        let start = line.find("needle").unwrap();
+        let (shown, matched) = preview(&line, start..start + 6);
+        assert!(shown.starts_with('…'));
+        assert_eq!(shown.chars().count(), PREVIEW_CHARS + 1);
+        assert_eq!(matched, PREVIEW_LEAD + 1..PREVIEW_LEAD + 7);
+        assert_eq!(shown.chars().skip(matched.start).take(6).collect::<String>(), "needle");
+    }
+
+    #[test]
+    fn read_text_skips_binary_and_oversized_files() {
+        let dir = TempDir::new("search-read-text");
+        assert_eq!(read_text(&dir.file("text.txt", "hello\n")).as_deref(), Some("hello\n"));
+        assert_eq!(read_text(&dir.file("binary.bin", b"PK\x03\x04\0\0")), None);
+        // Text up front, so only the size gives it away
+        let big = dir.file("big.log", "a\n".repeat(8 * 1024));
+        fs::OpenOptions::new().write(true).open(&big).unwrap().set_len(MAX_FILE_BYTES + 1).unwrap();
+        assert_eq!(read_text(&big), None);
+        assert_eq!(read_text(&dir.path().join("missing.txt")), None);
+    }
+
+    #[test]
+    fn latin1_hits_are_found_and_replaced_alike() {
+        let dir = TempDir::new("search-latin1");
+        let path = dir.file("latin1.txt", b"caf\xe9 au lait\r\nun caf\xe9\r\n");
+        let text = read_text(&path).unwrap();
+        let hits = search_text(&text, &regex("café"), usize::MAX);
+        assert_eq!(hits.iter().map(|hit| hit.line).collect::<Vec<_>>(), [0, 1]);
+        let (plans, problems) = replace::plan([path], &regex("café"), "tea", false);
+        assert!(problems.is_empty());
+        assert_eq!(plans[0].changes.iter().map(|change| change.line).collect::<Vec<_>>(), [0, 1]);
+    }
+}
@@ -0,0 +1,168 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::fileio::{self, BackupMode};
+use crate::layout::{Layout, PaneId};
+
+/// Command-line flag that starts with a fresh window and leaves the saved
+/// session alone.
+pub const NO_SESSION_FLAG: &str = "--no-session";
+
+/// Everything needed to put the window back the way it was left.
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    pub layout: Layout,
+    pub panes: Vec<PaneState>,
+    pub focused: PaneId,
+    pub maximized: bool,
+    pub word_wrap: bool,
+    pub manual_path: String,
+}
+
+/// The tabs of one pane.
+#[derive(Serialize, Deserialize)]
+pub struct PaneState {
+    pub id: PaneId,
+    pub docs: Vec<DocState>,
+    pub active: usize,
+}
+
+/// One tab: a file to reopen, or the text of an untitled scratch buffer.
+#[derive(Serialize, Deserialize)]
+pub struct DocState {
+    pub path: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    pub cursor: Option<usize>,
+    pub scroll: f32,
+}
+
+fn session_path() -> Option<PathBuf> {
+    fileio::state_dir().map(|dir| dir.join("session.json"))
+}
+
+impl Session {
+    /// Whether the layout is sound and every pane in it has its tabs, as
+    /// restoring assumes. A stale or hand-edited file may not be.
+    pub fn is_usable(&self) -> bool {
+        if !self.layout.is_well_formed() {
+            return false;
+        }
+        let mut leaves = self.layout.leaves();
+        leaves.sort();
+        let mut ids: Vec<_> = self.panes.iter().map(|pane| pane.id).collect();
+        ids.sort();
+        !leaves.is_empty() && leaves == ids
+    }
+}
+
+/// Read the saved session, if there is a usable one.
+pub fn load() -> Option<Session> {
+    let bytes = fs::read(session_path()?).ok()?;
+    let session: Session = serde_json::from_slice(&bytes).ok()?;
+    session.is_usable().then_some(session)
+}
+
+pub fn save(session: &Session) -> Result<()> {
+    let path = session_path().context("no per-user state directory")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    }
+    let json = serde_json::to_vec_pretty(session)?;
+    fileio::write_atomic(&path, &json, BackupMode::Off)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::Cursors;
+    use crate::layout::Axis;
+    use crate::tabs::TabGroup;
+    use crate::testutil::TempDir;
+    use crate::{App, Pane};
+
+    /// Through the file format and back, as between two runs.
+    fn reloaded(session: &Session) -> Session {
+        serde_json::from_slice(&serde_json::to_vec(session).unwrap()).unwrap()
+    }
+
+    fn texts(group: &TabGroup) -> Vec<String> {
+        group.docs.iter().map(|doc| doc.text.to_string()).collect()
+    }
+
+    #[test]
+    fn capture_and_restore_round_trip() {
+        let dir = TempDir::new("session-round-trip");
+        let notes = dir.file("notes.txt", "one\ntwo\n");
+        let mut app = App::default();
+        app.layout.split(PaneId(2), PaneId(3), Axis::Vertical);
+        let scratch = app.panes.get_mut(&PaneId(1)).unwrap().active_mut();
+        scratch.text = "scratch".into();
+        scratch.cursors = Cursors::at(3);
+        let mut file = Pane::untitled(10);
+        file.load_from(notes.clone()).unwrap();
+        file.cursors = Cursors::at(5);
+        file.scroll_offset = 40.0;
+        let mut group = TabGroup::new(Pane::untitled(11));
+        group.docs.push(file);
+        group.active = 1;
+        app.panes.insert(PaneId(3), group);
+        app.focused_pane = PaneId(3);
+        app.word_wrap = true;
+
+        let session = reloaded(&app.capture_session());
+        assert!(session.is_usable());
+        let restored = App::default().restore_session(session);
+        assert_eq!(restored.status, "Session restored");
+        assert_eq!(restored.layout.leaves(), [PaneId(1), PaneId(2), PaneId(3)]);
+        assert_eq!((restored.focused_pane, restored.word_wrap), (PaneId(3), true));
+        assert_eq!(texts(&restored.panes[&PaneId(1)]), ["scratch"]);
+        assert_eq!(restored.panes[&PaneId(1)].active().cursors.primary().head, 3);
+        let group = &restored.panes[&PaneId(3)];
+        assert_eq!(texts(group), ["", "one\ntwo\n"]);
+        assert_eq!(group.active, 1);
+        let file = group.active();
+        assert_eq!(file.path.as_deref(), Some(notes.as_path()));
+        assert_eq!((file.cursors.primary().head, file.scroll_offset), (5, 40.0));
+    }
+
+    #[test]
+    fn files_that_are_gone_are_dropped_and_reported() {
+        let dir = TempDir::new("session-missing");
+        let gone = dir.file("gone.txt", "bye");
+        let mut app = App::default();
+        app.panes.get_mut(&PaneId(2)).unwrap().active_mut().load_from(gone.clone()).unwrap();
+        app.focused_pane = PaneId(2);
+        let session = app.capture_session();
+        std::fs::remove_file(&gone).unwrap();
+        let restored = App::default().restore_session(reloaded(&session));
+        assert!(restored.status.contains("could not reopen"), "{}", restored.status);
+        // The pane is kept, with a fresh document in place of the file
+        assert_eq!(texts(&restored.panes[&PaneId(2)]), [""]);
+        assert_eq!(restored.panes[&PaneId(2)].active().path, None);
+    }
+
+    #[test]
+    fn sessions_that_do_not_match_their_layout_are_refused() {
+        let mut session = App::default().capture_session();
+        session.panes.pop();
+        assert!(!reloaded(&session).is_usable());
+        let restored = App::default().restore_session(reloaded(&session));
+        assert!(restored.status.contains("starting afresh"));
+        assert_eq!(restored.panes.len(), 2);
+
+        let mut session = App::default().capture_session();
+        session.layout = Layout::Split {
+            axis: Axis::Horizontal,
+            children: Vec::new(),
+            shares: Vec::new(),
+        };
+        session.panes.clear();
+        assert!(!session.is_usable());
+        let restored = App::default().restore_session(session);
+        assert_eq!(restored.layout.leaves(), [PaneId(1), PaneId(2)]);
+    }
+}
@@ -0,0 +1,283 @@
+use eframe::egui;
+use std::path::Path;
+
+use crate::Pane;
+use crate::find::FindBar;
+use crate::layout::PaneId;
+
+/// The documents open in one pane, shown as a tab strip.
+pub struct TabGroup {
+    pub docs: Vec<Pane>,
+    pub active: usize,
+    /// The pane's find bar, while it is open.
+    pub find: Option<FindBar>,
+    /// Document ids, most recently active first.
+    recent: Vec<u64>,
+    /// While Ctrl+Tab's modifier is held: how far along `recent` it went.
+    switch: Option<usize>,
+}
+
+/// Tab strip requests that need the app to act (closing may need a new
+/// untitled document or a confirmation).
+#[derive(Clone, Copy)]
+pub enum TabCommand {
+    Close(usize),
+    CloseOthers(usize),
+    New,
+}
+
+impl TabGroup {
+    pub fn new(doc: Pane) -> Self {
+        Self::restore(vec![doc], 0)
+    }
+
+    /// A group of `docs`, which must not be empty, with `active` in front.
+    pub fn restore(docs: Vec<Pane>, active: usize) -> Self {
+        Self {
+            docs,
+            active,
+            find: None,
+            recent: Vec::new(),
+            switch: None,
+        }
+    }
+
+    pub fn active(&self) -> &Pane {
+        &self.docs[self.active]
+    }
+
+    pub fn active_mut(&mut self) -> &mut Pane {
+        &mut self.docs[self.active]
+    }
+
+    pub fn find_path(&self, path: &Path) -> Option<usize> {
+        self.docs
+            .iter()
+            .position(|doc| doc.path.as_deref() == Some(path))
+    }
+
+    /// Add `doc` after the active tab and switch to it. A blank untitled
+    /// active tab is replaced rather than kept around.
+    pub fn open(&mut self, doc: Pane) {
+        let current = self.active();
+        if current.path.is_none() && current.text.is_empty() && !current.is_dirty() {
+            self.docs[self.active] = doc;
+        } else {
+            self.active += 1;
+            self.docs.insert(self.active, doc);
+        }
+    }
+
+    /// Remove the tab at `idx`; the caller must keep at least one tab open.
+    pub fn close(&mut self, idx: usize) -> Pane {
+        let doc = self.docs.remove(idx);
+        if self.active > idx || self.active == self.docs.len() {
+            self.active = self.active.saturating_sub(1);
+        }
+        doc
+    }
+
+    /// Keep only the tab at `idx`.
+    pub fn close_others(&mut self, idx: usize) {
+        let doc = self.docs.swap_remove(idx);
+        self.docs = vec![doc];
+        self.active = 0;
+    }
+
+    pub fn cycle(&mut self, step: isize) {
+        let len = self.docs.len() as isize;
+        self.active = (self.active as isize + step).rem_euclid(len) as usize;
+    }
+
+    /// Step through the tabs in most-recently-used order, as Ctrl+Tab does.
+    /// Repeated steps while the modifier stays held walk further back; the
+    /// order only changes once it is released (see `track_recent`).
+    pub fn switch_recent(&mut self, step: isize) {
+        self.touch();
+        let len = self.recent.len() as isize;
+        let at = (self.switch.unwrap_or(0) as isize + step).rem_euclid(len) as usize;
+        self.switch = Some(at);
+        if let Some(idx) = self.docs.iter().position(|doc| doc.doc_id == self.recent[at]) {
+            self.active = idx;
+        }
+    }
+
+    /// Called every frame: ends a Ctrl+Tab switch once no modifier is
+    /// `held`, and moves whichever tab is active to the front of the order.
+    pub fn track_recent(&mut self, held: bool) {
+        if !held {
+            self.switch = None;
+        }
+        self.touch();
+    }
+
+    fn touch(&mut self) {
+        if self.switch.is_some() {
+            return;
+        }
+        let id = self.active().doc_id;
+        if self.recent.first() == Some(&id) && self.recent.len() == self.docs.len() {
+            return;
+        }
+        let docs = &self.docs;
+        self.recent
+            .retain(|recent| *recent != id && docs.iter().any(|doc| doc.doc_id == *recent));
+        for doc in docs {
+            if doc.doc_id != id && !self.recent.contains(&doc.doc_id) {
+                self.recent.push(doc.doc_id);
+            }
+        }
+        self.recent.insert(0, id);
+    }
+
+    pub fn move_tab(&mut self, from: usize, to: usize) {
+        let active_id = self.active().doc_id;
+        let doc = self.docs.remove(from);
+        self.docs.insert(to, doc);
+        self.active = self
+            .docs
+            .iter()
+            .position(|doc| doc.doc_id == active_id)
+            .unwrap_or(0);
+    }
+}
+
+/// Draw the tab strip for a pane. Returns whether a tab was clicked (so the
+/// pane should take focus) and any command the app needs to handle.
+pub fn tab_bar(ui: &mut egui::Ui, group: &mut TabGroup, pane_id: PaneId) -> (bool, Option<TabCommand>) {
+    let mut clicked = false;
+    let mut command = None;
+    egui::ScrollArea::horizontal()
+        .id_source(("tab_bar", pane_id.0))
+        .auto_shrink([false, true])
+        .show(ui, |ui| {
+            ui.horizontal(|ui| {
+                let mut rects = Vec::with_capacity(group.docs.len());
+                let mut dragged = None;
+                for idx in 0..group.docs.len() {
+                    let doc = &group.docs[idx];
+                    let label = if doc.is_dirty() {
+                        format!("{} •", doc.title)
+                    } else {
+                        doc.title.clone()
+                    };
+                    let hover = doc
+                        .path
+                        .as_ref()
+                        .map_or_else(|| "Unsaved document".to_owned(), |p| p.display().to_string());
+                    // Key widgets by document so a drag survives reordering
+                    let resp = ui
+                        .push_id(doc.doc_id, |ui| {
+                            ui.add(
+                                egui::Button::new(label)
+                                    .selected(idx == group.active)
+                                    .sense(egui::Sense::click_and_drag()),
+                            )
+                        })
+                        .inner
+                        .on_hover_text(hover);
+                    if resp.clicked() {
+                        group.active = idx;
+                        clicked = true;
+                    }
+                    if resp.middle_clicked() {
+                        command = Some(TabCommand::Close(idx));
+                    }
+                    if resp.dragged() {
+                        dragged = Some(idx);
+                    }
+                    resp.context_menu(|ui| {
+                        if ui.button("Close Tab").clicked() {
+                            command = Some(TabCommand::Close(idx));
+                            ui.close_menu();
+                        }
+                        if ui.button("Close Other Tabs").clicked() {
+                            command = Some(TabCommand::CloseOthers(idx));
+                            ui.close_menu();
+                        }
+                    });
+                    if ui.small_button("×").on_hover_text("Close tab").clicked() {
+                        command = Some(TabCommand::Close(idx));
+                    }
+                    rects.push(resp.rect);
+                }
+                if ui.small_button("+").on_hover_text("New tab").clicked() {
+                    command = Some(TabCommand::New);
+                }
+
+                if let Some(from) = dragged
+                    && let Some(pointer) = ui.ctx().pointer_interact_pos()
+                    && let Some(to) = rects.iter().position(|rect| rect.x_range().contains(pointer.x))
+                    && to != from
+                {
+                    group.move_tab(from, to);
+                }
+            });
+        });
+    (clicked, command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A group of untitled tabs with ids 0..count, the first active.
+    fn group(count: u64) -> TabGroup {
+        TabGroup::restore((0..count).map(Pane::untitled).collect(), 0)
+    }
+
+    fn active_id(group: &TabGroup) -> u64 {
+        group.active().doc_id
+    }
+
+    /// Activate tab `idx` the way a click does, and let a frame pass.
+    fn activate(group: &mut TabGroup, idx: usize) {
+        group.active = idx;
+        group.track_recent(false);
+    }
+
+    #[test]
+    fn ctrl_tab_goes_back_to_the_previous_tab() {
+        let mut group = group(4);
+        for idx in [2, 1, 3] {
+            activate(&mut group, idx);
+        }
+        group.switch_recent(1);
+        group.track_recent(false);
+        assert_eq!(active_id(&group), 1);
+        // And pressing it again comes back
+        group.switch_recent(1);
+        group.track_recent(false);
+        assert_eq!(active_id(&group), 3);
+    }
+
+    #[test]
+    fn holding_the_modifier_walks_further_back() {
+        let mut group = group(4);
+        for idx in [2, 1, 3] {
+            activate(&mut group, idx);
+        }
+        group.switch_recent(1);
+        group.track_recent(true);
+        group.switch_recent(1);
+        group.track_recent(true);
+        assert_eq!(active_id(&group), 2);
+        group.switch_recent(-1);
+        assert_eq!(active_id(&group), 1);
+        group.switch_recent(1);
+        group.track_recent(false);
+        // Releasing makes the tab reached the most recent
+        assert_eq!(group.recent, [2, 3, 1, 0]);
+    }
+
+    #[test]
+    fn closed_tabs_leave_the_order() {
+        let mut group = group(3);
+        activate(&mut group, 2);
+        group.close(1);
+        group.track_recent(false);
+        assert_eq!(group.recent, [2, 0]);
+        group.switch_recent(1);
+        assert_eq!(active_id(&group), 0);
+    }
+}
@@ -0,0 +1,393 @@
+use std::time::{Duration, Instant};
+
+use crate::buffer::Buffer;
+
+/// Typing pauses longer than this start a new undo step.
+const GROUP_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// A single text replacement, in character offsets.
+#[derive(Clone, Debug)]
+pub struct Edit {
+    pub at: usize,
+    pub removed: String,
+    pub inserted: String,
+}
+
+impl Edit {
+    /// The smallest single edit that turns `old` into `new`, if they differ.
+    /// Walks the ropes so only the changed middle is copied out.
+    pub fn between(old: &Buffer, new: &Buffer) -> Option<Edit> {
+        let (old, new) = (old.rope(), new.rope());
+        let prefix = old.chars().zip(new.chars()).take_while(|(a, b)| a == b).count();
+        if prefix == old.len_chars() && prefix == new.len_chars() {
+            return None;
+        }
+        // Never let the suffix reach back into the prefix
+        let room = old.len_chars().min(new.len_chars()) - prefix;
+        let suffix = old
+            .chars_at(old.len_chars())
+            .reversed()
+            .zip(new.chars_at(new.len_chars()).reversed())
+            .take(room)
+            .take_while(|(a, b)| a == b)
+            .count();
+        Some(Edit {
+            at: prefix,
+            removed: old.slice(prefix..old.len_chars() - suffix).to_string(),
+            inserted: new.slice(prefix..new.len_chars() - suffix).to_string(),
+        })
+    }
+
+    fn kind(&self) -> EditKind {
+        match (self.removed.is_empty(), self.inserted.is_empty()) {
+            (true, false) => EditKind::Insert,
+            (false, true) => EditKind::Delete,
+            _ => EditKind::Replace,
+        }
+    }
+
+    fn inserted_len(&self) -> usize {
+        self.inserted.chars().count()
+    }
+
+    fn removed_len(&self) -> usize {
+        self.removed.chars().count()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum EditKind {
+    Insert,
+    Delete,
+    Replace,
+}
+
+/// One undo step: every edit in it is undone/redone together.
+struct Group {
+    revision: u64,
+    edits: Vec<Edit>,
+    /// How many carets the edits were made at.
+    carets: usize,
+    last_change: Instant,
+}
+
+impl Group {
+    /// Try to fold `edits` into this group so a run of typing or backspacing
+    /// undoes as one step.
+    fn absorb(&mut self, edits: &[Edit], now: Instant) -> bool {
+        if now.duration_since(self.last_change) > GROUP_TIMEOUT {
+            return false;
+        }
+        let ([last], [edit]) = (self.edits.as_mut_slice(), edits) else {
+            return self.absorb_carets(edits, now);
+        };
+        if last.kind() != edit.kind() {
+            return false;
+        }
+        match edit.kind() {
+            EditKind::Insert => {
+                // Break on newlines so each typed line is its own step.
+                if edit.inserted.contains('\n') || last.at + last.inserted_len() != edit.at {
+                    return false;
+                }
+                last.inserted.push_str(&edit.inserted);
+            }
+            EditKind::Delete => {
+                if edit.at + edit.removed_len() == last.at {
+                    // Backspace: the deleted text precedes what was already removed.
+                    last.removed.insert_str(0, &edit.removed);
+                    last.at = edit.at;
+                } else if edit.at == last.at {
+                    // Forward delete.
+                    last.removed.push_str(&edit.removed);
+                } else {
+                    return false;
+                }
+            }
+            EditKind::Replace => return false,
+        }
+        self.last_change = now;
+        true
+    }
+
+    /// The same, for typing or deleting at several carets at once. The
+    /// editor seals the history whenever the carets move, so a keystroke
+    /// with an edit of the same kind at each caret carries on where the
+    /// last one ended.
+    fn absorb_carets(&mut self, edits: &[Edit], now: Instant) -> bool {
+        let kind = self.edits[0].kind();
+        let continues = edits.len() == self.carets
+            && kind != EditKind::Replace
+            && self.edits.iter().chain(edits).all(|edit| edit.kind() == kind)
+            && !edits.iter().any(|edit| edit.inserted.contains('\n'));
+        if continues {
+            self.edits.extend_from_slice(edits);
+            self.last_change = now;
+        }
+        continues
+    }
+}
+
+/// App-owned undo/redo stack for one document.
+///
+/// Revisions are unique per step, so a document is clean exactly when the
+/// current revision matches the one recorded at load/save time, no matter
+/// how the user got there.
+pub struct UndoHistory {
+    undo: Vec<Group>,
+    redo: Vec<Group>,
+    next_revision: u64,
+    saved_revision: u64,
+    sealed: bool,
+}
+
+impl Default for UndoHistory {
+    fn default() -> Self {
+        Self {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            next_revision: 1,
+            saved_revision: 0,
+            sealed: true,
+        }
+    }
+}
+
+impl UndoHistory {
+    pub fn revision(&self) -> u64 {
+        self.undo.last().map_or(0, |group| group.revision)
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.revision() != self.saved_revision
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved_revision = self.revision();
+        self.sealed = true;
+    }
+
+    /// Make the next recorded edit start its own undo step.
+    pub fn seal(&mut self) {
+        self.sealed = true;
+    }
+
+    /// Forget everything, e.g. after loading a new file.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Record the edits made by one keystroke as a single undo step, merging
+    /// with the previous step when they continue the same run of typing.
+    pub fn record(&mut self, mut edits: Vec<Edit>, now: Instant) {
+        edits.retain(|edit| edit.removed != edit.inserted);
+        if edits.is_empty() {
+            return;
+        }
+        self.redo.clear();
+
+        if !self.sealed
+            && let Some(last) = self.undo.last_mut()
+            && last.absorb(&edits, now)
+        {
+            return;
+        }
+
+        let revision = self.next_revision;
+        self.next_revision += 1;
+        self.undo.push(Group {
+            revision,
+            carets: edits.len(),
+            edits,
+            last_change: now,
+        });
+        self.sealed = false;
+    }
+
+    /// Undo the most recent step, returning the cursor position to restore.
+    pub fn undo(&mut self, text: &mut Buffer) -> Option<usize> {
+        let group = self.undo.pop()?;
+        for edit in group.edits.iter().rev() {
+            text.replace(edit.at..edit.at + edit.inserted_len(), &edit.removed);
+        }
+        let cursor = group
+            .edits
+            .first()
+            .map(|edit| edit.at + edit.removed_len());
+        self.redo.push(group);
+        self.sealed = true;
+        cursor
+    }
+
+    /// Redo the most recently undone step, returning the cursor position to restore.
+    pub fn redo(&mut self, text: &mut Buffer) -> Option<usize> {
+        let group = self.redo.pop()?;
+        for edit in &group.edits {
+            text.replace(edit.at..edit.at + edit.removed_len(), &edit.inserted);
+        }
+        let cursor = group
+            .edits
+            .last()
+            .map(|edit| edit.at + edit.inserted_len());
+        self.undo.push(group);
+        self.sealed = true;
+        cursor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Apply `edit` to `text` and record it, as the editor does.
+    fn apply(history: &mut UndoHistory, text: &mut Buffer, edit: Edit, now: Instant) {
+        text.replace(edit.at..edit.at + edit.removed_len(), &edit.inserted);
+        history.record(vec![edit], now);
+    }
+
+    fn insert(at: usize, inserted: &str) -> Edit {
+        Edit {
+            at,
+            removed: String::new(),
+            inserted: inserted.into(),
+        }
+    }
+
+    fn delete(at: usize, removed: &str) -> Edit {
+        Edit {
+            at,
+            removed: removed.into(),
+            inserted: String::new(),
+        }
+    }
+
+    fn type_str(history: &mut UndoHistory, text: &mut Buffer, at: usize, typed: &str, now: Instant) {
+        for (i, c) in typed.chars().enumerate() {
+            apply(history, text, insert(at + i, &c.to_string()), now);
+        }
+    }
+
+    #[test]
+    fn between_finds_the_changed_middle() {
+        let between = |old: &str, new: &str| Edit::between(&old.into(), &new.into());
+        let edit = between("héllo world", "héllo there world").unwrap();
+        assert_eq!((edit.at, edit.removed.as_str(), edit.inserted.as_str()), (6, "", "there "));
+        assert!(between("same", "same").is_none());
+        // Repeats must not be claimed by both the prefix and the suffix
+        let edit = between("aaa", "aaaaa").unwrap();
+        assert_eq!((edit.at, edit.removed.as_str(), edit.inserted.as_str()), (3, "", "aa"));
+        let edit = between("abcabc", "abc").unwrap();
+        assert_eq!((edit.at, edit.removed.as_str(), edit.inserted.as_str()), (3, "abc", ""));
+    }
+
+    #[test]
+    fn typing_run_undoes_as_one_step() {
+        let (mut history, mut text, now) = (UndoHistory::default(), Buffer::default(), Instant::now());
+        type_str(&mut history, &mut text, 0, "hello", now);
+        assert_eq!(history.undo(&mut text), Some(0));
+        assert_eq!(text.to_string(), "");
+        assert!(history.undo(&mut text).is_none());
+    }
+
+    #[test]
+    fn newline_pause_and_caret_move_start_new_steps() {
+        let (mut history, mut text, now) = (UndoHistory::default(), Buffer::default(), Instant::now());
+        type_str(&mut history, &mut text, 0, "ab\ncd", now);
+        // A pause longer than the timeout
+        let later = now + GROUP_TIMEOUT * 2;
+        type_str(&mut history, &mut text, 5, "ef", later);
+        // The caret moved away and back
+        history.seal();
+        type_str(&mut history, &mut text, 7, "gh", later);
+        assert_eq!(text.to_string(), "ab\ncdefgh");
+        let mut states = Vec::new();
+        while history.undo(&mut text).is_some() {
+            states.push(text.to_string());
+        }
+        // The newline starts a step that the rest of its line joins
+        assert_eq!(states, ["ab\ncdef", "ab\ncd", "ab", ""]);
+    }
+
+    #[test]
+    fn typing_and_deleting_are_separate_steps() {
+        let (mut history, mut text, now) = (UndoHistory::default(), Buffer::default(), Instant::now());
+        type_str(&mut history, &mut text, 0, "abcd", now);
+        // Two backspaces, then two forward deletes at the start
+        apply(&mut history, &mut text, delete(3, "d"), now);
+        apply(&mut history, &mut text, delete(2, "c"), now);
+        history.seal();
+        apply(&mut history, &mut text, delete(0, "a"), now);
+        apply(&mut history, &mut text, delete(0, "b"), now);
+        assert_eq!(text.to_string(), "");
+        assert_eq!(history.undo(&mut text), Some(2));
+        assert_eq!(text.to_string(), "ab");
+        assert_eq!(history.undo(&mut text), Some(4));
+        assert_eq!(text.to_string(), "abcd");
+        history.undo(&mut text);
+        assert_eq!(text.to_string(), "");
+    }
+
+    #[test]
+    fn edits_at_several_carets_group_per_keystroke() {
+        let (mut history, mut text, now) = (UndoHistory::default(), Buffer::from("\n"), Instant::now());
+        // Type "x" then "y" at the start of both lines; each edit's offset
+        // already counts the edits before it
+        for (i, typed) in ["x", "y"].into_iter().enumerate() {
+            let edits = vec![insert(i, typed), insert(2 + 2 * i, typed)];
+            for edit in &edits {
+                text.replace(edit.at..edit.at, &edit.inserted);
+            }
+            history.record(edits, now);
+        }
+        assert_eq!(text.to_string(), "xy\nxy");
+        history.undo(&mut text);
+        assert_eq!(text.to_string(), "\n");
+    }
+
+    #[test]
+    fn saved_revision_tracks_dirty_state() {
+        let (mut history, mut text, now) = (UndoHistory::default(), Buffer::default(), Instant::now());
+        assert!(!history.is_dirty());
+        type_str(&mut history, &mut text, 0, "a", now);
+        assert!(history.is_dirty());
+        history.mark_saved();
+        assert!(!history.is_dirty());
+        type_str(&mut history, &mut text, 1, "b", now);
+        assert!(history.is_dirty());
+        history.undo(&mut text);
+        assert!(!history.is_dirty());
+        history.undo(&mut text);
+        assert!(history.is_dirty());
+        history.redo(&mut text);
+        assert!(!history.is_dirty());
+        // Back to an empty text by a different route is still not the saved state
+        history.undo(&mut text);
+        type_str(&mut history, &mut text, 0, "a", now);
+        assert_eq!(text.to_string(), "a");
+        assert!(history.is_dirty());
+    }
+
+    #[test]
+    fn new_edit_clears_redo() {
+        let (mut history, mut text, now) = (UndoHistory::default(), Buffer::default(), Instant::now());
+        type_str(&mut history, &mut text, 0, "ab", now);
+        history.undo(&mut text);
+        type_str(&mut history, &mut text, 0, "c", now);
+        assert!(history.redo(&mut text).is_none());
+        assert_eq!(text.to_string(), "c");
+    }
+
+    #[test]
+    fn undo_and_redo_round_trip() {
+        let (mut history, mut text, now) = (UndoHistory::default(), Buffer::default(), Instant::now());
+        type_str(&mut history, &mut text, 0, "one", now);
+        history.seal();
+        apply(&mut history, &mut text, Edit::between(&"one".into(), &"two".into()).unwrap(), now);
+        assert_eq!(text.to_string(), "two");
+        history.undo(&mut text);
+        history.undo(&mut text);
+        assert_eq!(history.redo(&mut text), Some(3));
+        assert_eq!(history.redo(&mut text), Some(3));
+        assert_eq!(text.to_string(), "two");
+    }
+}
@@ -0,0 +1,110 @@
+use eframe::egui;
+use std::time::{Duration, Instant};
+
+use crate::buffer::Buffer;
+use crate::diff::DiffView;
+use crate::fileio::DiskChange;
+
+/// How often open files are checked for changes made by other programs.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Decides when the next round of on-disk checks is due.
+#[derive(Default)]
+pub struct DiskWatcher {
+    last_poll: Option<Instant>,
+}
+
+impl DiskWatcher {
+    pub fn due(&mut self, now: Instant) -> bool {
+        if self
+            .last_poll
+            .is_some_and(|last| now.duration_since(last) < POLL_INTERVAL)
+        {
+            return false;
+        }
+        self.last_poll = Some(now);
+        true
+    }
+}
+
+/// The user's answer to a document that changed on disk under unsaved edits.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ConflictChoice {
+    Reload,
+    KeepMine,
+    Compare,
+}
+
+/// The disk and buffer versions of a conflicting document, shown side by side.
+pub struct DiskCompare {
+    pub doc_id: u64,
+    pub title: String,
+    pub disk_text: String,
+    /// Keyed on the buffer's version.
+    pub diff: DiffView<u64>,
+}
+
+/// Banner shown above a document whose file changed behind its back.
+pub fn changed_on_disk_banner(ui: &mut egui::Ui, change: DiskChange) -> Option<ConflictChoice> {
+    let mut choice = None;
+    egui::Frame::none()
+        .fill(ui.visuals().extreme_bg_color)
+        .inner_margin(egui::Margin::symmetric(6.0, 4.0))
+        .show(ui, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                let warn = ui.visuals().warn_fg_color;
+                match change {
+                    DiskChange::Modified(_) => {
+                        ui.colored_label(warn, "This file changed on disk and you have unsaved edits.");
+                        if ui.button("Reload").on_hover_text("Load the disk version (undoable)").clicked() {
+                            choice = Some(ConflictChoice::Reload);
+                        }
+                    }
+                    DiskChange::Deleted => {
+                        ui.colored_label(warn, "This file was deleted or moved on disk.");
+                    }
+                }
+                if ui
+                    .button("Keep Mine")
+                    .on_hover_text("Keep editing this version; saving overwrites the disk")
+                    .clicked()
+                {
+                    choice = Some(ConflictChoice::KeepMine);
+                }
+                if matches!(change, DiskChange::Modified(_)) && ui.button("Compare").clicked() {
+                    choice = Some(ConflictChoice::Compare);
+                }
+            });
+        });
+    choice
+}
+
+/// Window diffing the disk version (old) against the buffer (new). Returns
+/// whether it is still open and any choice made from it.
+pub fn compare_window(
+    ctx: &egui::Context,
+    compare: &mut DiskCompare,
+    mine: &Buffer,
+) -> (bool, Option<ConflictChoice>) {
+    let mut open = true;
+    let mut choice = None;
+    egui::Window::new(format!("{}: disk vs. unsaved", compare.title))
+        .id(egui::Id::new("disk_compare"))
+        .open(&mut open)
+        .default_size([640.0, 420.0])
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Reload").clicked() {
+                    choice = Some(ConflictChoice::Reload);
+                }
+                if ui.button("Keep Mine").clicked() {
+                    choice = Some(ConflictChoice::KeepMine);
+                }
+                ui.label("− disk   + unsaved");
+            });
+            ui.separator();
+            let disk = &compare.disk_text;
+            compare.diff.show(ui, mine.version(), || (disk, mine.to_string()));
+        });
+    (open, choice)
+}